@@ -0,0 +1,67 @@
+//! Compares `index_to_string` with `writer::to_string` on a 10k-entry index.
+//! Run with `cargo bench --bench write`.
+
+use helm_repo_updater::{index_to_string, parse_index, writer, ChartYaml, Warnings};
+use std::time::{Duration, Instant};
+
+const CHARTS: usize = 100;
+const VERSIONS: usize = 100;
+const RUNS: u32 = 5;
+
+fn build_index() -> ChartYaml {
+    let mut yaml = String::from("apiVersion: v1\nentries:\n");
+    for chart in 0..CHARTS {
+        yaml.push_str(&format!("  chart-{}:\n", chart));
+        for version in 0..VERSIONS {
+            yaml.push_str(&format!(
+                "  - apiVersion: v2
+    name: chart-{chart}
+    version: 1.{version}.0
+    appVersion: \"{version}.0\"
+    created: 2026-01-02T03:04:05.678Z
+    description: Chart number {chart}, release {version}
+    digest: {digest:064x}
+    home: https://example.com/chart-{chart}
+    keywords: [cache, database]
+    maintainers:
+    - name: Ann
+      email: ann@example.com
+    urls:
+    - https://charts.example.com/chart-{chart}-1.{version}.0.tgz
+",
+                digest = chart * VERSIONS + version + 0xabc
+            ));
+        }
+    }
+    yaml.push_str("generated: 2026-01-02T03:04:05Z\n");
+
+    parse_index(&yaml, false, &mut Warnings::default()).expect("benchmark index parses")
+}
+
+fn time(name: &str, write: impl Fn() -> String) -> Duration {
+    let mut best = Duration::MAX;
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        std::hint::black_box(write());
+        best = best.min(start.elapsed());
+    }
+    println!("{:<12} {:>8.1} ms", name, best.as_secs_f64() * 1000.0);
+    best
+}
+
+fn main() {
+    let data = build_index();
+    assert_eq!(
+        writer::to_string(&data).unwrap(),
+        index_to_string(&data).unwrap(),
+        "the writers must agree"
+    );
+
+    println!("best of {} runs, {} entries", RUNS, CHARTS * VERSIONS);
+    let serde = time("serde_yaml", || index_to_string(&data).unwrap());
+    let fast = time("fast-write", || writer::to_string(&data).unwrap());
+    println!(
+        "speedup      {:>8.1}x",
+        serde.as_secs_f64() / fast.as_secs_f64()
+    );
+}
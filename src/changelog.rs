@@ -0,0 +1,100 @@
+use serde::Serialize;
+#[cfg(feature = "io")]
+use std::{fs, path::Path, process::Command};
+
+/// One item of the `artifacthub.io/changes` annotation
+#[derive(Debug, Serialize)]
+struct Change {
+    kind: &'static str,
+    description: String,
+}
+
+/// Maps a conventional-commit prefix to an ArtifactHub change kind, returning
+/// the kind and the subject without its prefix
+fn classify(subject: &str) -> (&'static str, &str) {
+    let Some((prefix, rest)) = subject.split_once(':') else {
+        return ("changed", subject);
+    };
+    // Drop any scope and breaking-change marker: `fix(api)!: ...`
+    let kind = match prefix.split(['(', '!']).next().unwrap_or(prefix) {
+        "feat" => "added",
+        "fix" => "fixed",
+        "security" => "security",
+        "deprecate" | "deprecated" => "deprecated",
+        "remove" | "removed" => "removed",
+        _ => return ("changed", subject),
+    };
+
+    (kind, rest.trim())
+}
+
+/// Formats commit subjects as the YAML list ArtifactHub expects
+pub fn format_changes(subjects: &[&str]) -> Result<String, Box<dyn std::error::Error>> {
+    let changes: Vec<Change> = subjects
+        .iter()
+        .map(|subject| subject.trim())
+        .filter(|subject| !subject.is_empty())
+        .map(|subject| {
+            let (kind, description) = classify(subject);
+            Change {
+                kind,
+                description: description.to_string(),
+            }
+        })
+        .collect();
+
+    Ok(serde_yaml::to_string(&changes)?)
+}
+
+/// Builds the changes annotation from the commit subjects in a git range
+#[cfg(feature = "io")]
+pub fn from_git(range: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let output = Command::new("git")
+        .args(["log", "--pretty=format:%s", range])
+        .output()?;
+    if !output.status.success() {
+        return Err(format!(
+            "git log {} failed: {}",
+            range,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+
+    let log = String::from_utf8(output.stdout)?;
+    format_changes(&log.lines().collect::<Vec<_>>())
+}
+
+/// Reads a pre-formatted changes annotation, checking it's a YAML list
+#[cfg(feature = "io")]
+pub fn from_file(path: &Path) -> Result<String, Box<dyn std::error::Error>> {
+    let contents = fs::read_to_string(path)?;
+    if !serde_yaml::from_str::<serde_yaml::Value>(&contents)?.is_sequence() {
+        return Err(format!("{} must contain a YAML list of changes", path.display()).into());
+    }
+
+    Ok(contents)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_changes() -> Result<(), Box<dyn std::error::Error>> {
+        let changes = format_changes(&[
+            "feat(ui): Add dark mode",
+            "fix!: Crash on start",
+            "Bump deps",
+        ])?;
+
+        assert_eq!(
+            changes,
+            "- kind: added\n  description: Add dark mode\n\
+             - kind: fixed\n  description: Crash on start\n\
+             - kind: changed\n  description: Bump deps\n"
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,28 @@
+use chrono::{DateTime, Utc};
+
+/// Source of the current time for `created` timestamps
+pub trait Clock {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// The real system time
+#[cfg(feature = "io")]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+#[cfg(feature = "io")]
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// Always returns the same instant, for deterministic tests
+#[derive(Debug, Clone, Copy)]
+pub struct FixedClock(pub DateTime<Utc>);
+
+impl Clock for FixedClock {
+    fn now(&self) -> DateTime<Utc> {
+        self.0
+    }
+}
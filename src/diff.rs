@@ -0,0 +1,74 @@
+use similar::{udiff::UnifiedHunkHeader, ChangeTag, TextDiff};
+
+/// Whether a line only carries a timestamp Helm tooling rewrites on every run
+fn is_timestamp_line(line: &str) -> bool {
+    let line = line.trim_start().trim_start_matches("- ");
+    line.starts_with("created:") || line.starts_with("generated:")
+}
+
+/// Renders a unified diff between two versions of an index, or an empty
+/// string when they're the same. With `ignore_timestamps`, hunks that only
+/// change `created`/`generated` lines are left out.
+pub fn unified(
+    old: &str,
+    new: &str,
+    old_name: &str,
+    new_name: &str,
+    ignore_timestamps: bool,
+) -> String {
+    let diff = TextDiff::from_lines(old, new);
+    let mut rendered = String::new();
+
+    for group in diff.grouped_ops(3) {
+        let changes: Vec<_> = group.iter().flat_map(|op| diff.iter_changes(op)).collect();
+        let only_timestamps = changes
+            .iter()
+            .filter(|change| change.tag() != ChangeTag::Equal)
+            .all(|change| is_timestamp_line(change.value()));
+        if ignore_timestamps && only_timestamps {
+            continue;
+        }
+
+        if rendered.is_empty() {
+            rendered.push_str(&format!("--- {}\n+++ {}\n", old_name, new_name));
+        }
+        rendered.push_str(&format!("{}\n", UnifiedHunkHeader::new(&group)));
+        for change in changes {
+            let sign = match change.tag() {
+                ChangeTag::Delete => '-',
+                ChangeTag::Insert => '+',
+                ChangeTag::Equal => ' ',
+            };
+            rendered.push(sign);
+            rendered.push_str(change.value());
+            if change.missing_newline() {
+                rendered.push('\n');
+            }
+        }
+    }
+
+    rendered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unified() {
+        let old = "entries:\n  a:\n  - created: \"2024-01-01T00:00:00Z\"\n    version: 1.0.0\n";
+        let new = "entries:\n  a:\n  - created: \"2024-06-01T00:00:00Z\"\n    version: 1.0.0\n";
+
+        let diff = unified(old, new, "index.yaml", "index.yaml", false);
+        assert_eq!(
+            diff,
+            "--- index.yaml\n+++ index.yaml\n@@ -1,4 +1,4 @@\n entries:\n   a:\n\
+             -  - created: \"2024-01-01T00:00:00Z\"\n\
+             +  - created: \"2024-06-01T00:00:00Z\"\n     version: 1.0.0\n"
+        );
+        assert_eq!(unified(old, new, "a", "b", true), "");
+
+        let new = new.replace("1.0.0", "1.0.1");
+        assert!(unified(old, &new, "a", "b", true).contains("+    version: 1.0.1\n"));
+    }
+}
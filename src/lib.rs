@@ -0,0 +1,4103 @@
+//! Reading, updating and verifying Helm chart repository indexes (`index.yaml`)
+
+use chrono::{
+    format::{Item, StrftimeItems},
+    DateTime, Utc,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use serde_yaml::{Mapping, Value};
+pub mod changelog;
+pub mod clock;
+pub mod diff;
+pub mod package;
+pub mod verify;
+pub mod writer;
+
+#[cfg(feature = "io")]
+pub use clock::SystemClock;
+pub use clock::{Clock, FixedClock};
+pub use verify::{verify, Finding, Severity};
+
+#[cfg(feature = "io")]
+use std::fs;
+use std::{
+    collections::BTreeMap,
+    fmt,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChartYaml {
+    #[serde(rename = "apiVersion")]
+    pub api_version: Option<String>,
+    pub entries: Mapping,
+    /// Top-level keys we don't model (e.g. `generated`, `serverInfo`), kept
+    /// so they survive a round-trip
+    #[serde(flatten)]
+    pub extra: Mapping,
+}
+
+impl ChartYaml {
+    /// Sets the top-level `indexUrl` pointing at the canonical location of
+    /// the index, returning whether it changed. Like any other top-level key
+    /// it survives later updates.
+    pub fn set_index_url(&mut self, url: &str) -> bool {
+        self.set_top_level("indexUrl", url)
+    }
+
+    /// Sets the top-level `repoName` so aggregators can attribute the charts
+    /// to this repository, returning whether it changed
+    pub fn set_repo_name(&mut self, name: &str) -> bool {
+        self.set_top_level("repoName", name)
+    }
+
+    fn set_top_level(&mut self, key: &str, value: &str) -> bool {
+        let value = Value::from(value);
+        self.extra.insert(Value::from(key), value.clone()) != Some(value)
+    }
+}
+
+impl Default for ChartYaml {
+    fn default() -> Self {
+        Self {
+            api_version: Some("v1".to_string()),
+            entries: Mapping::new(),
+            extra: Mapping::new(),
+        }
+    }
+}
+
+/// An index entry as `add_entry` writes it. Optional fields are left out
+/// when unset rather than written as `null`, which some Helm parsers reject.
+#[derive(Debug, Serialize, Deserialize)]
+struct ChartEntry {
+    #[serde(rename = "apiVersion")]
+    api_version: String,
+    #[serde(rename = "appVersion")]
+    app_version: String,
+    created: String,
+    description: String,
+    digest: String,
+    home: String,
+    icon: String,
+    keywords: Vec<String>,
+    maintainers: Vec<Maintainer>,
+    name: String,
+    sources: Vec<String>,
+    #[serde(rename = "type")]
+    entry_type: String,
+    urls: Vec<String>,
+    version: String,
+    /// Additional digests keyed by kind (e.g. `manifest` for the OCI manifest)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    digests: Option<BTreeMap<String, String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    annotations: Option<BTreeMap<String, String>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Maintainer {
+    #[serde(default)]
+    pub email: String,
+    pub name: String,
+    #[serde(default)]
+    pub url: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Constants {
+    #[serde(rename = "apiVersion")]
+    pub api_version: String,
+    #[serde(rename = "appVersion")]
+    pub app_version: String,
+    pub description: String,
+    pub home: String,
+    pub icon: String,
+    pub keywords: Vec<String>,
+    pub maintainers: Vec<Maintainer>,
+    pub name: String,
+    pub sources: Vec<String>,
+    #[serde(rename = "type")]
+    pub entry_type: String,
+    /// Signing key published as the `artifacthub.io/signKey` annotation
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sign_key: Option<SignKey>,
+    /// Annotations for every entry of the chart
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub annotations: Option<BTreeMap<String, String>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SignKey {
+    pub fingerprint: String,
+    pub url: String,
+}
+
+impl Default for Constants {
+    fn default() -> Self {
+        Self {
+            api_version: "v2".to_string(),
+            app_version: "1.0.0".to_string(),
+            description: "Test Chart".to_string(),
+            home: "https://example.com".to_string(),
+            icon: "https://example.com/icon.png".to_string(),
+            keywords: vec!["test".to_string(), "chart".to_string()],
+            maintainers: vec![Maintainer {
+                email: "test@example.com".to_string(),
+                name: "Abdulrhman Alkhodiry".to_string(),
+                url: "https://example.com".to_string(),
+            }],
+            name: "test-chart".to_string(),
+            sources: vec!["https://github.com/test/chart".to_string()],
+            entry_type: "application".to_string(),
+            sign_key: None,
+            annotations: None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(from = "RawParameters")]
+pub struct Parameters {
+    #[serde(rename = "appVersion")]
+    pub app_version: Option<String>,
+    pub digest: String,
+    pub version: String,
+    pub urls: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub digests: Option<BTreeMap<String, String>>,
+    /// Chart these parameters belong to, used to pick the matching profile
+    /// from a constants file listing several charts
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chart: Option<String>,
+    /// Time to record as the entry's `created` instead of now
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created: Option<DateTime<Utc>>,
+    /// Subresource-integrity hashes (e.g. `sha384-...`), one per entry of
+    /// `urls` in the same order
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url_integrity: Option<Vec<String>>,
+    /// Mirror labels keyed by URL, from `{ url, name }` items of `urls`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url_labels: Option<BTreeMap<String, String>>,
+    /// Size of the package in bytes, only known when it's read from a package
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    /// Annotations for this entry, replacing the constants' ones unless
+    /// `UpdateOptions::merge_annotations` is set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub annotations: Option<BTreeMap<String, String>>,
+}
+
+/// An item of `urls` in a parameters file: a plain URL or a labelled mirror
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum UrlItem {
+    Plain(String),
+    Labelled { url: String, name: String },
+}
+
+/// `Parameters` as written in a file, before labelled URLs are split out
+#[derive(Deserialize)]
+struct RawParameters {
+    #[serde(rename = "appVersion")]
+    app_version: Option<String>,
+    digest: String,
+    version: String,
+    urls: Vec<UrlItem>,
+    #[serde(default)]
+    digests: Option<BTreeMap<String, String>>,
+    #[serde(default)]
+    chart: Option<String>,
+    #[serde(default)]
+    created: Option<DateTime<Utc>>,
+    #[serde(default)]
+    url_integrity: Option<Vec<String>>,
+    #[serde(default)]
+    url_labels: Option<BTreeMap<String, String>>,
+    #[serde(default)]
+    annotations: Option<BTreeMap<String, String>>,
+}
+
+impl From<RawParameters> for Parameters {
+    fn from(raw: RawParameters) -> Self {
+        let mut urls = Vec::new();
+        let mut labels = raw.url_labels.unwrap_or_default();
+        for item in raw.urls {
+            match item {
+                UrlItem::Plain(url) => urls.push(url),
+                UrlItem::Labelled { url, name } => {
+                    labels.insert(url.clone(), name);
+                    urls.push(url);
+                }
+            }
+        }
+
+        Self {
+            app_version: raw.app_version,
+            digest: raw.digest,
+            version: raw.version,
+            urls,
+            digests: raw.digests,
+            chart: raw.chart,
+            created: raw.created,
+            url_integrity: raw.url_integrity,
+            url_labels: (!labels.is_empty()).then_some(labels),
+            size: None,
+            annotations: raw.annotations,
+        }
+    }
+}
+
+impl Default for Parameters {
+    fn default() -> Self {
+        Self {
+            app_version: None,
+            digest: "abc123".to_string(),
+            version: "0.1.0".to_string(),
+            urls: vec!["https://example.com/test-chart-0.1.0.tgz".to_string()],
+            digests: None,
+            chart: None,
+            created: None,
+            url_integrity: None,
+            url_labels: None,
+            size: None,
+            annotations: None,
+        }
+    }
+}
+
+/// Reads a YAML file holding either a single document or a list of them
+#[cfg(feature = "io")]
+pub fn read_yaml_list<T: serde::de::DeserializeOwned>(
+    path: &Path,
+) -> Result<Vec<T>, Box<dyn std::error::Error>> {
+    value_list(serde_yaml::from_str(&fs::read_to_string(path)?)?)
+}
+
+/// Deserializes either a single document or a list of them
+pub fn value_list<T: serde::de::DeserializeOwned>(
+    value: Value,
+) -> Result<Vec<T>, Box<dyn std::error::Error>> {
+    match value {
+        Value::Sequence(items) => items
+            .into_iter()
+            .map(|item| serde_yaml::from_value(item).map_err(Into::into))
+            .collect(),
+        value => Ok(vec![serde_yaml::from_value(value)?]),
+    }
+}
+
+/// The syntax of an input file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputFormat {
+    Yaml,
+    Json,
+}
+
+impl fmt::Display for InputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Self::Yaml => "YAML",
+            Self::Json => "JSON",
+        })
+    }
+}
+
+/// Parses `contents` as `format`, or with `None` sniffs it: YAML is tried
+/// first, then JSON, and the format that parsed is returned along with the
+/// value. When neither does, the YAML error is reported.
+pub fn parse_input<T: serde::de::DeserializeOwned>(
+    contents: &str,
+    format: Option<InputFormat>,
+) -> Result<(T, InputFormat), Box<dyn std::error::Error>> {
+    match format {
+        Some(InputFormat::Yaml) => Ok((serde_yaml::from_str(contents)?, InputFormat::Yaml)),
+        Some(InputFormat::Json) => Ok((serde_json::from_str(contents)?, InputFormat::Json)),
+        None => match serde_yaml::from_str(contents) {
+            Ok(value) => Ok((value, InputFormat::Yaml)),
+            Err(yaml_error) => match serde_json::from_str(contents) {
+                Ok(value) => Ok((value, InputFormat::Json)),
+                Err(_) => Err(yaml_error.into()),
+            },
+        },
+    }
+}
+
+/// Picks the constants profile for a chart: the only one if there is just
+/// one, otherwise the one with a matching name
+pub fn find_profile<'a>(profiles: &'a [Constants], chart: Option<&str>) -> Option<&'a Constants> {
+    match profiles {
+        [profile] => Some(profile),
+        _ => profiles
+            .iter()
+            .find(|profile| Some(profile.name.as_str()) == chart),
+    }
+}
+
+/// Pairs each parameters document with its constants profile
+pub fn pair_inputs(
+    profiles: &[Constants],
+    parameters: Vec<Parameters>,
+) -> Result<Vec<(Constants, Parameters)>, Box<dyn std::error::Error>> {
+    parameters
+        .into_iter()
+        .map(|parameters| {
+            let constants = find_profile(profiles, parameters.chart.as_deref()).ok_or_else(|| {
+                match &parameters.chart {
+                    Some(chart) => format!("No constants profile for chart '{}'", chart),
+                    None => format!(
+                        "Parameters for version {} need a `chart` to pick one of {} constants profiles",
+                        parameters.version,
+                        profiles.len()
+                    ),
+                }
+            })?;
+            Ok((constants.clone(), parameters))
+        })
+        .collect()
+}
+
+/// Warnings emitted while running a command, collected so they can be
+/// counted (and optionally treated as fatal) once the command completes
+#[derive(Debug, Default)]
+pub struct Warnings {
+    pub messages: Vec<String>,
+}
+
+impl Warnings {
+    pub fn warn(&mut self, message: impl Into<String>) {
+        let message = message.into();
+        eprintln!("warning: {}", message);
+        self.messages.push(message);
+    }
+
+    pub fn len(&self) -> usize {
+        self.messages.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+}
+
+/// The optional prefix of an OCI-style digest, `sha256:<hex>`
+pub const DIGEST_PREFIX: &str = "sha256:";
+
+/// A digest without its `sha256:` prefix, the bare hex form Helm writes
+pub fn bare_digest(digest: &str) -> &str {
+    digest.strip_prefix(DIGEST_PREFIX).unwrap_or(digest)
+}
+
+pub(crate) fn validate_hex(label: &str, value: &str) -> Result<(), Box<dyn std::error::Error>> {
+    if value.is_empty() || !value.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!("Invalid {} '{}': expected a hex string", label, value).into());
+    }
+    Ok(())
+}
+
+/// Applies the description options, leaving it untouched when none are set
+fn clean_description(description: &str, options: &UpdateOptions) -> String {
+    let mut description = description.to_string();
+    if options.strip_markdown {
+        description = strip_markdown(&description);
+    }
+    if options.trim_description {
+        description = description.split_whitespace().collect::<Vec<_>>().join(" ");
+    }
+    if let Some(max) = options.max_description_length {
+        if description.chars().count() > max {
+            let kept: String = description.chars().take(max.saturating_sub(1)).collect();
+            description = format!("{}…", kept.trim_end());
+        }
+    }
+
+    description
+}
+
+/// Removes the markdown most often found in chart descriptions: headings,
+/// quotes and list markers at the start of lines, emphasis and code markers,
+/// and links and images (keeping their text)
+fn strip_markdown(text: &str) -> String {
+    let mut stripped = Vec::new();
+    for line in text.lines() {
+        let line = line.trim_start();
+        let line = line.trim_start_matches('#').trim_start_matches('>');
+        let line = ["- ", "* ", "+ "]
+            .iter()
+            .find_map(|marker| line.trim_start().strip_prefix(marker))
+            .unwrap_or(line);
+
+        let mut out = String::new();
+        let mut rest = line;
+        while let Some(start) = rest.find('[') {
+            let (before, link) = rest.split_at(start);
+            let before = before.strip_suffix('!').unwrap_or(before);
+            let parsed = link[1..].split_once("](").and_then(|(label, after)| {
+                let (_, tail) = after.split_once(')')?;
+                Some((label, tail))
+            });
+            match parsed {
+                Some((label, tail)) => {
+                    out.push_str(before);
+                    out.push_str(label);
+                    rest = tail;
+                }
+                None => {
+                    out.push_str(&rest[..=start]);
+                    rest = &rest[start + 1..];
+                }
+            }
+        }
+        out.push_str(rest);
+
+        stripped.push(
+            out.replace("**", "")
+                .replace("__", "")
+                .replace('`', "")
+                .trim()
+                .to_string(),
+        );
+    }
+
+    stripped.join("\n")
+}
+
+/// Annotation holding the entry's `url_integrity` hashes as a JSON object
+/// keyed by URL
+pub const URL_INTEGRITY_ANNOTATION: &str = "helm-repo-updater/url-integrity";
+
+/// Annotation holding the entry's mirror labels as a JSON object keyed by URL
+pub const URL_LABELS_ANNOTATION: &str = "helm-repo-updater/url-labels";
+
+/// Annotation holding the package size in bytes, for UIs showing the
+/// download size
+pub const SIZE_ANNOTATION: &str = "hru.io/size";
+
+/// Checks a subresource-integrity hash has the `<algorithm>-<base64>` form
+fn validate_integrity(hash: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let valid = hash.split_once('-').is_some_and(|(algorithm, digest)| {
+        matches!(algorithm, "sha256" | "sha384" | "sha512")
+            && !digest.is_empty()
+            && digest
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '/' | '='))
+    });
+    if !valid {
+        return Err(format!("Invalid url_integrity hash '{}'", hash).into());
+    }
+    Ok(())
+}
+
+/// Deep-merges `overlay` into `base`: mappings are merged key by key, any
+/// other value in `overlay` replaces the one in `base`
+pub fn merge_values(base: &mut Value, overlay: Value) {
+    merge_values_with(base, overlay, false);
+}
+
+/// Like [`merge_values`], but with `merge_lists` a list in `overlay` is
+/// appended to the one in `base`, skipping items it already has
+pub fn merge_values_with(base: &mut Value, overlay: Value, merge_lists: bool) {
+    match (base, overlay) {
+        (Value::Mapping(base), Value::Mapping(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(existing) => merge_values_with(existing, value, merge_lists),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (Value::Sequence(base), Value::Sequence(overlay)) if merge_lists => {
+            for item in overlay {
+                if !base.contains(&item) {
+                    base.push(item);
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+/// Builds constants from layers (e.g. org defaults, team overrides, chart
+/// specifics), each deep-merged over the ones before it. Only the last layer
+/// may be a list of profiles; each one is merged over the earlier layers.
+pub fn layer_constants(
+    layers: Vec<Value>,
+    merge_lists: bool,
+) -> Result<Vec<Constants>, Box<dyn std::error::Error>> {
+    let count = layers.len();
+    let mut base = Value::Mapping(Mapping::new());
+    for (i, layer) in layers.into_iter().enumerate() {
+        match layer {
+            Value::Sequence(profiles) if i + 1 == count => {
+                return profiles
+                    .into_iter()
+                    .map(|profile| {
+                        let mut merged = base.clone();
+                        merge_values_with(&mut merged, profile, merge_lists);
+                        serde_yaml::from_value(merged).map_err(Into::into)
+                    })
+                    .collect();
+            }
+            Value::Sequence(_) => {
+                return Err("Only the last constants file may hold a list of profiles".into())
+            }
+            layer => merge_values_with(&mut base, layer, merge_lists),
+        }
+    }
+
+    Ok(vec![serde_yaml::from_value(base)?])
+}
+
+/// Reads constants from one file, or from several layered with
+/// [`layer_constants`]
+#[cfg(feature = "io")]
+pub fn read_constants(
+    paths: &[PathBuf],
+    merge_lists: bool,
+) -> Result<Vec<Constants>, Box<dyn std::error::Error>> {
+    match paths {
+        [] => Ok(Vec::new()),
+        [path] => read_yaml_list(path),
+        paths => {
+            let layers = paths
+                .iter()
+                .map(|path| {
+                    let contents = fs::read_to_string(path)?;
+                    serde_yaml::from_str(&contents)
+                        .map_err(|e| format!("{}: {}", path.display(), e).into())
+                })
+                .collect::<Result<Vec<Value>, Box<dyn std::error::Error>>>()?;
+            layer_constants(layers, merge_lists)
+        }
+    }
+}
+
+/// Sets the value at a dotted path like `annotations.team`, creating
+/// mappings along the way. A dot within a key is escaped as `\.`, e.g.
+/// `annotations.example\.com/team`.
+pub fn set_path(
+    base: &mut Value,
+    path: &str,
+    value: Value,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut keys = vec![String::new()];
+    let mut chars = path.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.as_str().starts_with('.') => {
+                chars.next();
+                keys.last_mut().unwrap().push('.');
+            }
+            '.' => keys.push(String::new()),
+            c => keys.last_mut().unwrap().push(c),
+        }
+    }
+
+    let mut current = base;
+    let mut keys = keys.into_iter().peekable();
+    while let Some(key) = keys.next() {
+        if key.is_empty() {
+            return Err(format!("Invalid field path '{}'", path).into());
+        }
+        if current.is_null() {
+            *current = Value::Mapping(Mapping::new());
+        }
+        let Value::Mapping(mapping) = current else {
+            return Err(format!("Can't set '{}': '{}' is not a mapping", path, key).into());
+        };
+        let key = Value::String(key);
+        if keys.peek().is_none() {
+            mapping.insert(key, value);
+            return Ok(());
+        }
+        current = mapping.entry(key).or_insert(Value::Null);
+    }
+    Ok(())
+}
+
+/// The versions of `chart` in index order
+pub fn chart_versions<'a>(data: &'a ChartYaml, chart: &str) -> Vec<&'a str> {
+    data.entries
+        .get(chart)
+        .and_then(Value::as_sequence)
+        .map(|entries| {
+            entries
+                .iter()
+                .filter_map(|entry| entry.get("version")?.as_str())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+pub fn has_version(data: &ChartYaml, chart: &str, version: &str) -> bool {
+    chart_versions(data, chart).contains(&version)
+}
+
+/// The part of a version Helm compares: valid semver without its `+build`
+/// metadata, anything else as it is
+pub fn version_identity(version: &str) -> &str {
+    match version.split_once('+') {
+        Some((identity, _)) if semver::Version::parse(version).is_ok() => identity,
+        _ => version,
+    }
+}
+
+/// The entry for `version` of `chart`, or its highest semver version when
+/// `version` is `None`
+pub fn find_entry<'a>(
+    data: &'a ChartYaml,
+    chart: &str,
+    version: Option<&str>,
+) -> Option<&'a Value> {
+    fn version_of(entry: &Value) -> Option<&str> {
+        entry.get("version").and_then(Value::as_str)
+    }
+    let entries = data.entries.get(chart)?.as_sequence()?;
+
+    match version {
+        Some(version) => entries
+            .iter()
+            .find(|entry| version_of(entry) == Some(version)),
+        None => entries
+            .iter()
+            .filter_map(|entry| Some((semver::Version::parse(version_of(entry)?).ok()?, entry)))
+            .max_by(|(a, _), (b, _)| a.cmp(b))
+            .map(|(_, entry)| entry),
+    }
+}
+
+/// The `<file>.sha256` sibling holding an index's checksum
+pub fn checksum_path(file: &Path) -> PathBuf {
+    let mut path = file.as_os_str().to_owned();
+    path.push(".sha256");
+    PathBuf::from(path)
+}
+
+/// A standalone index holding only `chart`'s entries, as served per chart
+/// alongside the combined index
+pub fn chart_shard(data: &ChartYaml, chart: &str) -> ChartYaml {
+    let mut entries = Mapping::new();
+    if let Some(versions) = data.entries.get(chart) {
+        entries.insert(Value::from(chart), versions.clone());
+    }
+
+    ChartYaml {
+        api_version: data.api_version.clone(),
+        entries,
+        extra: Mapping::new(),
+    }
+}
+
+/// The `<file>.gz` sibling holding a compressed copy of an index
+pub fn gzip_path(file: &Path) -> PathBuf {
+    let mut path = file.as_os_str().to_owned();
+    path.push(".gz");
+    PathBuf::from(path)
+}
+
+/// Gzip-compresses an index deterministically: the header records no file
+/// name and a zero mtime, so the same index always compresses to the same
+/// bytes
+pub fn gzip(contents: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let mut encoder = flate2::GzBuilder::new()
+        .mtime(0)
+        .write(Vec::new(), flate2::Compression::best());
+    encoder.write_all(contents.as_bytes())?;
+    Ok(encoder.finish()?)
+}
+
+/// Whether any URL's file name mentions `version`, catching entries whose
+/// URLs were copied from another release
+pub(crate) fn urls_match_version(urls: &[String], version: &str) -> bool {
+    urls.iter().any(|url| {
+        let path = url.split(['?', '#']).next().unwrap_or(url);
+        path.rsplit('/').next().unwrap_or(path).contains(version)
+    })
+}
+
+/// Expands `{placeholder}`s in a URL template from `values`, failing on an
+/// unknown placeholder or one whose value is empty, which would otherwise
+/// produce a malformed URL like `chart--.tgz`
+pub fn expand_url_template(
+    template: &str,
+    values: &[(&str, &str)],
+) -> Result<String, Box<dyn std::error::Error>> {
+    let mut url = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        url.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| format!("Unclosed placeholder in URL template '{}'", template))?;
+        let placeholder = &rest[start + 1..start + end];
+        let (_, value) = values
+            .iter()
+            .find(|(name, _)| *name == placeholder)
+            .ok_or_else(|| {
+                format!(
+                    "Unknown placeholder {{{}}} in URL template '{}'",
+                    placeholder, template
+                )
+            })?;
+        if value.is_empty() {
+            return Err(format!(
+                "Placeholder {{{}}} in URL template '{}' is empty",
+                placeholder, template
+            )
+            .into());
+        }
+        url.push_str(value);
+        rest = &rest[start + end + 1..];
+    }
+    url.push_str(rest);
+
+    Ok(url)
+}
+
+pub fn is_http_url(url: &str) -> bool {
+    url.starts_with("https://") || url.starts_with("http://")
+}
+
+/// The host of an absolute URL, without any user info or port, e.g.
+/// `charts.example.com` for `https://user@charts.example.com:8443/a.tgz`.
+/// `None` for relative URLs.
+pub fn url_host(url: &str) -> Option<&str> {
+    let (_, rest) = url.split_once("://")?;
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or_default();
+    let host = authority
+        .rsplit_once('@')
+        .map_or(authority, |(_, host)| host);
+    let host = match host.strip_prefix('[') {
+        // An IPv6 literal, whose colons aren't a port separator
+        Some(literal) => literal
+            .split_once(']')
+            .map_or(literal, |(address, _)| address),
+        None => host.split_once(':').map_or(host, |(name, _)| name),
+    };
+    Some(host)
+}
+
+/// Whether `url` is relative or its host is one of `allowed`, ignoring case
+pub fn host_allowed(url: &str, allowed: &[String]) -> bool {
+    url_host(url).is_none_or(|host| allowed.iter().any(|a| a.eq_ignore_ascii_case(host)))
+}
+
+/// The distinct scheme families of `urls` (http and https count as one) when
+/// there is more than one, e.g. `["http", "oci"]`. Relative URLs are ignored.
+pub(crate) fn mixed_url_schemes(urls: &[String]) -> Option<Vec<String>> {
+    let mut families: Vec<String> = Vec::new();
+    for url in urls {
+        let family = match url.split_once("://") {
+            Some(("http" | "https", _)) => "http".to_string(),
+            Some((scheme, _)) => scheme.to_lowercase(),
+            None => continue,
+        };
+        if !families.contains(&family) {
+            families.push(family);
+        }
+    }
+    (families.len() > 1).then_some(families)
+}
+
+/// Strips trailing slashes and decodes percent-escapes of unreserved
+/// characters (RFC 3986), upper-casing the escapes that remain
+pub(crate) fn normalize_url(url: &str) -> String {
+    let url = url.trim_end_matches('/');
+    let mut normalized = String::with_capacity(url.len());
+    let mut rest = url;
+
+    while let Some(start) = rest.find('%') {
+        normalized.push_str(&rest[..start]);
+        let escape = rest.get(start + 1..start + 3);
+        match escape.and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+            Some(byte) if byte.is_ascii_alphanumeric() || b"-._~".contains(&byte) => {
+                normalized.push(byte as char);
+                rest = &rest[start + 3..];
+            }
+            Some(byte) => {
+                normalized.push_str(&format!("%{:02X}", byte));
+                rest = &rest[start + 3..];
+            }
+            None => {
+                normalized.push('%');
+                rest = &rest[start + 1..];
+            }
+        }
+    }
+    normalized.push_str(rest);
+
+    normalized
+}
+
+/// Options controlling how `add_entry` builds and inserts a new entry
+#[derive(Debug, Default)]
+pub struct UpdateOptions {
+    /// Drop pre-release and build metadata from the version before inserting
+    pub strip_prerelease: bool,
+    /// Maximum number of versions a chart may have after inserting
+    pub max_entries: Option<usize>,
+    /// Remove the lowest versions instead of failing when `max_entries` is
+    /// exceeded; a new version that would itself be removed is rejected
+    pub auto_prune: bool,
+    /// Sort maintainers by name, then email
+    pub sort_maintainers: bool,
+    /// Fail instead of warning when no URL's file name contains the version,
+    /// or when the URLs mix scheme families
+    pub strict_urls: bool,
+    /// Insert the new entry first in the chart's sequence instead of last
+    pub prepend: bool,
+    /// Sort the chart's versions newest first after inserting
+    pub sort_versions: bool,
+    /// With `sort_versions`, binary-search the new entry's position in the
+    /// chart's versions instead of re-sorting them, falling back to a full
+    /// sort (with a warning) when they turn out not to be sorted
+    pub assume_sorted: bool,
+    /// Pre-formatted `artifacthub.io/changes` annotation for the new entry
+    pub changelog: Option<String>,
+    /// Merge the parameters' annotations over the constants' ones key by key
+    /// instead of replacing them
+    pub merge_annotations: bool,
+    /// Warn when the entry has more keywords than this
+    pub max_keywords: Option<usize>,
+    /// Warn about keywords longer than this many characters
+    pub max_keyword_length: Option<usize>,
+    /// Trim, lowercase and de-duplicate keywords
+    pub normalize_keywords: bool,
+    /// Normalize the entry's URLs and drop duplicates
+    pub compact_urls: bool,
+    /// Write the digest as `sha256:<hex>` instead of Helm's bare hex
+    pub digest_prefix: bool,
+    /// Treat an existing entry with the same version and digest as a no-op
+    /// instead of an error
+    pub idempotent: bool,
+    /// Collapse runs of whitespace (including newlines) in the description
+    pub trim_description: bool,
+    /// Remove basic markdown (emphasis, code, links, headings, list markers)
+    /// from the description
+    pub strip_markdown: bool,
+    /// Truncate the description to this many characters, ending it with `…`
+    pub max_description_length: Option<usize>,
+    /// chrono format for `created` [default: `DEFAULT_TIMESTAMP_FORMAT`]
+    pub timestamp_format: Option<String>,
+    /// Fail instead of warning when the resolved app version is empty
+    pub require_app_version: bool,
+    /// Fail when the resolved entry type is empty
+    pub require_type: bool,
+    /// Sort the entry's URLs lexicographically
+    pub sort_urls: bool,
+    /// Move this URL, which must be one of the entry's, to the front
+    pub primary_url: Option<String>,
+    /// Fail if the entry would have more than this many URLs
+    pub max_urls: Option<usize>,
+    /// Keep only the first `max_urls` URLs instead of failing
+    pub truncate_urls: bool,
+    /// Accept `v1.2.3` by stripping the leading `v`
+    pub normalize_version: bool,
+    /// Extra fields set on the new entry after it's built, as dotted paths
+    /// (see [`set_path`]) and values
+    pub set_fields: Vec<(String, Value)>,
+}
+
+/// How `created` is rendered unless `UpdateOptions::timestamp_format` is set
+pub const DEFAULT_TIMESTAMP_FORMAT: &str = "%Y-%m-%dT%H:%M:%S%.3fZ";
+
+/// Renders `time` with a chrono format string, which must produce an RFC 3339
+/// timestamp so the index stays readable by Helm
+pub fn format_timestamp(
+    time: DateTime<Utc>,
+    format: &str,
+) -> Result<String, Box<dyn std::error::Error>> {
+    if StrftimeItems::new(format).any(|item| item == Item::Error) {
+        return Err(format!("Invalid timestamp format '{}'", format).into());
+    }
+    let rendered = time.format(format).to_string();
+    if DateTime::parse_from_rfc3339(&rendered).is_err() {
+        return Err(format!(
+            "Timestamp format '{}' renders '{}', which isn't an RFC 3339 timestamp",
+            format, rendered
+        )
+        .into());
+    }
+
+    Ok(rendered)
+}
+
+/// What `add_entry` changed in the index
+#[derive(Debug)]
+pub struct AddOutcome {
+    pub chart: String,
+    pub version: String,
+    /// Number of versions the chart has after the insert
+    pub total_versions: usize,
+    /// Versions removed to stay within `max_entries`
+    pub pruned: Vec<String>,
+    /// False when an identical entry was already present (`--idempotent`)
+    pub changed: bool,
+}
+
+/// Chart keys of an `entries` mapping in document order, including duplicates,
+/// which serde_yaml would otherwise reject outright
+struct EntryPairs(Vec<(Value, Value)>);
+
+impl<'de> Deserialize<'de> for EntryPairs {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct PairsVisitor;
+
+        impl<'de> serde::de::Visitor<'de> for PairsVisitor {
+            type Value = EntryPairs;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("a mapping of chart names to entries")
+            }
+
+            fn visit_map<A: serde::de::MapAccess<'de>>(
+                self,
+                mut map: A,
+            ) -> Result<Self::Value, A::Error> {
+                let mut pairs = Vec::new();
+                while let Some(pair) = map.next_entry()? {
+                    pairs.push(pair);
+                }
+                Ok(EntryPairs(pairs))
+            }
+        }
+
+        deserializer.deserialize_map(PairsVisitor)
+    }
+}
+
+#[derive(Deserialize)]
+struct RawChartYaml {
+    #[serde(rename = "apiVersion")]
+    api_version: Option<String>,
+    entries: EntryPairs,
+    #[serde(flatten)]
+    extra: Mapping,
+}
+
+/// Parses an index, rejecting duplicate chart keys unless
+/// `tolerate_duplicate_keys` is set, in which case their entries are merged
+pub fn parse_index(
+    contents: &str,
+    tolerate_duplicate_keys: bool,
+    warnings: &mut Warnings,
+) -> Result<ChartYaml, Box<dyn std::error::Error>> {
+    parse_index_as(
+        contents,
+        Some(InputFormat::Yaml),
+        tolerate_duplicate_keys,
+        warnings,
+    )
+    .map(|(data, _)| data)
+}
+
+/// Like [`parse_index`] for an index in `format`, or sniffed as YAML or JSON
+/// with `None` (see [`parse_input`]), also returning the format
+pub fn parse_index_as(
+    contents: &str,
+    format: Option<InputFormat>,
+    tolerate_duplicate_keys: bool,
+    warnings: &mut Warnings,
+) -> Result<(ChartYaml, InputFormat), Box<dyn std::error::Error>> {
+    let (raw, format): (RawChartYaml, _) = parse_input(contents, format)?;
+    let mut entries = Mapping::new();
+
+    for (key, value) in raw.entries.0 {
+        let Some(existing) = entries.get_mut(&key) else {
+            entries.insert(key, value);
+            continue;
+        };
+
+        let name = key.as_str().unwrap_or("<non-string key>");
+        if !tolerate_duplicate_keys {
+            return Err(format!(
+                "Duplicate chart key '{}' in entries (use --tolerate-duplicate-keys to merge them)",
+                name
+            )
+            .into());
+        }
+        match (existing, value) {
+            (Value::Sequence(existing), Value::Sequence(more)) => existing.extend(more),
+            _ => return Err(format!("Cannot merge non-sequence entries for '{}'", name).into()),
+        }
+        warnings.warn(format!("Merged duplicate chart key '{}'", name));
+    }
+
+    Ok((
+        ChartYaml {
+            api_version: raw.api_version,
+            entries,
+            extra: raw.extra,
+        },
+        format,
+    ))
+}
+
+#[cfg(feature = "io")]
+pub fn load_index(
+    file_path: &str,
+    tolerate_duplicate_keys: bool,
+    warnings: &mut Warnings,
+) -> Result<ChartYaml, Box<dyn std::error::Error>> {
+    if Path::new(file_path).exists() {
+        let contents = fs::read_to_string(file_path)?;
+
+        let contents = if contents.trim().is_empty() {
+            "apiVersion: v1\nentries: {}\n"
+        } else {
+            &contents
+        };
+        parse_index(contents, tolerate_duplicate_keys, warnings)
+    } else {
+        Ok(ChartYaml::default())
+    }
+}
+
+/// Serializes an index for writing. Repeated values such as identical
+/// maintainers are always written out in full: serde_yaml never emits
+/// anchors or aliases, which some Helm parsers can't resolve.
+pub fn index_to_string(data: &ChartYaml) -> Result<String, Box<dyn std::error::Error>> {
+    Ok(serde_yaml::to_string(data)?)
+}
+
+/// Sets the top-level `generated` timestamp by editing the text, so the rest
+/// of the file stays byte-identical. The key is appended if it's missing.
+pub fn touch_generated(
+    contents: &str,
+    generated: &str,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let line = format!("generated: {}", generated);
+    let mut found = false;
+    let mut touched = String::with_capacity(contents.len() + line.len() + 1);
+    for old in contents.split_inclusive('\n') {
+        match old.strip_prefix("generated:") {
+            Some(value) => {
+                if found {
+                    return Err("The index has more than one top-level `generated` key".into());
+                }
+                if value.trim().is_empty() {
+                    return Err("The top-level `generated` key isn't a single-line value".into());
+                }
+                found = true;
+                touched.push_str(&line);
+                touched.push_str(&old[old.trim_end_matches(['\r', '\n']).len()..]);
+            }
+            None => touched.push_str(old),
+        }
+    }
+    if !found {
+        if !touched.is_empty() && !touched.ends_with('\n') {
+            touched.push('\n');
+        }
+        touched.push_str(&line);
+        touched.push('\n');
+    }
+
+    let parsed: Value = serde_yaml::from_str(&touched)?;
+    if parsed["generated"].as_str() != Some(generated) {
+        return Err("Couldn't update the top-level `generated` key in place".into());
+    }
+
+    Ok(touched)
+}
+
+/// The version `add_entry` stores for `version`, once `normalize_version` and
+/// `strip_prerelease` have rewritten it
+pub fn stored_version(
+    version: &str,
+    options: &UpdateOptions,
+) -> Result<semver::Version, Box<dyn std::error::Error>> {
+    let input = match options.normalize_version {
+        true => strip_version_prefix(version).unwrap_or(version),
+        false => version,
+    };
+    let mut parsed = semver::Version::parse(input)
+        .map_err(|e| format!("Invalid version '{}': {}", version, e))?;
+    if options.strip_prerelease {
+        parsed.pre = semver::Prerelease::EMPTY;
+        parsed.build = semver::BuildMetadata::EMPTY;
+    }
+    Ok(parsed)
+}
+
+/// Adds an entry created now; without the `io` feature use
+/// [`add_entry_with_clock`] with a [`FixedClock`] holding the timestamp
+#[cfg(feature = "io")]
+pub fn add_entry(
+    data: &mut ChartYaml,
+    constants: &Constants,
+    parameters: &Parameters,
+    options: &UpdateOptions,
+    warnings: &mut Warnings,
+) -> Result<AddOutcome, Box<dyn std::error::Error>> {
+    add_entry_with_clock(data, constants, parameters, options, warnings, &SystemClock)
+}
+
+/// Like `add_entry`, taking the time for `created` from `clock`
+pub fn add_entry_with_clock(
+    data: &mut ChartYaml,
+    constants: &Constants,
+    parameters: &Parameters,
+    options: &UpdateOptions,
+    warnings: &mut Warnings,
+    clock: &dyn Clock,
+) -> Result<AddOutcome, Box<dyn std::error::Error>> {
+    let version = stored_version(&parameters.version, options)?;
+    let mut version_input = parameters.version.as_str();
+    if options.normalize_version {
+        if let Some(stripped) = strip_version_prefix(version_input) {
+            warnings.warn(format!(
+                "Rewrote version '{}' to '{}' (--normalize-version)",
+                version_input, stripped
+            ));
+            version_input = stripped;
+        }
+    }
+    if options.strip_prerelease && version.to_string() != version_input {
+        warnings.warn(format!(
+            "Rewrote version '{}' to '{}' (--strip-prerelease)",
+            parameters.version, version
+        ));
+    }
+
+    let digest = bare_digest(&parameters.digest);
+    validate_hex("digest", digest)?;
+    if let Some(digests) = &parameters.digests {
+        for (kind, digest) in digests {
+            validate_hex(&format!("digests.{}", kind), bare_digest(digest))?;
+        }
+    }
+
+    if parameters.size == Some(0) {
+        return Err(format!(
+            "The package for {} {} is empty (0 bytes)",
+            constants.name, parameters.version
+        )
+        .into());
+    }
+    if let Some(integrity) = &parameters.url_integrity {
+        if integrity.len() != parameters.urls.len() {
+            return Err(format!(
+                "url_integrity has {} hashes for {} urls",
+                integrity.len(),
+                parameters.urls.len()
+            )
+            .into());
+        }
+        for hash in integrity {
+            validate_integrity(hash)?;
+        }
+    }
+
+    let mut urls: Vec<String> = Vec::new();
+    for url in &parameters.urls {
+        if !options.compact_urls {
+            urls.push(url.clone());
+            continue;
+        }
+        let url = normalize_url(url);
+        if !urls.contains(&url) {
+            urls.push(url);
+        }
+    }
+
+    if options.sort_urls {
+        urls.sort();
+    }
+    if let Some(primary) = &options.primary_url {
+        let position = urls
+            .iter()
+            .position(|url| url == primary)
+            .ok_or_else(|| format!("Primary URL '{}' is not one of the entry's URLs", primary))?;
+        let primary = urls.remove(position);
+        urls.insert(0, primary);
+    }
+    if let Some(max_urls) = options.max_urls {
+        if urls.len() > max_urls {
+            let message = format!(
+                "{} {} has {} URLs, more than --max-urls {}",
+                constants.name,
+                parameters.version,
+                urls.len(),
+                max_urls
+            );
+            if !options.truncate_urls {
+                return Err(message.into());
+            }
+            warnings.warn(format!("{}; keeping the first {}", message, max_urls));
+            urls.truncate(max_urls);
+        }
+    }
+
+    for url in &urls {
+        if !is_http_url(url) {
+            warnings.warn(format!("URL '{}' is not an http(s) URL", url));
+        }
+    }
+
+    let version_string = version.to_string();
+    if !urls.is_empty() && !urls_match_version(&urls, &version_string) {
+        let message = format!(
+            "No URL file name contains version {}: {}",
+            version_string,
+            urls.join(", ")
+        );
+        if options.strict_urls {
+            return Err(message.into());
+        }
+        warnings.warn(message);
+    }
+
+    if let Some(schemes) = mixed_url_schemes(&urls) {
+        let message = format!(
+            "URLs of {} {} mix {} schemes: {}",
+            constants.name,
+            version_string,
+            schemes.join(" and "),
+            urls.join(", ")
+        );
+        if options.strict_urls {
+            return Err(message.into());
+        }
+        warnings.warn(message);
+    }
+
+    let mut keywords: Vec<String> = Vec::new();
+    for keyword in &constants.keywords {
+        if options.normalize_keywords {
+            let keyword = keyword.trim().to_lowercase();
+            if !keyword.is_empty() && !keywords.contains(&keyword) {
+                keywords.push(keyword);
+            }
+        } else {
+            if keywords.contains(keyword) {
+                warnings.warn(format!("Duplicate keyword '{}'", keyword));
+            }
+            keywords.push(keyword.clone());
+        }
+    }
+    if let Some(max_keywords) = options.max_keywords {
+        if keywords.len() > max_keywords {
+            warnings.warn(format!(
+                "'{}' has {} keywords, more than --max-keywords {}",
+                constants.name,
+                keywords.len(),
+                max_keywords
+            ));
+        }
+    }
+    if let Some(max_length) = options.max_keyword_length {
+        for keyword in keywords.iter().filter(|k| k.chars().count() > max_length) {
+            warnings.warn(format!(
+                "Keyword '{}' is longer than --max-keyword-length {}",
+                keyword, max_length
+            ));
+        }
+    }
+
+    let mut annotations = match &parameters.annotations {
+        Some(own) if options.merge_annotations => {
+            let mut merged = constants.annotations.clone().unwrap_or_default();
+            merged.extend(own.clone());
+            merged
+        }
+        Some(own) => own.clone(),
+        None => constants.annotations.clone().unwrap_or_default(),
+    };
+    if let Some(sign_key) = &constants.sign_key {
+        validate_hex("signKey fingerprint", &sign_key.fingerprint)?;
+        annotations.insert(
+            "artifacthub.io/signKey".to_string(),
+            serde_json::to_string(sign_key)?,
+        );
+    }
+    if let Some(changelog) = &options.changelog {
+        annotations.insert("artifacthub.io/changes".to_string(), changelog.clone());
+    }
+    if let Some(integrity) = &parameters.url_integrity {
+        let by_url: BTreeMap<String, &String> = parameters
+            .urls
+            .iter()
+            .map(|url| {
+                if options.compact_urls {
+                    normalize_url(url)
+                } else {
+                    url.clone()
+                }
+            })
+            .zip(integrity)
+            .filter(|(url, _)| urls.contains(url))
+            .collect();
+        annotations.insert(
+            URL_INTEGRITY_ANNOTATION.to_string(),
+            serde_json::to_string(&by_url)?,
+        );
+    }
+    if let Some(labels) = &parameters.url_labels {
+        let by_url: BTreeMap<String, &String> = labels
+            .iter()
+            .map(|(url, name)| {
+                let url = if options.compact_urls {
+                    normalize_url(url)
+                } else {
+                    url.clone()
+                };
+                (url, name)
+            })
+            .filter(|(url, _)| urls.contains(url))
+            .collect();
+        if !by_url.is_empty() {
+            annotations.insert(
+                URL_LABELS_ANNOTATION.to_string(),
+                serde_json::to_string(&by_url)?,
+            );
+        }
+    }
+
+    if let Some(size) = parameters.size {
+        annotations.insert(SIZE_ANNOTATION.to_string(), size.to_string());
+    }
+
+    let mut maintainers = constants.maintainers.clone();
+    if options.sort_maintainers {
+        maintainers.sort_by(|a, b| (&a.name, &a.email).cmp(&(&b.name, &b.email)));
+    }
+
+    let now = parameters.created.unwrap_or_else(|| clock.now());
+    let created = format_timestamp(
+        now,
+        options
+            .timestamp_format
+            .as_deref()
+            .unwrap_or(DEFAULT_TIMESTAMP_FORMAT),
+    )?;
+
+    let app_version = parameters
+        .app_version
+        .clone()
+        .unwrap_or_else(|| constants.app_version.clone());
+    if app_version.trim().is_empty() {
+        let message = format!(
+            "{} {} has an empty app version",
+            constants.name, version_string
+        );
+        if options.require_app_version {
+            return Err(message.into());
+        }
+        warnings.warn(message);
+    }
+    if options.require_type && constants.entry_type.trim().is_empty() {
+        return Err(format!("{} {} has an empty type", constants.name, version_string).into());
+    }
+
+    let new_entry = ChartEntry {
+        api_version: constants.api_version.clone(),
+        app_version,
+        created,
+        description: clean_description(&constants.description, options),
+        digest: match options.digest_prefix {
+            true => format!("{}{}", DIGEST_PREFIX, digest),
+            false => digest.to_string(),
+        },
+        home: constants.home.clone(),
+        icon: constants.icon.clone(),
+        keywords,
+        maintainers,
+        name: constants.name.clone(),
+        sources: constants.sources.clone(),
+        entry_type: constants.entry_type.clone(),
+        urls,
+        version: version_string,
+        digests: parameters.digests.clone(),
+        annotations: (!annotations.is_empty()).then_some(annotations),
+    };
+
+    let entries_key = Value::String(constants.name.clone());
+    let entries = data
+        .entries
+        .entry(entries_key)
+        .or_insert(Value::Sequence(Vec::new()));
+
+    let Value::Sequence(ref mut vec) = entries else {
+        return Err("Unexpected value type for entries".into());
+    };
+
+    // Helm ignores build metadata, so `1.0.0+a` and `1.0.0+b` are the same
+    let identity = version_identity(&new_entry.version);
+    let existing = vec.iter().find(|entry| {
+        entry
+            .get("version")
+            .and_then(Value::as_str)
+            .is_some_and(|version| version_identity(version) == identity)
+    });
+    let has_digest = |entry: &Value| {
+        entry.get("digest").and_then(Value::as_str).map(bare_digest) == Some(digest)
+    };
+    if let Some(existing) = existing {
+        let existing_version = existing.get("version").and_then(Value::as_str);
+        if existing_version != Some(&new_entry.version) {
+            return Err(format!(
+                "'{}' already has version {}, which differs from {} only in build metadata \
+                 that Helm ignores",
+                constants.name,
+                existing_version.unwrap_or_default(),
+                new_entry.version
+            )
+            .into());
+        }
+        let same_digest = has_digest(existing);
+        if options.idempotent && same_digest {
+            return Ok(AddOutcome {
+                chart: new_entry.name,
+                version: new_entry.version,
+                total_versions: vec.len(),
+                pruned: Vec::new(),
+                changed: false,
+            });
+        }
+        return Err(format!(
+            "'{}' already has version {}{}",
+            constants.name,
+            new_entry.version,
+            if same_digest {
+                " (pass --idempotent to accept re-running the same update)"
+            } else {
+                " with a different digest"
+            }
+        )
+        .into());
+    }
+
+    let reused_by: Vec<&str> = vec
+        .iter()
+        .filter(|entry| has_digest(entry))
+        .filter_map(|entry| entry.get("version")?.as_str())
+        .filter(|existing| *existing != new_entry.version)
+        .collect();
+    if !reused_by.is_empty() {
+        warnings.warn(format!(
+            "Digest of '{}' {} is already used by version {}; was the same package re-tagged?",
+            constants.name,
+            new_entry.version,
+            reused_by.join(", ")
+        ));
+    }
+
+    let latest = vec
+        .iter()
+        .filter_map(|entry| entry.get("created")?.as_str())
+        .filter_map(|created| DateTime::parse_from_rfc3339(created).ok())
+        .max();
+    if let Some(latest) = latest {
+        if latest > now {
+            warnings.warn(format!(
+                "New entry for '{}' is older than the latest existing entry ({})",
+                constants.name,
+                latest.to_rfc3339()
+            ));
+        }
+    }
+    if let Some(max_entries) = options.max_entries {
+        if vec.len() + 1 > max_entries && (!options.auto_prune || max_entries == 0) {
+            return Err(format!(
+                "'{}' would have {} versions, exceeding --max-entries {}",
+                constants.name,
+                vec.len() + 1,
+                max_entries
+            )
+            .into());
+        }
+        // Pruning removes the lowest versions, which may include the new one
+        let new = semver::Version::parse(&new_entry.version).ok();
+        let higher = vec
+            .iter()
+            .filter_map(|entry| entry.get("version")?.as_str())
+            .filter(|version| semver::Version::parse(version).ok() > new)
+            .count();
+        if options.auto_prune && higher >= max_entries {
+            return Err(format!(
+                "'{}' {} is lower than the {} versions --auto-prune would keep, so it would \
+                 be pruned right away",
+                constants.name, new_entry.version, max_entries
+            )
+            .into());
+        }
+    }
+    let mut value = serde_yaml::to_value(&new_entry)?;
+    for (path, field) in &options.set_fields {
+        set_path(&mut value, path, field.clone())?;
+    }
+    // Falls through to a full sort when the versions turn out not to be
+    // sorted, or the new one isn't semver
+    let unplaced = match options.sort_versions && options.assume_sorted {
+        true if is_sorted_newest_first(vec) => insert_sorted(vec, value).err(),
+        true => {
+            warnings.warn(format!(
+                "{}: versions aren't sorted newest first despite --assume-sorted, sorting \
+                 them fully",
+                constants.name
+            ));
+            Some(value)
+        }
+        false => Some(value),
+    };
+    if let Some(value) = unplaced {
+        if options.prepend {
+            vec.insert(0, value);
+        } else {
+            vec.push(value);
+        }
+        if options.sort_versions {
+            for version in sort_versions(vec, false) {
+                warnings.warn(format!(
+                    "{}: version '{}' is not valid semver, sorted last",
+                    constants.name, version
+                ));
+            }
+        }
+    }
+
+    let pruned = match options.max_entries {
+        Some(max_entries) => prune_lowest_versions(vec, max_entries, &new_entry.version),
+        None => Vec::new(),
+    };
+
+    Ok(AddOutcome {
+        chart: new_entry.name,
+        version: new_entry.version,
+        total_versions: vec.len(),
+        pruned,
+        changed: true,
+    })
+}
+
+/// Strips a single leading `v` from a version like `v1.2.3`, if what remains
+/// is valid semver
+pub fn strip_version_prefix(version: &str) -> Option<&str> {
+    version
+        .strip_prefix('v')
+        .filter(|stripped| semver::Version::parse(stripped).is_ok())
+}
+
+/// Rewrites `v`-prefixed versions of existing entries (see
+/// [`strip_version_prefix`]), returning each original version
+pub fn normalize_entry_versions(entries: &mut [Value]) -> Vec<String> {
+    let mut rewritten = Vec::new();
+    for entry in entries {
+        let Some(version) = entry.get("version").and_then(Value::as_str) else {
+            continue;
+        };
+        if let Some(stripped) = strip_version_prefix(version) {
+            let stripped = Value::String(stripped.to_string());
+            rewritten.push(version.to_string());
+            entry["version"] = stripped;
+        }
+    }
+    rewritten
+}
+
+fn entry_semver(entry: &Value) -> Option<semver::Version> {
+    entry
+        .get("version")
+        .and_then(Value::as_str)
+        .and_then(|version| semver::Version::parse(version).ok())
+}
+
+/// Whether entries are sorted newest first, with unparseable versions last
+pub fn is_sorted_newest_first(entries: &[Value]) -> bool {
+    let versions: Vec<_> = entries.iter().map(entry_semver).collect();
+    versions.windows(2).all(|pair| match (&pair[0], &pair[1]) {
+        (Some(a), Some(b)) => a >= b,
+        (None, Some(_)) => false,
+        _ => true,
+    })
+}
+
+/// Inserts `entry` into entries sorted newest first (unparseable versions
+/// last) at its semver position, found by binary search. The order isn't
+/// checked here; see [`is_sorted_newest_first`]. Hands the entry back if its
+/// version isn't semver.
+pub fn insert_sorted(entries: &mut Vec<Value>, entry: Value) -> Result<(), Value> {
+    let Some(version) = entry_semver(&entry) else {
+        return Err(entry);
+    };
+
+    let position =
+        entries.partition_point(|entry| entry_semver(entry).is_some_and(|v| v > version));
+    entries.insert(position, entry);
+    Ok(())
+}
+
+/// Sorts a chart's entries by semver, descending unless `ascending` is set.
+/// Entries with unparseable versions keep their relative order at the end, and
+/// are returned so the caller can report them.
+pub fn sort_versions(entries: &mut [Value], ascending: bool) -> Vec<String> {
+    let version_of = entry_semver;
+
+    entries.sort_by(|a, b| match (version_of(a), version_of(b)) {
+        (Some(a), Some(b)) if ascending => a.cmp(&b),
+        (Some(a), Some(b)) => b.cmp(&a),
+        (a, b) => b.is_some().cmp(&a.is_some()),
+    });
+
+    entries
+        .iter()
+        .filter(|entry| version_of(entry).is_none())
+        .map(|entry| {
+            entry
+                .get("version")
+                .and_then(Value::as_str)
+                .unwrap_or("<missing>")
+                .to_string()
+        })
+        .collect()
+}
+
+/// Rewrites the whole index in its canonical form: charts sorted by name,
+/// versions newest first, and each entry's URLs sorted, maintainers sorted by
+/// name then email and keywords trimmed, lowercased and de-duplicated.
+/// Returns the `chart@version`s that aren't valid semver (sorted last).
+pub fn canonicalize(data: &mut ChartYaml) -> Vec<String> {
+    sort_charts(data);
+
+    let mut unsorted = Vec::new();
+    for (name, entries) in data.entries.iter_mut() {
+        if let Value::Sequence(entries) = entries {
+            let chart = name.as_str().unwrap_or("<non-string key>");
+            for version in sort_versions(entries, false) {
+                unsorted.push(format!("{}@{}", chart, version));
+            }
+            for entry in entries.iter_mut() {
+                canonicalize_entry(entry);
+            }
+        }
+    }
+    unsorted
+}
+
+/// Sorts the `entries` map by chart name. `Mapping` keeps insertion order,
+/// so the map is rebuilt.
+pub fn sort_charts(data: &mut ChartYaml) {
+    let mut charts: Vec<(Value, Value)> = std::mem::take(&mut data.entries).into_iter().collect();
+    charts.sort_by(|(a, _), (b, _)| a.as_str().cmp(&b.as_str()));
+    data.entries = charts.into_iter().collect();
+}
+
+fn canonicalize_entry(entry: &mut Value) {
+    if let Some(Value::Sequence(urls)) = entry.get_mut("urls") {
+        urls.sort_by(|a, b| a.as_str().cmp(&b.as_str()));
+    }
+    if let Some(Value::Sequence(maintainers)) = entry.get_mut("maintainers") {
+        let key = |maintainer: &Value| {
+            let field = |name| {
+                maintainer
+                    .get(name)
+                    .and_then(Value::as_str)
+                    .map(str::to_string)
+            };
+            (field("name"), field("email"))
+        };
+        maintainers.sort_by_key(key);
+    }
+    if let Some(Value::Sequence(keywords)) = entry.get_mut("keywords") {
+        let mut normalized: Vec<Value> = Vec::new();
+        for keyword in keywords.iter() {
+            let Some(keyword) = keyword.as_str() else {
+                normalized.push(keyword.clone());
+                continue;
+            };
+            let keyword = Value::String(keyword.trim().to_lowercase());
+            if keyword.as_str() != Some("") && !normalized.contains(&keyword) {
+                normalized.push(keyword);
+            }
+        }
+        *keywords = normalized;
+    }
+}
+
+impl AddOutcome {
+    pub fn to_json(&self) -> serde_json::Value {
+        json!({
+            "action": "add",
+            "chart": self.chart,
+            "version": self.version,
+            "changed": self.changed,
+            "total_versions": self.total_versions,
+            "pruned": self.pruned,
+        })
+    }
+}
+
+/// Removes every entry of `chart` whose version satisfies `matches`, dropping
+/// the chart altogether once it has no entries left. Returns the removed versions.
+pub fn remove_versions(
+    data: &mut ChartYaml,
+    chart: &str,
+    matches: impl Fn(&str) -> bool,
+) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let Some(entries) = data.entries.get_mut(chart) else {
+        return Err(format!("Chart '{}' not found", chart).into());
+    };
+    let Value::Sequence(entries) = entries else {
+        return Err(format!("Unexpected value type for entries of '{}'", chart).into());
+    };
+
+    let mut removed = Vec::new();
+    entries.retain(|entry| {
+        let version = entry.get("version").and_then(Value::as_str).unwrap_or("");
+        if matches(version) {
+            removed.push(version.to_string());
+            false
+        } else {
+            true
+        }
+    });
+    if entries.is_empty() {
+        data.entries.remove(chart);
+    }
+
+    Ok(removed)
+}
+
+/// Entry fields identifying the published artifact, which a metadata patch
+/// must never change
+pub const ARTIFACT_FIELDS: [&str; 5] = ["digest", "urls", "version", "created", "name"];
+
+/// Merges a partial entry (e.g. a new `description` or `maintainers`) into the
+/// entries of `chart`, or only into `version`. Patches touching any of
+/// `ARTIFACT_FIELDS` are rejected, so a patch is always artifact-neutral.
+/// Returns the patched versions.
+pub fn patch_entries(
+    data: &mut ChartYaml,
+    chart: &str,
+    version: Option<&str>,
+    patch: &Value,
+) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let Value::Mapping(fields) = patch else {
+        return Err("A patch must be a mapping of entry fields".into());
+    };
+    for field in ARTIFACT_FIELDS {
+        if fields.contains_key(field) {
+            return Err(format!("A patch can't change `{}`", field).into());
+        }
+    }
+    let Some(Value::Sequence(entries)) = data.entries.get_mut(chart) else {
+        return Err(format!("Chart '{}' not found", chart).into());
+    };
+
+    let mut patched = Vec::new();
+    for entry in entries.iter_mut() {
+        let Some(current) = entry.get("version").and_then(Value::as_str) else {
+            continue;
+        };
+        if version.is_some_and(|version| version != current) {
+            continue;
+        }
+
+        patched.push(current.to_string());
+        merge_values(entry, patch.clone());
+    }
+    if patched.is_empty() {
+        return Err(format!(
+            "Version {} of '{}' not found",
+            version.unwrap_or_default(),
+            chart
+        )
+        .into());
+    }
+
+    Ok(patched)
+}
+
+/// Collapses entries of the same chart sharing a version, keeping the one
+/// with the newest `created`. Differing digests are reported as warnings, or
+/// rejected with `strict` before anything is removed. Returns the removed
+/// entries as `chart@version`.
+pub fn dedup_entries(
+    data: &mut ChartYaml,
+    strict: bool,
+    warnings: &mut Warnings,
+) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    fn field<'a>(entry: &'a Value, key: &str) -> Option<&'a str> {
+        entry.get(key).and_then(Value::as_str)
+    }
+    let mut removed = Vec::new();
+
+    for (name, entries) in data.entries.iter_mut() {
+        let name = name.as_str().unwrap_or("<non-string key>");
+        let Value::Sequence(entries) = entries else {
+            return Err(format!("Unexpected value type for entries of '{}'", name).into());
+        };
+
+        // The index of the entry kept for each duplicated version
+        let mut keep: BTreeMap<&str, usize> = BTreeMap::new();
+        for (i, entry) in entries.iter().enumerate() {
+            let Some(version) = field(entry, "version") else {
+                continue;
+            };
+            let Some(&kept) = keep.get(version) else {
+                keep.insert(version, i);
+                continue;
+            };
+
+            let kept_entry = &entries[kept];
+            if field(kept_entry, "digest").map(bare_digest)
+                != field(entry, "digest").map(bare_digest)
+            {
+                let message = format!(
+                    "{}@{}: duplicate entries have different digests",
+                    name, version
+                );
+                if strict {
+                    return Err(message.into());
+                }
+                warnings.warn(message);
+            }
+            let created = |entry: &Value| {
+                field(entry, "created").and_then(|c| DateTime::parse_from_rfc3339(c).ok())
+            };
+            if created(entry) > created(kept_entry) {
+                keep.insert(version, i);
+            }
+        }
+
+        let kept: Vec<usize> = keep.values().copied().collect();
+        let mut i = 0;
+        entries.retain(|entry| {
+            let retain = field(entry, "version").is_none() || kept.contains(&i);
+            if !retain {
+                removed.push(format!(
+                    "{}@{}",
+                    name,
+                    field(entry, "version").unwrap_or("")
+                ));
+            }
+            i += 1;
+            retain
+        });
+    }
+
+    Ok(removed)
+}
+
+/// The highest version inserted per chart, kept across runs so a pipeline
+/// re-running an old build can't insert a lower version
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct VersionLock(pub BTreeMap<String, String>);
+
+impl VersionLock {
+    /// Fails if `version` is lower than the one recorded for `chart`, or
+    /// isn't valid semver and so can't be ordered against it
+    pub fn check(&self, chart: &str, version: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let Some(locked) = self.0.get(chart) else {
+            return Ok(());
+        };
+        let Ok(new) = semver::Version::parse(version) else {
+            return Err(format!(
+                "Refusing to add {}@{}: it isn't valid semver, so it can't be checked against {} \
+                 already inserted (pass --allow-downgrade to add it anyway)",
+                chart, version, locked
+            )
+            .into());
+        };
+        if let Ok(old) = semver::Version::parse(locked) {
+            if new.cmp_precedence(&old).is_lt() {
+                return Err(format!(
+                    "Refusing to add {}@{}: {} was already inserted (pass --allow-downgrade to add it anyway)",
+                    chart, version, locked
+                )
+                .into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Records `version` if it's higher than the one recorded for `chart`,
+    /// returning whether it was
+    pub fn record(&mut self, chart: &str, version: &str) -> bool {
+        let Ok(new) = semver::Version::parse(version) else {
+            return false;
+        };
+        let higher = match self.0.get(chart).map(|v| semver::Version::parse(v)) {
+            Some(Ok(old)) => new.cmp_precedence(&old).is_gt(),
+            _ => true,
+        };
+        if higher {
+            self.0.insert(chart.to_string(), version.to_string());
+        }
+        higher
+    }
+}
+
+/// Selects charts by name with `*`/`?` globs. A chart is selected if it
+/// matches any include pattern (or there are none) and no exclude pattern, so
+/// excludes take precedence.
+#[derive(Debug, Default, Clone)]
+pub struct ChartFilter {
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+}
+
+impl ChartFilter {
+    /// An allowlist read from one name or glob per line, skipping blank lines
+    /// and `#` comments. Unlike an empty `include`, an empty list allows
+    /// nothing, so it's rejected.
+    pub fn allowlist(contents: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let include: Vec<String> = contents
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(str::to_string)
+            .collect();
+        if include.is_empty() {
+            return Err("The allowlist doesn't allow any chart names".into());
+        }
+
+        Ok(Self {
+            include,
+            exclude: Vec::new(),
+        })
+    }
+
+    pub fn matches(&self, chart: &str) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|p| glob_match(p, chart)))
+            && !self.exclude.iter().any(|p| glob_match(p, chart))
+    }
+
+    /// Drops the charts that aren't selected from an `entries` mapping
+    pub fn retain(&self, entries: &mut Mapping) {
+        entries.retain(|name, _| name.as_str().is_some_and(|name| self.matches(name)));
+    }
+}
+
+/// Matches `text` against a glob where `*` is any run of characters and `?`
+/// any single character
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // Where the last `*` was, and the text position it currently absorbs up to
+    let mut star: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                Some((star_p, star_t)) => {
+                    p = star_p + 1;
+                    t = star_t + 1;
+                    star = Some((star_p, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Keeps at most `keep` versions of each selected chart, removing the lowest
+/// semver versions first. With `keep_latest_stable`, each chart's highest
+/// non-prerelease version is kept even if that means dropping a higher
+/// pre-release instead, with a warning when this overrides the count. Returns
+/// the removed entries as `chart@version`.
+pub fn prune_index(
+    data: &mut ChartYaml,
+    keep: usize,
+    filter: &ChartFilter,
+    keep_latest_stable: bool,
+    warnings: &mut Warnings,
+) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let mut removed = Vec::new();
+    for (name, entries) in data.entries.iter_mut() {
+        let name = name.as_str().unwrap_or("<non-string key>");
+        if !filter.matches(name) {
+            continue;
+        }
+        let Value::Sequence(entries) = entries else {
+            return Err(format!("Unexpected value type for entries of '{}'", name).into());
+        };
+
+        let latest_stable = keep_latest_stable
+            .then(|| latest_stable_version(entries))
+            .flatten();
+        let protected = latest_stable.as_ref().map(ToString::to_string);
+        let pruned = prune_lowest_versions(entries, keep, protected.as_deref().unwrap_or(""));
+        // Pruning a higher version means the stable one was skipped over
+        if let Some(latest_stable) = &latest_stable {
+            let pruned_higher = pruned
+                .iter()
+                .any(|v| semver::Version::parse(v).is_ok_and(|v| v > *latest_stable));
+            if pruned_higher {
+                warnings.warn(format!(
+                    "Kept {}@{}, the latest stable version, over --keep {}",
+                    name, latest_stable, keep
+                ));
+            }
+        }
+        for version in pruned {
+            removed.push(format!("{}@{}", name, version));
+        }
+    }
+
+    Ok(removed)
+}
+
+/// The highest version without a pre-release part
+fn latest_stable_version(entries: &[Value]) -> Option<semver::Version> {
+    entries
+        .iter()
+        .filter_map(|entry| entry.get("version")?.as_str())
+        .filter_map(|version| semver::Version::parse(version).ok())
+        .filter(|version| version.pre.is_empty())
+        .max()
+}
+
+/// Removes the lowest semver versions (unparseable ones first) until at most
+/// `keep` entries remain, never removing `protected`. Returns the removed versions.
+fn prune_lowest_versions(entries: &mut Vec<Value>, keep: usize, protected: &str) -> Vec<String> {
+    let mut pruned = Vec::new();
+
+    while entries.len() > keep {
+        let lowest = entries
+            .iter()
+            .enumerate()
+            .filter_map(|(i, entry)| Some((i, entry.get("version")?.as_str()?)))
+            .filter(|(_, version)| *version != protected)
+            .min_by_key(|(_, version)| semver::Version::parse(version).ok())
+            .map(|(i, _)| i);
+        let Some(lowest) = lowest else {
+            break;
+        };
+
+        let removed = entries.remove(lowest);
+        if let Some(version) = removed.get("version").and_then(Value::as_str) {
+            pruned.push(version.to_string());
+        }
+    }
+
+    pruned
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use tempfile::NamedTempFile;
+
+    fn update_yaml(
+        file_path: &str,
+        constants: &Constants,
+        parameters: &Parameters,
+        options: &UpdateOptions,
+        warnings: &mut Warnings,
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        let mut data = load_index(file_path, false, warnings)?;
+        add_entry(&mut data, constants, parameters, options, warnings)?;
+        index_to_string(&data)
+    }
+
+    fn create_test_constants() -> Constants {
+        Constants {
+            api_version: "v2".to_string(),
+            app_version: "1.0.0".to_string(),
+            description: "Test Chart".to_string(),
+            home: "https://example.com".to_string(),
+            icon: "https://example.com/icon.png".to_string(),
+            keywords: vec!["test".to_string(), "chart".to_string()],
+            maintainers: vec![Maintainer {
+                email: "test@example.com".to_string(),
+                name: "Test Maintainer".to_string(),
+                url: "https://example.com".to_string(),
+            }],
+            name: "test-chart".to_string(),
+            sources: vec!["https://github.com/test/chart".to_string()],
+            entry_type: "application".to_string(),
+            sign_key: None,
+            annotations: None,
+        }
+    }
+
+    fn create_test_parameters() -> Parameters {
+        Parameters {
+            app_version: Some("1.0.1".to_string()),
+            digest: "abc123".to_string(),
+            version: "0.1.0".to_string(),
+            urls: vec!["https://example.com/test-chart-0.1.0.tgz".to_string()],
+            digests: None,
+            chart: None,
+            created: None,
+            url_integrity: None,
+            url_labels: None,
+            size: None,
+            annotations: None,
+        }
+    }
+
+    #[test]
+    fn test_update_yaml_new_file() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_file = NamedTempFile::new()?;
+        let file_path = temp_file.path().to_str().unwrap();
+
+        let constants = create_test_constants();
+        let parameters = create_test_parameters();
+
+        let updated_yaml = update_yaml(
+            file_path,
+            &constants,
+            &parameters,
+            &UpdateOptions::default(),
+            &mut Warnings::default(),
+        )?;
+        let parsed: ChartYaml = serde_yaml::from_str(&updated_yaml)?;
+
+        assert_eq!(parsed.api_version, Some("v1".to_string()));
+        assert_eq!(parsed.entries.len(), 1);
+
+        let entries = parsed
+            .entries
+            .get(Value::String("test-chart".to_string()))
+            .unwrap();
+        let entries: Vec<ChartEntry> = serde_yaml::from_value(entries.clone())?;
+        assert_eq!(entries.len(), 1);
+
+        let entry = &entries[0];
+        assert_eq!(entry.api_version, "v2");
+        assert_eq!(entry.app_version, "1.0.1");
+        assert_eq!(entry.description, "Test Chart");
+        assert_eq!(entry.digest, "abc123");
+        assert_eq!(entry.version, "0.1.0");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_update_yaml_existing_file() -> Result<(), Box<dyn std::error::Error>> {
+        let mut temp_file = NamedTempFile::new()?;
+
+        let initial_content = r#"
+apiVersion: v1
+entries:
+  test-chart:
+    - apiVersion: v2
+      appVersion: 1.0.0
+      created: "2023-01-01T00:00:00.000Z"
+      description: Initial Test Chart
+      digest: def456
+      home: https://example.com
+      icon: https://example.com/icon.png
+      keywords:
+        - test
+        - chart
+      maintainers:
+        - email: test@example.com
+          name: Test Maintainer
+          url: https://example.com
+      name: test-chart
+      sources:
+        - https://github.com/test/chart
+      type: application
+      urls:
+        - https://example.com/test-chart-0.0.1.tgz
+      version: 0.0.1
+"#;
+        write!(temp_file, "{}", initial_content)?;
+        let file_path = temp_file.path().to_str().unwrap();
+
+        let constants = create_test_constants();
+        let parameters = create_test_parameters();
+
+        let updated_yaml = update_yaml(
+            file_path,
+            &constants,
+            &parameters,
+            &UpdateOptions::default(),
+            &mut Warnings::default(),
+        )?;
+        let parsed: ChartYaml = serde_yaml::from_str(&updated_yaml)?;
+
+        assert_eq!(parsed.api_version, Some("v1".to_string()));
+        assert_eq!(parsed.entries.len(), 1);
+
+        let entries = parsed
+            .entries
+            .get(Value::String("test-chart".to_string()))
+            .unwrap();
+        let entries: Vec<ChartEntry> = serde_yaml::from_value(entries.clone())?;
+        assert_eq!(entries.len(), 2);
+
+        let new_entry = &entries[1];
+        assert_eq!(new_entry.api_version, "v2");
+        assert_eq!(new_entry.app_version, "1.0.1");
+        assert_eq!(new_entry.description, "Test Chart");
+        assert_eq!(new_entry.digest, "abc123");
+        assert_eq!(new_entry.version, "0.1.0");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_update_yaml_multiple_digests() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_file = NamedTempFile::new()?;
+        let file_path = temp_file.path().to_str().unwrap();
+
+        let constants = create_test_constants();
+        let mut parameters = create_test_parameters();
+        parameters.digests = Some(BTreeMap::from([(
+            "manifest".to_string(),
+            "0123abcd".to_string(),
+        )]));
+
+        let updated_yaml = update_yaml(
+            file_path,
+            &constants,
+            &parameters,
+            &UpdateOptions::default(),
+            &mut Warnings::default(),
+        )?;
+        let parsed: ChartYaml = serde_yaml::from_str(&updated_yaml)?;
+        let entries = parsed
+            .entries
+            .get(Value::String("test-chart".to_string()))
+            .unwrap();
+        let entries: Vec<ChartEntry> = serde_yaml::from_value(entries.clone())?;
+        assert_eq!(entries[0].digest, "abc123");
+        assert_eq!(
+            entries[0]
+                .digests
+                .as_ref()
+                .unwrap()
+                .get("manifest")
+                .unwrap(),
+            "0123abcd"
+        );
+
+        parameters.digests = Some(BTreeMap::from([(
+            "manifest".to_string(),
+            "sha256:xyz".to_string(),
+        )]));
+        assert!(update_yaml(
+            file_path,
+            &constants,
+            &parameters,
+            &UpdateOptions::default(),
+            &mut Warnings::default()
+        )
+        .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_update_yaml_collects_warnings() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_file = NamedTempFile::new()?;
+        let file_path = temp_file.path().to_str().unwrap();
+
+        let mut constants = create_test_constants();
+        constants.keywords.push("test".to_string());
+        let mut parameters = create_test_parameters();
+        parameters
+            .urls
+            .push("example.com/test-chart-0.1.0.tgz".to_string());
+
+        let mut warnings = Warnings::default();
+        update_yaml(
+            file_path,
+            &constants,
+            &parameters,
+            &UpdateOptions::default(),
+            &mut warnings,
+        )?;
+        assert_eq!(warnings.len(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_update_yaml_strip_prerelease() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_file = NamedTempFile::new()?;
+        let file_path = temp_file.path().to_str().unwrap();
+
+        let constants = create_test_constants();
+        let mut parameters = create_test_parameters();
+        parameters.version = "1.2.3-ci.45+abc".to_string();
+        parameters.urls = vec!["https://example.com/test-chart-1.2.3-ci.45.tgz".to_string()];
+        let options = UpdateOptions {
+            strip_prerelease: true,
+            ..Default::default()
+        };
+
+        let mut warnings = Warnings::default();
+        let updated_yaml =
+            update_yaml(file_path, &constants, &parameters, &options, &mut warnings)?;
+        let parsed: ChartYaml = serde_yaml::from_str(&updated_yaml)?;
+        let entries = parsed
+            .entries
+            .get(Value::String("test-chart".to_string()))
+            .unwrap();
+        let entries: Vec<ChartEntry> = serde_yaml::from_value(entries.clone())?;
+        assert_eq!(entries[0].version, "1.2.3");
+        assert_eq!(warnings.len(), 1);
+
+        parameters.version = "not-a-version".to_string();
+        assert!(update_yaml(file_path, &constants, &parameters, &options, &mut warnings).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_add_outcome_to_json() {
+        let outcome = AddOutcome {
+            chart: "test-chart".to_string(),
+            version: "0.3.0".to_string(),
+            total_versions: 2,
+            pruned: vec!["0.1.0".to_string()],
+            changed: true,
+        };
+        assert_eq!(
+            outcome.to_json(),
+            json!({
+                "action": "add",
+                "chart": "test-chart",
+                "version": "0.3.0",
+                "changed": true,
+                "total_versions": 2,
+                "pruned": ["0.1.0"],
+            })
+        );
+    }
+
+    #[test]
+    fn test_add_entry_max_entries() -> Result<(), Box<dyn std::error::Error>> {
+        let mut data = ChartYaml::default();
+        let constants = create_test_constants();
+        let mut parameters = create_test_parameters();
+        let mut options = UpdateOptions {
+            max_entries: Some(2),
+            ..Default::default()
+        };
+
+        for version in ["0.2.0", "0.1.0"] {
+            parameters.version = version.to_string();
+            add_entry(
+                &mut data,
+                &constants,
+                &parameters,
+                &options,
+                &mut Warnings::default(),
+            )?;
+        }
+
+        parameters.version = "0.3.0".to_string();
+        assert!(add_entry(
+            &mut data,
+            &constants,
+            &parameters,
+            &options,
+            &mut Warnings::default()
+        )
+        .is_err());
+
+        options.auto_prune = true;
+        let outcome = add_entry(
+            &mut data,
+            &constants,
+            &parameters,
+            &options,
+            &mut Warnings::default(),
+        )?;
+        assert_eq!(outcome.total_versions, 2);
+        assert_eq!(outcome.pruned, vec!["0.1.0".to_string()]);
+
+        // Inserted below both kept versions, it would be the one pruned
+        parameters.version = "0.0.1".to_string();
+        let error = add_entry(
+            &mut data,
+            &constants,
+            &parameters,
+            &options,
+            &mut Warnings::default(),
+        )
+        .unwrap_err();
+        assert!(error.to_string().contains("would be pruned right away"));
+        assert_eq!(chart_versions(&data, "test-chart"), ["0.2.0", "0.3.0"]);
+
+        // Between them, the lowest existing version goes instead
+        parameters.version = "0.2.5".to_string();
+        let outcome = add_entry(
+            &mut data,
+            &constants,
+            &parameters,
+            &options,
+            &mut Warnings::default(),
+        )?;
+        assert_eq!(outcome.pruned, vec!["0.2.0".to_string()]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_input_sniffs_json() -> Result<(), Box<dyn std::error::Error>> {
+        // Surrogate pair escapes, as Python's json.dumps writes for emoji, are
+        // valid JSON but not YAML
+        let json = r#"{"apiVersion": "v1", "entries": {"a": [{"description": "\ud83d\ude00"}]}}"#;
+        let (data, format) = parse_index_as(json, None, false, &mut Warnings::default())?;
+        assert_eq!(format, InputFormat::Json);
+        assert!(data.entries.contains_key("a"));
+        assert!(parse_index_as(
+            json,
+            Some(InputFormat::Yaml),
+            false,
+            &mut Warnings::default()
+        )
+        .is_err());
+
+        let (_, format): (Value, _) = parse_input("name: web\n", None)?;
+        assert_eq!(format, InputFormat::Yaml);
+        let (value, format): (Value, _) =
+            parse_input(r#"{"name": "web"}"#, Some(InputFormat::Json))?;
+        assert_eq!(
+            (value["name"].as_str(), format),
+            (Some("web"), InputFormat::Json)
+        );
+        assert!(parse_input::<Value>("name: [", None).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_index_duplicate_keys() -> Result<(), Box<dyn std::error::Error>> {
+        let contents = r#"
+apiVersion: v1
+entries:
+  test-chart:
+    - version: 0.1.0
+  other-chart:
+    - version: 1.0.0
+  test-chart:
+    - version: 0.2.0
+"#;
+
+        let err = parse_index(contents, false, &mut Warnings::default()).unwrap_err();
+        assert!(err.to_string().contains("'test-chart'"));
+
+        let mut warnings = Warnings::default();
+        let parsed = parse_index(contents, true, &mut warnings)?;
+        assert_eq!(parsed.entries.len(), 2);
+        let entries = parsed
+            .entries
+            .get(Value::String("test-chart".to_string()))
+            .and_then(Value::as_sequence)
+            .unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(warnings.len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_add_entry_sign_key_annotation() -> Result<(), Box<dyn std::error::Error>> {
+        let mut data = ChartYaml::default();
+        let mut constants = create_test_constants();
+        constants.sign_key = Some(SignKey {
+            fingerprint: "C874011F0AB405110D02105534365D9472D7468F".to_string(),
+            url: "https://keybase.io/hashicorp/pgp_keys.asc".to_string(),
+        });
+        let parameters = create_test_parameters();
+
+        add_entry(
+            &mut data,
+            &constants,
+            &parameters,
+            &UpdateOptions::default(),
+            &mut Warnings::default(),
+        )?;
+        let entries = data
+            .entries
+            .get(Value::String("test-chart".to_string()))
+            .unwrap();
+        let entries: Vec<ChartEntry> = serde_yaml::from_value(entries.clone())?;
+        let sign_key = &entries[0].annotations.as_ref().unwrap()["artifacthub.io/signKey"];
+        assert_eq!(
+            sign_key,
+            r#"{"fingerprint":"C874011F0AB405110D02105534365D9472D7468F","url":"https://keybase.io/hashicorp/pgp_keys.asc"}"#
+        );
+
+        constants.sign_key.as_mut().unwrap().fingerprint = "not-hex".to_string();
+        assert!(add_entry(
+            &mut data,
+            &constants,
+            &parameters,
+            &UpdateOptions::default(),
+            &mut Warnings::default(),
+        )
+        .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_sort_versions() -> Result<(), Box<dyn std::error::Error>> {
+        let mut entries: Vec<Value> = serde_yaml::from_str(
+            r#"
+- version: 0.2.0
+- version: latest
+- version: 1.0.0
+- version: 0.10.0
+"#,
+        )?;
+        let versions = |entries: &[Value]| -> Vec<String> {
+            entries
+                .iter()
+                .map(|entry| entry["version"].as_str().unwrap().to_string())
+                .collect()
+        };
+
+        let unparseable = sort_versions(&mut entries, false);
+        assert_eq!(versions(&entries), ["1.0.0", "0.10.0", "0.2.0", "latest"]);
+        assert_eq!(unparseable, ["latest"]);
+
+        sort_versions(&mut entries, true);
+        assert_eq!(versions(&entries), ["0.2.0", "0.10.0", "1.0.0", "latest"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_add_entry_assume_sorted_falls_back_to_sort() -> Result<(), Box<dyn std::error::Error>> {
+        let mut data: ChartYaml = serde_yaml::from_str(
+            "apiVersion: v1\nentries:\n  test-chart:\n  - version: 0.1.0\n  - version: 0.3.0\n",
+        )?;
+        let mut parameters = create_test_parameters();
+        parameters.version = "0.2.0".to_string();
+        let options = UpdateOptions {
+            sort_versions: true,
+            assume_sorted: true,
+            ..Default::default()
+        };
+
+        let mut warnings = Warnings::default();
+        add_entry(
+            &mut data,
+            &create_test_constants(),
+            &parameters,
+            &options,
+            &mut warnings,
+        )?;
+        assert_eq!(
+            chart_versions(&data, "test-chart"),
+            ["0.3.0", "0.2.0", "0.1.0"]
+        );
+        assert!(warnings
+            .messages
+            .iter()
+            .any(|warning| warning.contains("aren't sorted newest first")));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_insert_sorted() -> Result<(), Box<dyn std::error::Error>> {
+        let mut entries: Vec<Value> =
+            serde_yaml::from_str("[{version: 1.0.0}, {version: 0.2.0}, {version: latest}]")?;
+        let entry = |version: &str| serde_yaml::from_str::<Value>(&format!("version: {}", version));
+
+        assert!(insert_sorted(&mut entries, entry("0.10.0")?).is_ok());
+        assert!(insert_sorted(&mut entries, entry("2.0.0")?).is_ok());
+        let versions: Vec<_> = entries.iter().map(|e| e["version"].as_str()).collect();
+        assert_eq!(
+            versions,
+            [
+                Some("2.0.0"),
+                Some("1.0.0"),
+                Some("0.10.0"),
+                Some("0.2.0"),
+                Some("latest")
+            ]
+        );
+
+        assert!(is_sorted_newest_first(&entries));
+        assert!(insert_sorted(&mut entries, entry("latest")?).is_err());
+
+        let unsorted: Vec<Value> = serde_yaml::from_str("[{version: 0.1.0}, {version: 0.2.0}]")?;
+        assert!(!is_sorted_newest_first(&unsorted));
+        let unparseable_first: Vec<Value> =
+            serde_yaml::from_str("[{version: latest}, {version: 0.2.0}]")?;
+        assert!(!is_sorted_newest_first(&unparseable_first));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge_values_over_default_constants() -> Result<(), Box<dyn std::error::Error>> {
+        let mut merged = serde_yaml::to_value(Constants::default())?;
+        merge_values(
+            &mut merged,
+            serde_yaml::from_str(
+                r#"
+home: https://charts.acme.io
+maintainers:
+  - name: Platform Team
+    email: platform@acme.io
+    url: https://acme.io
+"#,
+            )?,
+        );
+        let constants: Constants = serde_yaml::from_value(merged)?;
+
+        assert_eq!(constants.home, "https://charts.acme.io");
+        assert_eq!(constants.maintainers.len(), 1);
+        assert_eq!(constants.maintainers[0].name, "Platform Team");
+        assert_eq!(constants.icon, Constants::default().icon);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_layer_constants() -> Result<(), Box<dyn std::error::Error>> {
+        let org = serde_yaml::to_value(Constants {
+            keywords: vec!["acme".to_string()],
+            ..Constants::default()
+        })?;
+        let team: Value =
+            serde_yaml::from_str("home: https://platform.acme.io\nkeywords: [platform]\n")?;
+        let charts: Value = serde_yaml::from_str(
+            "- name: dns\n- name: ingress\n  home: https://ingress.acme.io\n",
+        )?;
+
+        let profiles = layer_constants(vec![org.clone(), team.clone(), charts.clone()], false)?;
+        assert_eq!(profiles.len(), 2);
+        assert_eq!(profiles[0].name, "dns");
+        assert_eq!(profiles[0].home, "https://platform.acme.io");
+        assert_eq!(profiles[0].keywords, ["platform"]);
+        assert_eq!(profiles[1].home, "https://ingress.acme.io");
+        assert_eq!(profiles[1].icon, Constants::default().icon);
+
+        let merged = layer_constants(vec![org.clone(), team.clone()], true)?;
+        assert_eq!(merged[0].keywords, ["acme", "platform"]);
+
+        assert!(layer_constants(vec![org, charts, team], false).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_add_entry_sort_maintainers() -> Result<(), Box<dyn std::error::Error>> {
+        let mut data = ChartYaml::default();
+        let mut constants = create_test_constants();
+        let maintainer = |name: &str, email: &str| Maintainer {
+            email: email.to_string(),
+            name: name.to_string(),
+            url: String::new(),
+        };
+        constants.maintainers = vec![
+            maintainer("Zed", "zed@example.com"),
+            maintainer("Amy", "b@example.com"),
+            maintainer("Amy", "a@example.com"),
+        ];
+        let options = UpdateOptions {
+            sort_maintainers: true,
+            ..Default::default()
+        };
+
+        add_entry(
+            &mut data,
+            &constants,
+            &create_test_parameters(),
+            &options,
+            &mut Warnings::default(),
+        )?;
+        let entries = data
+            .entries
+            .get(Value::String("test-chart".to_string()))
+            .unwrap();
+        let entries: Vec<ChartEntry> = serde_yaml::from_value(entries.clone())?;
+        let emails: Vec<&str> = entries[0]
+            .maintainers
+            .iter()
+            .map(|m| m.email.as_str())
+            .collect();
+        assert_eq!(
+            emails,
+            ["a@example.com", "b@example.com", "zed@example.com"]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_add_entry_prepend() -> Result<(), Box<dyn std::error::Error>> {
+        let mut data = ChartYaml::default();
+        let constants = create_test_constants();
+        let mut parameters = create_test_parameters();
+        let options = UpdateOptions {
+            prepend: true,
+            ..Default::default()
+        };
+
+        let mut warnings = Warnings::default();
+        for version in ["0.1.0", "0.2.0"] {
+            parameters.version = version.to_string();
+            add_entry(&mut data, &constants, &parameters, &options, &mut warnings)?;
+        }
+        // Both versions were added with the same test digest
+        assert!(warnings
+            .messages
+            .iter()
+            .any(|m| m.contains("0.2.0 is already used by version 0.1.0")));
+        let entries = data
+            .entries
+            .get(Value::String("test-chart".to_string()))
+            .and_then(Value::as_sequence)
+            .unwrap();
+        assert_eq!(entries[0]["version"].as_str(), Some("0.2.0"));
+        assert_eq!(entries[1]["version"].as_str(), Some("0.1.0"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_update_yaml_compact_urls() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_file = NamedTempFile::new()?;
+        let mut parameters = create_test_parameters();
+        parameters.urls = vec![
+            "https://example.com/charts/test-chart-0.1.0.tgz/".to_string(),
+            "https://example.com/%63harts/test-chart-0.1.0.tgz".to_string(),
+            "https://mirror.example.com/test%2fchart-0.1.0.tgz".to_string(),
+        ];
+        let options = UpdateOptions {
+            compact_urls: true,
+            ..Default::default()
+        };
+
+        let updated_yaml = update_yaml(
+            temp_file.path().to_str().unwrap(),
+            &create_test_constants(),
+            &parameters,
+            &options,
+            &mut Warnings::default(),
+        )?;
+        let data: ChartYaml = serde_yaml::from_str(&updated_yaml)?;
+        let entry = &data.entries["test-chart"][0];
+        assert_eq!(
+            entry["urls"],
+            serde_yaml::from_str::<Value>(
+                "[https://example.com/charts/test-chart-0.1.0.tgz, https://mirror.example.com/test%2Fchart-0.1.0.tgz]"
+            )?
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_add_entry_annotations() -> Result<(), Box<dyn std::error::Error>> {
+        let single =
+            |key: &str, value: &str| Some(BTreeMap::from([(key.to_string(), value.to_string())]));
+        let mut constants = create_test_constants();
+        constants.annotations = single("artifacthub.io/license", "MIT");
+        let mut parameters = create_test_parameters();
+        parameters.annotations = single("artifacthub.io/prerelease", "false");
+
+        let mut data = ChartYaml::default();
+        add_entry(
+            &mut data,
+            &constants,
+            &parameters,
+            &UpdateOptions::default(),
+            &mut Warnings::default(),
+        )?;
+        let annotations = &data.entries["test-chart"][0]["annotations"];
+        assert!(annotations["artifacthub.io/license"].is_null());
+        assert_eq!(
+            annotations["artifacthub.io/prerelease"].as_str(),
+            Some("false")
+        );
+
+        parameters.version = "0.2.0".to_string();
+        let options = UpdateOptions {
+            merge_annotations: true,
+            ..UpdateOptions::default()
+        };
+        add_entry(
+            &mut data,
+            &constants,
+            &parameters,
+            &options,
+            &mut Warnings::default(),
+        )?;
+        let annotations = &data.entries["test-chart"][1]["annotations"];
+        assert_eq!(annotations["artifacthub.io/license"].as_str(), Some("MIT"));
+        assert_eq!(
+            annotations["artifacthub.io/prerelease"].as_str(),
+            Some("false")
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_add_entry_size() -> Result<(), Box<dyn std::error::Error>> {
+        let mut data = ChartYaml::default();
+        let mut parameters = create_test_parameters();
+        add_entry(
+            &mut data,
+            &create_test_constants(),
+            &parameters,
+            &UpdateOptions::default(),
+            &mut Warnings::default(),
+        )?;
+        assert!(data.entries["test-chart"][0]["annotations"][SIZE_ANNOTATION].is_null());
+
+        parameters.version = "0.2.0".to_string();
+        parameters.size = Some(2048);
+        add_entry(
+            &mut data,
+            &create_test_constants(),
+            &parameters,
+            &UpdateOptions::default(),
+            &mut Warnings::default(),
+        )?;
+        assert_eq!(
+            data.entries["test-chart"][1]["annotations"][SIZE_ANNOTATION].as_str(),
+            Some("2048")
+        );
+
+        parameters.version = "0.3.0".to_string();
+        parameters.size = Some(0);
+        assert!(add_entry(
+            &mut data,
+            &create_test_constants(),
+            &parameters,
+            &UpdateOptions::default(),
+            &mut Warnings::default(),
+        )
+        .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_add_entry_minimal_constants_writes_no_nulls() -> Result<(), Box<dyn std::error::Error>>
+    {
+        let constants: Constants = serde_yaml::from_str(
+            "apiVersion: v2\nappVersion: ''\ndescription: ''\nhome: ''\nicon: ''\n\
+             keywords: []\nmaintainers: []\nname: test-chart\nsources: []\ntype: application\n",
+        )?;
+        let mut data = ChartYaml::default();
+        add_entry(
+            &mut data,
+            &constants,
+            &create_test_parameters(),
+            &UpdateOptions::default(),
+            &mut Warnings::default(),
+        )?;
+
+        for yaml in [index_to_string(&data)?, writer::to_string(&data)?] {
+            assert!(
+                !yaml
+                    .split_whitespace()
+                    .any(|token| token == "null" || token == "~"),
+                "{}",
+                yaml
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_add_entry_url_integrity() -> Result<(), Box<dyn std::error::Error>> {
+        let mut data = ChartYaml::default();
+        let mut parameters = create_test_parameters();
+        parameters.url_integrity =
+            Some(vec!["sha384-oqVuAfXRKap7fdgcCY5uykM6+R9GqQ8K".to_string()]);
+
+        add_entry(
+            &mut data,
+            &create_test_constants(),
+            &parameters,
+            &UpdateOptions::default(),
+            &mut Warnings::default(),
+        )?;
+        let entry = &data.entries["test-chart"][0];
+        assert_eq!(
+            entry["annotations"][URL_INTEGRITY_ANNOTATION].as_str(),
+            Some(
+                r#"{"https://example.com/test-chart-0.1.0.tgz":"sha384-oqVuAfXRKap7fdgcCY5uykM6+R9GqQ8K"}"#
+            )
+        );
+
+        // One hash per URL is required
+        parameters.version = "0.2.0".to_string();
+        parameters
+            .urls
+            .push("https://mirror.example.com/test-chart-0.2.0.tgz".to_string());
+        let result = add_entry(
+            &mut data,
+            &create_test_constants(),
+            &parameters,
+            &UpdateOptions::default(),
+            &mut Warnings::default(),
+        );
+        assert!(result.is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_clean_description() {
+        let description = "# My chart\n\nA **fast** `cache` for\n[Kubernetes](https://k8s.io).\n";
+        assert_eq!(
+            clean_description(description, &UpdateOptions::default()),
+            description
+        );
+
+        let options = UpdateOptions {
+            trim_description: true,
+            strip_markdown: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            clean_description(description, &options),
+            "My chart A fast cache for Kubernetes."
+        );
+
+        let options = UpdateOptions {
+            trim_description: true,
+            max_description_length: Some(13),
+            ..Default::default()
+        };
+        assert_eq!(
+            clean_description("A   fast cache for K8s", &options),
+            "A fast cache…"
+        );
+    }
+
+    #[test]
+    fn test_add_entry_empty_app_version() -> Result<(), Box<dyn std::error::Error>> {
+        let mut data = ChartYaml::default();
+        let constants = Constants {
+            app_version: String::new(),
+            ..create_test_constants()
+        };
+        let parameters = Parameters {
+            app_version: None,
+            ..create_test_parameters()
+        };
+        let mut warnings = Warnings::default();
+
+        let options = UpdateOptions {
+            require_app_version: true,
+            ..Default::default()
+        };
+        let result = add_entry(&mut data, &constants, &parameters, &options, &mut warnings);
+        assert!(result.is_err());
+        assert!(data.entries.is_empty());
+
+        add_entry(
+            &mut data,
+            &constants,
+            &parameters,
+            &UpdateOptions::default(),
+            &mut warnings,
+        )?;
+        assert_eq!(
+            warnings.messages,
+            ["test-chart 0.1.0 has an empty app version"]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_url_host() {
+        assert_eq!(
+            url_host("https://charts.example.com/a.tgz"),
+            Some("charts.example.com")
+        );
+        assert_eq!(
+            url_host("https://u:p@Mirror.example.com:8443"),
+            Some("Mirror.example.com")
+        );
+        assert_eq!(
+            url_host("oci://registry.example.com/charts/a"),
+            Some("registry.example.com")
+        );
+        assert_eq!(url_host("http://[::1]:8080/a.tgz"), Some("::1"));
+        assert_eq!(url_host("charts/a.tgz"), None);
+
+        let allowed = ["mirror.example.com".to_string()];
+        assert!(host_allowed(
+            "https://u:p@Mirror.example.com:8443/a.tgz",
+            &allowed
+        ));
+        assert!(host_allowed("charts/a.tgz", &allowed));
+        assert!(!host_allowed("https://evil.example.com/a.tgz", &allowed));
+    }
+
+    #[test]
+    fn test_sort_charts() -> Result<(), Box<dyn std::error::Error>> {
+        let mut data = ChartYaml::default();
+        for name in ["web", "api", "db"] {
+            let constants = Constants {
+                name: name.to_string(),
+                ..create_test_constants()
+            };
+            add_entry(
+                &mut data,
+                &constants,
+                &create_test_parameters(),
+                &UpdateOptions::default(),
+                &mut Warnings::default(),
+            )?;
+        }
+
+        sort_charts(&mut data);
+        let names: Vec<_> = data.entries.keys().filter_map(Value::as_str).collect();
+        assert_eq!(names, ["api", "db", "web"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_add_entry_require_type() -> Result<(), Box<dyn std::error::Error>> {
+        let mut data = ChartYaml::default();
+        let constants = Constants {
+            entry_type: " ".to_string(),
+            ..create_test_constants()
+        };
+        let options = UpdateOptions {
+            require_type: true,
+            ..Default::default()
+        };
+        let error = add_entry(
+            &mut data,
+            &constants,
+            &create_test_parameters(),
+            &options,
+            &mut Warnings::default(),
+        )
+        .unwrap_err();
+        assert_eq!(error.to_string(), "test-chart 0.1.0 has an empty type");
+
+        add_entry(
+            &mut data,
+            &create_test_constants(),
+            &create_test_parameters(),
+            &options,
+            &mut Warnings::default(),
+        )?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_canonicalize() -> Result<(), Box<dyn std::error::Error>> {
+        let mut data = parse_index(
+            "apiVersion: v1
+entries:
+  zeta:
+  - name: zeta
+    version: 0.1.0
+  alpha:
+  - name: alpha
+    version: 0.1.0
+    keywords: [' Cache', cache, DB]
+    urls: [https://b.example.com/a.tgz, https://a.example.com/a.tgz]
+    maintainers:
+    - name: Zoe
+    - name: Ann
+      email: b@example.com
+    - name: Ann
+      email: a@example.com
+  - name: alpha
+    version: 0.2.0
+  - name: alpha
+    version: latest
+",
+            false,
+            &mut Warnings::default(),
+        )?;
+
+        assert_eq!(canonicalize(&mut data), ["alpha@latest"]);
+        let names: Vec<_> = data.entries.keys().filter_map(Value::as_str).collect();
+        assert_eq!(names, ["alpha", "zeta"]);
+        let alpha = data.entries["alpha"].as_sequence().unwrap();
+        let versions: Vec<_> = alpha.iter().map(|e| e["version"].as_str()).collect();
+        assert_eq!(versions, [Some("0.2.0"), Some("0.1.0"), Some("latest")]);
+        let entry = &alpha[1];
+        assert_eq!(
+            entry["keywords"],
+            serde_yaml::from_str::<Value>("[cache, db]")?
+        );
+        assert_eq!(
+            entry["urls"][0].as_str(),
+            Some("https://a.example.com/a.tgz")
+        );
+        let emails: Vec<_> = entry["maintainers"]
+            .as_sequence()
+            .unwrap()
+            .iter()
+            .map(|m| m.get("email").and_then(Value::as_str))
+            .collect();
+        assert_eq!(emails, [Some("a@example.com"), Some("b@example.com"), None]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_add_entry_sort_urls() -> Result<(), Box<dyn std::error::Error>> {
+        let constants = create_test_constants();
+        let parameters = Parameters {
+            urls: vec![
+                "https://mirror-b.example.com/test-chart-0.1.0.tgz".to_string(),
+                "https://example.com/test-chart-0.1.0.tgz".to_string(),
+                "https://mirror-a.example.com/test-chart-0.1.0.tgz".to_string(),
+            ],
+            ..create_test_parameters()
+        };
+        let mut warnings = Warnings::default();
+        let urls = |options: &UpdateOptions| -> Result<Vec<String>, Box<dyn std::error::Error>> {
+            let mut data = ChartYaml::default();
+            add_entry(
+                &mut data,
+                &constants,
+                &parameters,
+                options,
+                &mut Warnings::default(),
+            )?;
+            Ok(serde_yaml::from_value(
+                data.entries["test-chart"][0]["urls"].clone(),
+            )?)
+        };
+
+        let options = UpdateOptions {
+            sort_urls: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            urls(&options)?,
+            [
+                "https://example.com/test-chart-0.1.0.tgz",
+                "https://mirror-a.example.com/test-chart-0.1.0.tgz",
+                "https://mirror-b.example.com/test-chart-0.1.0.tgz",
+            ]
+        );
+
+        let options = UpdateOptions {
+            sort_urls: true,
+            primary_url: Some("https://mirror-b.example.com/test-chart-0.1.0.tgz".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            urls(&options)?[0],
+            "https://mirror-b.example.com/test-chart-0.1.0.tgz"
+        );
+
+        let options = UpdateOptions {
+            primary_url: Some("https://elsewhere.example.com/x.tgz".to_string()),
+            ..Default::default()
+        };
+        assert!(add_entry(
+            &mut ChartYaml::default(),
+            &constants,
+            &parameters,
+            &options,
+            &mut warnings
+        )
+        .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_add_entry_max_urls() -> Result<(), Box<dyn std::error::Error>> {
+        let mut data = ChartYaml::default();
+        let constants = create_test_constants();
+        let parameters = Parameters {
+            urls: (0..5)
+                .map(|i| format!("https://mirror-{}.example.com/test-chart-0.1.0.tgz", i))
+                .collect(),
+            ..create_test_parameters()
+        };
+        let mut warnings = Warnings::default();
+
+        let mut options = UpdateOptions {
+            max_urls: Some(2),
+            ..Default::default()
+        };
+        let error = add_entry(&mut data, &constants, &parameters, &options, &mut warnings)
+            .unwrap_err()
+            .to_string();
+        assert_eq!(error, "test-chart 0.1.0 has 5 URLs, more than --max-urls 2");
+
+        options.truncate_urls = true;
+        add_entry(&mut data, &constants, &parameters, &options, &mut warnings)?;
+        let urls = data.entries["test-chart"][0]["urls"].as_sequence().unwrap();
+        assert_eq!(urls.len(), 2);
+        assert_eq!(
+            urls[0].as_str(),
+            Some("https://mirror-0.example.com/test-chart-0.1.0.tgz")
+        );
+        assert_eq!(warnings.len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_normalize_version() -> Result<(), Box<dyn std::error::Error>> {
+        let mut data = ChartYaml::default();
+        let constants = create_test_constants();
+        let parameters = Parameters {
+            version: "v0.1.0".to_string(),
+            ..create_test_parameters()
+        };
+        let mut warnings = Warnings::default();
+
+        assert!(add_entry(
+            &mut data,
+            &constants,
+            &parameters,
+            &UpdateOptions::default(),
+            &mut warnings
+        )
+        .is_err());
+
+        let options = UpdateOptions {
+            normalize_version: true,
+            ..Default::default()
+        };
+        add_entry(&mut data, &constants, &parameters, &options, &mut warnings)?;
+        assert_eq!(chart_versions(&data, "test-chart"), ["0.1.0"]);
+        assert_eq!(
+            warnings.messages,
+            ["Rewrote version 'v0.1.0' to '0.1.0' (--normalize-version)"]
+        );
+
+        let mut entries: Vec<Value> =
+            serde_yaml::from_str("[{version: v1.0.0}, {version: 2.0.0}, {version: vnext}]")?;
+        assert_eq!(normalize_entry_versions(&mut entries), ["v1.0.0"]);
+        assert_eq!(entries[0]["version"].as_str(), Some("1.0.0"));
+        assert_eq!(entries[2]["version"].as_str(), Some("vnext"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_expand_url_template() -> Result<(), Box<dyn std::error::Error>> {
+        let values = [
+            ("name", "mychart"),
+            ("version", "1.2.3"),
+            ("app_version", ""),
+        ];
+        assert_eq!(
+            expand_url_template("https://example.com/{name}-{version}.tgz", &values)?,
+            "https://example.com/mychart-1.2.3.tgz"
+        );
+
+        let error = expand_url_template("https://example.com/{name}-{app_version}.tgz", &values)
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("{app_version}"), "{}", error);
+        assert!(expand_url_template("https://example.com/{chart}.tgz", &values).is_err());
+        assert!(expand_url_template("https://example.com/{name", &values).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_chart_shard() -> Result<(), Box<dyn std::error::Error>> {
+        let data = parse_index(
+            "apiVersion: v1\nentries:\n  a:\n  - version: 0.1.0\n  b:\n  - version: 0.2.0\ngenerated: now\n",
+            false,
+            &mut Warnings::default(),
+        )?;
+
+        assert_eq!(
+            index_to_string(&chart_shard(&data, "b"))?,
+            "apiVersion: v1\nentries:\n  b:\n  - version: 0.2.0\n"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_gzip_is_deterministic() -> Result<(), Box<dyn std::error::Error>> {
+        let index = "apiVersion: v1\nentries: {}\n";
+        let compressed = gzip(index)?;
+        assert_eq!(compressed, gzip(index)?);
+        assert_eq!(compressed[4..8], [0, 0, 0, 0]);
+
+        let mut decompressed = String::new();
+        flate2::read::GzDecoder::new(compressed.as_slice()).read_to_string(&mut decompressed)?;
+        assert_eq!(decompressed, index);
+        assert_eq!(
+            gzip_path(Path::new("index.yaml")),
+            Path::new("index.yaml.gz")
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_add_entry_set_fields() -> Result<(), Box<dyn std::error::Error>> {
+        let mut data = ChartYaml::default();
+        let options = UpdateOptions {
+            set_fields: vec![
+                ("kubeVersion".to_string(), Value::from(">=1.28.0")),
+                ("annotations.team".to_string(), Value::from("cache")),
+                (
+                    "annotations.example\\.com/tier".to_string(),
+                    Value::from("1"),
+                ),
+                ("deprecated".to_string(), Value::from(true)),
+            ],
+            ..Default::default()
+        };
+        add_entry(
+            &mut data,
+            &create_test_constants(),
+            &create_test_parameters(),
+            &options,
+            &mut Warnings::default(),
+        )?;
+
+        let entry = &data.entries["test-chart"][0];
+        assert_eq!(entry["kubeVersion"].as_str(), Some(">=1.28.0"));
+        assert_eq!(entry["annotations"]["team"].as_str(), Some("cache"));
+        assert_eq!(entry["annotations"]["example.com/tier"].as_str(), Some("1"));
+        assert_eq!(entry["deprecated"].as_bool(), Some(true));
+
+        let mut base = Value::from("scalar");
+        assert!(set_path(&mut base, "a.b", Value::Null).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_add_entry_mixed_url_schemes() -> Result<(), Box<dyn std::error::Error>> {
+        let constants = create_test_constants();
+        let parameters = Parameters {
+            urls: vec![
+                "https://example.com/test-chart-0.1.0.tgz".to_string(),
+                "http://mirror.example.com/test-chart-0.1.0.tgz".to_string(),
+                "oci://registry.example.com/test-chart:0.1.0".to_string(),
+            ],
+            ..create_test_parameters()
+        };
+        let mut warnings = Warnings::default();
+
+        add_entry(
+            &mut ChartYaml::default(),
+            &constants,
+            &parameters,
+            &UpdateOptions::default(),
+            &mut warnings,
+        )?;
+        assert!(warnings
+            .messages
+            .iter()
+            .any(|w| w.starts_with("URLs of test-chart 0.1.0 mix http and oci schemes")));
+
+        let options = UpdateOptions {
+            strict_urls: true,
+            ..Default::default()
+        };
+        let result = add_entry(
+            &mut ChartYaml::default(),
+            &constants,
+            &parameters,
+            &options,
+            &mut warnings,
+        );
+        assert!(result.is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parameters_labelled_urls() -> Result<(), Box<dyn std::error::Error>> {
+        let parameters: Parameters = serde_yaml::from_str(
+            r#"
+digest: abc123
+version: 0.1.0
+urls:
+  - https://example.com/test-chart-0.1.0.tgz
+  - url: https://eu.example.com/test-chart-0.1.0.tgz
+    name: EU mirror
+"#,
+        )?;
+        assert_eq!(
+            parameters.urls,
+            [
+                "https://example.com/test-chart-0.1.0.tgz",
+                "https://eu.example.com/test-chart-0.1.0.tgz",
+            ]
+        );
+
+        let mut data = ChartYaml::default();
+        add_entry(
+            &mut data,
+            &create_test_constants(),
+            &parameters,
+            &UpdateOptions::default(),
+            &mut Warnings::default(),
+        )?;
+        let entry = &data.entries["test-chart"][0];
+        assert_eq!(
+            entry["urls"][1].as_str(),
+            parameters.urls.get(1).map(String::as_str)
+        );
+        assert_eq!(
+            entry["annotations"][URL_LABELS_ANNOTATION].as_str(),
+            Some(r#"{"https://eu.example.com/test-chart-0.1.0.tgz":"EU mirror"}"#)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_add_entry_idempotent() -> Result<(), Box<dyn std::error::Error>> {
+        let mut data = ChartYaml::default();
+        let constants = create_test_constants();
+        let mut parameters = create_test_parameters();
+        let mut warnings = Warnings::default();
+        let options = UpdateOptions {
+            idempotent: true,
+            ..Default::default()
+        };
+
+        add_entry(&mut data, &constants, &parameters, &options, &mut warnings)?;
+        let outcome = add_entry(&mut data, &constants, &parameters, &options, &mut warnings)?;
+        assert!(!outcome.changed);
+        assert_eq!(chart_versions(&data, "test-chart"), ["0.1.0"]);
+
+        // Without --idempotent a re-run is still rejected
+        let result = add_entry(
+            &mut data,
+            &constants,
+            &parameters,
+            &UpdateOptions::default(),
+            &mut warnings,
+        );
+        assert!(result.is_err());
+
+        parameters.digest = "def456".to_string();
+        let result = add_entry(&mut data, &constants, &parameters, &options, &mut warnings);
+        assert!(result.unwrap_err().to_string().contains("different digest"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_add_entry_digest_prefix() -> Result<(), Box<dyn std::error::Error>> {
+        let mut data = ChartYaml::default();
+        let constants = create_test_constants();
+        let mut parameters = create_test_parameters();
+        parameters.digest = "sha256:abc123".to_string();
+        let mut warnings = Warnings::default();
+
+        add_entry(
+            &mut data,
+            &constants,
+            &parameters,
+            &UpdateOptions::default(),
+            &mut warnings,
+        )?;
+        assert_eq!(
+            data.entries["test-chart"][0]["digest"].as_str(),
+            Some("abc123")
+        );
+
+        // A prefixed re-run matches the bare digest already recorded
+        let options = UpdateOptions {
+            idempotent: true,
+            digest_prefix: true,
+            ..Default::default()
+        };
+        let outcome = add_entry(&mut data, &constants, &parameters, &options, &mut warnings)?;
+        assert!(!outcome.changed);
+
+        parameters.version = "0.2.0".to_string();
+        add_entry(&mut data, &constants, &parameters, &options, &mut warnings)?;
+        assert_eq!(
+            data.entries["test-chart"][1]["digest"].as_str(),
+            Some("sha256:abc123")
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_pair_inputs() -> Result<(), Box<dyn std::error::Error>> {
+        let first = create_test_constants();
+        let second = Constants {
+            name: "other-chart".to_string(),
+            ..create_test_constants()
+        };
+        let mut parameters = create_test_parameters();
+
+        // A single profile is used whether or not the parameters name a chart
+        let inputs = pair_inputs(std::slice::from_ref(&first), vec![create_test_parameters()])?;
+        assert_eq!(inputs[0].0.name, "test-chart");
+
+        let profiles = [first, second];
+        assert!(pair_inputs(&profiles, vec![create_test_parameters()]).is_err());
+
+        parameters.chart = Some("other-chart".to_string());
+        let inputs = pair_inputs(&profiles, vec![parameters])?;
+        assert_eq!(inputs[0].0.name, "other-chart");
+
+        let mut parameters = create_test_parameters();
+        parameters.chart = Some("missing".to_string());
+        assert!(pair_inputs(&profiles, vec![parameters]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_update_yaml_preserves_existing_created() -> Result<(), Box<dyn std::error::Error>> {
+        let mut temp_file = NamedTempFile::new()?;
+        let initial_content = r#"
+apiVersion: v1
+entries:
+  test-chart:
+    - created: "2023-01-01T00:00:00.000Z"
+      digest: def456
+      urls:
+        - https://example.com/test-chart-0.0.1.tgz
+      version: 0.0.1
+    - created: 2023-02-01T10:20:30.123456789+02:00
+      digest: def789
+      urls:
+        - https://example.com/test-chart-0.0.2.tgz
+      version: 0.0.2
+"#;
+        write!(temp_file, "{}", initial_content)?;
+        let file_path = temp_file.path().to_str().unwrap();
+
+        let updated_yaml = update_yaml(
+            file_path,
+            &create_test_constants(),
+            &create_test_parameters(),
+            &UpdateOptions::default(),
+            &mut Warnings::default(),
+        )?;
+        let parsed: ChartYaml = serde_yaml::from_str(&updated_yaml)?;
+        let entries = parsed
+            .entries
+            .get(Value::String("test-chart".to_string()))
+            .and_then(Value::as_sequence)
+            .unwrap();
+
+        assert_eq!(entries.len(), 3);
+        assert_eq!(
+            entries[0]["created"].as_str(),
+            Some("2023-01-01T00:00:00.000Z")
+        );
+        assert_eq!(
+            entries[1]["created"].as_str(),
+            Some("2023-02-01T10:20:30.123456789+02:00")
+        );
+        assert!(updated_yaml.contains("2023-01-01T00:00:00.000Z"));
+        assert!(updated_yaml.contains("2023-02-01T10:20:30.123456789+02:00"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_add_entry_with_fixed_clock() -> Result<(), Box<dyn std::error::Error>> {
+        let mut data: ChartYaml = serde_yaml::from_str(
+            r#"
+apiVersion: v1
+entries:
+  test-chart:
+    - created: "2024-01-01T00:00:00.000Z"
+      digest: def456
+      version: 0.0.1
+"#,
+        )?;
+        let clock = FixedClock(DateTime::parse_from_rfc3339("2023-06-01T12:00:00Z")?.into());
+        let mut warnings = Warnings::default();
+
+        add_entry_with_clock(
+            &mut data,
+            &create_test_constants(),
+            &create_test_parameters(),
+            &UpdateOptions::default(),
+            &mut warnings,
+            &clock,
+        )?;
+        let entries = data.entries["test-chart"].as_sequence().unwrap();
+        assert_eq!(
+            entries[1]["created"].as_str(),
+            Some("2023-06-01T12:00:00.000Z")
+        );
+        assert!(warnings
+            .messages
+            .iter()
+            .any(|m| m.contains("older than the latest existing entry")));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_format_timestamp() -> Result<(), Box<dyn std::error::Error>> {
+        let time = DateTime::parse_from_rfc3339("2023-06-01T12:00:00.5Z")?.into();
+
+        assert_eq!(
+            format_timestamp(time, DEFAULT_TIMESTAMP_FORMAT)?,
+            "2023-06-01T12:00:00.500Z"
+        );
+        assert_eq!(
+            format_timestamp(time, "%Y-%m-%dT%H:%M:%S%:z")?,
+            "2023-06-01T12:00:00+00:00"
+        );
+        assert!(format_timestamp(time, "%Y-%m-%d").is_err());
+        assert!(format_timestamp(time, "%Q").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_urls_match_version() {
+        let urls = |urls: &[&str]| urls.iter().map(|u| u.to_string()).collect::<Vec<_>>();
+
+        assert!(urls_match_version(
+            &urls(&["https://example.com/test-chart-0.1.0.tgz"]),
+            "0.1.0"
+        ));
+        assert!(urls_match_version(
+            &urls(&[
+                "https://mirror.example.com/0.2.0/download",
+                "https://example.com/test-chart-0.2.0.tgz?token=1",
+            ]),
+            "0.2.0"
+        ));
+        assert!(!urls_match_version(
+            &urls(&["https://example.com/0.2.0/test-chart-0.1.0.tgz"]),
+            "0.2.0"
+        ));
+    }
+
+    #[test]
+    fn test_update_yaml_preserves_extra_top_level_keys() -> Result<(), Box<dyn std::error::Error>> {
+        let mut temp_file = NamedTempFile::new()?;
+        let initial_content = r#"
+apiVersion: v1
+entries: {}
+generated: "2023-01-01T00:00:00Z"
+serverInfo:
+  contextPath: /charts
+"#;
+        write!(temp_file, "{}", initial_content)?;
+        let file_path = temp_file.path().to_str().unwrap();
+
+        let updated_yaml = update_yaml(
+            file_path,
+            &create_test_constants(),
+            &create_test_parameters(),
+            &UpdateOptions::default(),
+            &mut Warnings::default(),
+        )?;
+        let parsed: Value = serde_yaml::from_str(&updated_yaml)?;
+
+        assert_eq!(
+            parsed["serverInfo"]["contextPath"].as_str(),
+            Some("/charts")
+        );
+        assert_eq!(parsed["generated"].as_str(), Some("2023-01-01T00:00:00Z"));
+        assert_eq!(
+            parsed["entries"]["test-chart"].as_sequence().unwrap().len(),
+            1
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_index_url_survives_updates() -> Result<(), Box<dyn std::error::Error>> {
+        let mut data = ChartYaml::default();
+        assert!(data.set_index_url("https://charts.example.com/index.yaml"));
+        assert!(!data.set_index_url("https://charts.example.com/index.yaml"));
+        assert!(data.set_repo_name("platform"));
+        let mut data: ChartYaml = serde_yaml::from_str(&serde_yaml::to_string(&data)?)?;
+
+        add_entry(
+            &mut data,
+            &create_test_constants(),
+            &create_test_parameters(),
+            &UpdateOptions::default(),
+            &mut Warnings::default(),
+        )?;
+        let parsed: Value = serde_yaml::from_str(&serde_yaml::to_string(&data)?)?;
+        assert_eq!(
+            parsed["indexUrl"].as_str(),
+            Some("https://charts.example.com/index.yaml")
+        );
+        assert_eq!(parsed["repoName"].as_str(), Some("platform"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_entry() -> Result<(), Box<dyn std::error::Error>> {
+        let data: ChartYaml = serde_yaml::from_str(
+            r#"
+apiVersion: v1
+entries:
+  test-chart:
+    - version: 1.2.0
+      digest: aaa
+    - version: 1.10.0
+      digest: bbb
+    - version: not-semver
+      digest: ccc
+"#,
+        )?;
+
+        let latest = find_entry(&data, "test-chart", None).unwrap();
+        assert_eq!(latest["digest"].as_str(), Some("bbb"));
+        let entry = find_entry(&data, "test-chart", Some("1.2.0")).unwrap();
+        assert_eq!(entry["digest"].as_str(), Some("aaa"));
+        assert!(find_entry(&data, "test-chart", Some("2.0.0")).is_none());
+        assert!(find_entry(&data, "missing", None).is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_add_entry_build_metadata_is_same_version() -> Result<(), Box<dyn std::error::Error>> {
+        assert_eq!(version_identity("1.0.0+build.5"), "1.0.0");
+        assert_eq!(version_identity("1.0.0-rc.1+b"), "1.0.0-rc.1");
+        assert_eq!(version_identity("v1+b"), "v1+b");
+
+        let mut data = ChartYaml::default();
+        let mut parameters = create_test_parameters();
+        parameters.version = "0.1.0+a".to_string();
+        add_entry(
+            &mut data,
+            &create_test_constants(),
+            &parameters,
+            &UpdateOptions::default(),
+            &mut Warnings::default(),
+        )?;
+
+        parameters.version = "0.1.0+b".to_string();
+        let result = add_entry(
+            &mut data,
+            &create_test_constants(),
+            &parameters,
+            &UpdateOptions::default(),
+            &mut Warnings::default(),
+        );
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("0.1.0+a, which differs from 0.1.0+b only in build metadata"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_dedup_entries() -> Result<(), Box<dyn std::error::Error>> {
+        let index = r#"
+apiVersion: v1
+entries:
+  test-chart:
+    - version: 0.1.0
+      digest: aaa
+      created: "2023-01-01T00:00:00Z"
+    - version: 0.2.0
+      digest: bbb
+    - version: 0.1.0
+      digest: aaa
+      created: "2023-02-01T00:00:00Z"
+"#;
+        let mut data: ChartYaml = serde_yaml::from_str(index)?;
+        let removed = dedup_entries(&mut data, true, &mut Warnings::default())?;
+        assert_eq!(removed, ["test-chart@0.1.0"]);
+        let entries = data.entries["test-chart"].as_sequence().unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[1]["created"].as_str(), Some("2023-02-01T00:00:00Z"));
+
+        let mut data: ChartYaml = serde_yaml::from_str(&index.replacen("aaa", "ccc", 1))?;
+        assert!(dedup_entries(&mut data, true, &mut Warnings::default()).is_err());
+        let mut warnings = Warnings::default();
+        dedup_entries(&mut data, false, &mut warnings)?;
+        assert_eq!(warnings.len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_version_lock() {
+        let mut lock = VersionLock::default();
+        assert!(lock.record("web", "1.2.0"));
+        assert!(!lock.record("web", "1.1.0"));
+        assert!(!lock.record("web", "1.2.0+rebuild"));
+        assert!(lock.record("web", "1.10.0"));
+
+        assert!(lock.check("web", "1.10.0").is_ok());
+        assert!(lock.check("web", "2.0.0").is_ok());
+        assert!(lock.check("db", "0.1.0").is_ok());
+        assert!(lock
+            .check("web", "1.9.0")
+            .unwrap_err()
+            .to_string()
+            .contains("1.10.0 was already inserted"));
+        assert!(lock
+            .check("web", "v2.0.0")
+            .unwrap_err()
+            .to_string()
+            .contains("isn't valid semver"));
+        assert!(lock.check("db", "latest").is_ok());
+    }
+
+    #[test]
+    fn test_chart_filter() {
+        assert!(glob_match("vendor-*", "vendor-redis"));
+        assert!(glob_match("*-db?", "my-db1"));
+        assert!(!glob_match("*-db?", "my-db"));
+        assert!(glob_match("*", ""));
+
+        let filter = ChartFilter {
+            include: vec!["app-*".to_string(), "web".to_string()],
+            exclude: vec!["*-legacy".to_string()],
+        };
+        assert!(filter.matches("app-api"));
+        assert!(filter.matches("web"));
+        assert!(!filter.matches("app-legacy"));
+        assert!(!filter.matches("db"));
+        assert!(ChartFilter::default().matches("anything"));
+
+        let allowed = ChartFilter::allowlist("# sanctioned\nplatform-*\n\nweb\n").unwrap();
+        assert!(allowed.matches("platform-dns"));
+        assert!(!allowed.matches("webapp"));
+        assert!(ChartFilter::allowlist("# nothing yet\n").is_err());
+    }
+
+    #[test]
+    fn test_prune_index() -> Result<(), Box<dyn std::error::Error>> {
+        let mut data: ChartYaml = serde_yaml::from_str(
+            r#"
+apiVersion: v1
+entries:
+  app:
+    - version: 1.0.0
+    - version: 1.2.0
+    - version: 1.1.0
+  vendor-redis:
+    - version: 1.0.0
+    - version: 2.0.0
+"#,
+        )?;
+        let filter = ChartFilter {
+            exclude: vec!["vendor-*".to_string()],
+            ..Default::default()
+        };
+
+        let mut warnings = Warnings::default();
+        let removed = prune_index(&mut data, 1, &filter, false, &mut warnings)?;
+        assert_eq!(removed, ["app@1.0.0", "app@1.1.0"]);
+        assert_eq!(chart_versions(&data, "vendor-redis").len(), 2);
+        assert!(warnings.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_prune_index_keep_latest_stable() -> Result<(), Box<dyn std::error::Error>> {
+        let mut data: ChartYaml = serde_yaml::from_str(
+            r#"
+apiVersion: v1
+entries:
+  app:
+    - version: 1.0.0
+    - version: 1.1.0
+    - version: 1.2.0-rc.1
+    - version: 1.2.0-rc.2
+  stable:
+    - version: 1.0.0
+    - version: 2.0.0
+"#,
+        )?;
+        let mut warnings = Warnings::default();
+
+        let removed = prune_index(&mut data, 2, &ChartFilter::default(), true, &mut warnings)?;
+        assert_eq!(removed, ["app@1.0.0", "app@1.2.0-rc.1"]);
+        assert_eq!(chart_versions(&data, "app"), ["1.1.0", "1.2.0-rc.2"]);
+        assert_eq!(
+            warnings.messages,
+            ["Kept app@1.1.0, the latest stable version, over --keep 2"]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_touch_generated() -> Result<(), Box<dyn std::error::Error>> {
+        let contents = "apiVersion: v1\nentries:\n  a:\n  - generated: keep\n    version: 0.1.0\ngenerated: \"2023-01-01T00:00:00Z\"\nserverInfo: {}\n";
+        assert_eq!(
+            touch_generated(contents, "2026-01-02T03:04:05.000Z")?,
+            contents.replace("\"2023-01-01T00:00:00Z\"", "2026-01-02T03:04:05.000Z")
+        );
+        assert_eq!(
+            touch_generated("apiVersion: v1\nentries: {}", "2026-01-02T03:04:05.000Z")?,
+            "apiVersion: v1\nentries: {}\ngenerated: 2026-01-02T03:04:05.000Z\n"
+        );
+        assert!(touch_generated("generated:\n  nested: 1\n", "2026-01-02T03:04:05.000Z").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_index_to_string_expands_repeated_values() -> Result<(), Box<dyn std::error::Error>> {
+        let mut data = ChartYaml::default();
+        let constants = create_test_constants();
+        let mut warnings = Warnings::default();
+        for version in ["0.1.0", "0.2.0"] {
+            let parameters = Parameters {
+                version: version.to_string(),
+                digest: format!("{}abc", version.replace('.', "")),
+                urls: vec![format!("https://example.com/test-chart-{}.tgz", version)],
+                ..create_test_parameters()
+            };
+            add_entry(
+                &mut data,
+                &constants,
+                &parameters,
+                &UpdateOptions::default(),
+                &mut warnings,
+            )?;
+        }
+
+        let yaml = index_to_string(&data)?;
+        assert_eq!(yaml.matches("name: Test Maintainer").count(), 2);
+        assert!(!yaml.contains('&'));
+        assert!(!yaml.contains('*'));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_patch_entries_keeps_artifact() -> Result<(), Box<dyn std::error::Error>> {
+        let mut data: ChartYaml = serde_yaml::from_str(
+            r#"
+apiVersion: v1
+entries:
+  test-chart:
+    - version: 0.1.0
+      digest: aaa
+      description: Old
+      urls:
+        - https://example.com/test-chart-0.1.0.tgz
+    - version: 0.2.0
+      digest: bbb
+      description: Old
+"#,
+        )?;
+        let digest_before = data.entries["test-chart"][0]["digest"].clone();
+
+        let patch: Value = serde_yaml::from_str("description: New\nkeywords: [cache]")?;
+        let patched = patch_entries(&mut data, "test-chart", Some("0.1.0"), &patch)?;
+        assert_eq!(patched, ["0.1.0"]);
+        let entries = &data.entries["test-chart"];
+        assert_eq!(entries[0]["description"].as_str(), Some("New"));
+        assert_eq!(entries[0]["digest"], digest_before);
+        assert_eq!(entries[1]["description"].as_str(), Some("Old"));
+
+        let before = serde_yaml::to_string(&data)?;
+        for (field, patch) in [
+            ("digest", "digest: ccc"),
+            ("urls", "urls: []"),
+            ("version", "{description: New, version: 9.9.9}"),
+            ("created", "created: now"),
+        ] {
+            let patch: Value = serde_yaml::from_str(patch)?;
+            let error = patch_entries(&mut data, "test-chart", None, &patch).unwrap_err();
+            assert_eq!(
+                error.to_string(),
+                format!("A patch can't change `{}`", field)
+            );
+        }
+        assert_eq!(serde_yaml::to_string(&data)?, before);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_remove_versions_in_range() -> Result<(), Box<dyn std::error::Error>> {
+        let mut data: ChartYaml = serde_yaml::from_str(
+            r#"
+apiVersion: v1
+entries:
+  test-chart:
+    - version: 1.1.0
+    - version: 1.2.0-rc.1
+    - version: 1.2.0-rc.2
+    - version: 1.2.0
+"#,
+        )?;
+        let req = semver::VersionReq::parse(">=1.2.0-0, <1.2.0")?;
+
+        let removed = remove_versions(&mut data, "test-chart", |v| {
+            semver::Version::parse(v).is_ok_and(|v| req.matches(&v))
+        })?;
+        assert_eq!(removed, ["1.2.0-rc.1", "1.2.0-rc.2"]);
+        assert_eq!(chart_versions(&data, "test-chart"), ["1.1.0", "1.2.0"]);
+
+        remove_versions(&mut data, "test-chart", |_| true)?;
+        assert!(data.entries.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_add_entry_keyword_guards() -> Result<(), Box<dyn std::error::Error>> {
+        let mut data = ChartYaml::default();
+        let mut constants = create_test_constants();
+        constants.keywords = vec![
+            " Test ".to_string(),
+            "test".to_string(),
+            "Observability".to_string(),
+        ];
+        let options = UpdateOptions {
+            normalize_keywords: true,
+            max_keywords: Some(1),
+            max_keyword_length: Some(8),
+            ..Default::default()
+        };
+
+        let mut warnings = Warnings::default();
+        add_entry(
+            &mut data,
+            &constants,
+            &create_test_parameters(),
+            &options,
+            &mut warnings,
+        )?;
+        let entries = data
+            .entries
+            .get(Value::String("test-chart".to_string()))
+            .unwrap();
+        let entries: Vec<ChartEntry> = serde_yaml::from_value(entries.clone())?;
+        assert_eq!(entries[0].keywords, ["test", "observability"]);
+        assert_eq!(warnings.len(), 2);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,91 @@
+use std::{
+    io::Read,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+use helm_repo_updater::package;
+
+/// How long a download may wait to connect or between reads before failing
+const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Checks each URL with a HEAD request (falling back to GET for servers that
+/// refuse HEAD), at most `concurrency` at a time. Returns why each URL is
+/// dead, or `None` if it answered with a non-error status.
+pub fn check(urls: &[&str], timeout: Duration, concurrency: usize) -> Vec<Option<String>> {
+    let agent = ureq::AgentBuilder::new().timeout(timeout).build();
+    run(urls, concurrency, |url| probe(&agent, url))
+}
+
+/// Downloads each URL, at most `concurrency` at a time, returning the
+/// SHA-256 of its body or why it couldn't be downloaded
+pub fn digests(urls: &[&str], concurrency: usize) -> Vec<Result<String, String>> {
+    let agent = ureq::AgentBuilder::new()
+        .timeout_connect(DOWNLOAD_TIMEOUT)
+        .timeout_read(DOWNLOAD_TIMEOUT)
+        .build();
+    run(urls, concurrency, |url| {
+        let mut bytes = Vec::new();
+        agent
+            .get(url)
+            .call()
+            .map_err(reason)?
+            .into_reader()
+            .read_to_end(&mut bytes)
+            .map_err(|e| e.to_string())?;
+        Ok(package::digest(&bytes))
+    })
+}
+
+/// Calls `f` for each URL on up to `concurrency` threads, keeping the results
+/// in the URLs' order
+fn run<T: Send>(urls: &[&str], concurrency: usize, f: impl Fn(&str) -> T + Sync) -> Vec<T> {
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new((0..urls.len()).map(|_| None).collect::<Vec<_>>());
+
+    thread::scope(|scope| {
+        for _ in 0..concurrency.clamp(1, urls.len().max(1)) {
+            scope.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                let Some(url) = urls.get(i) else {
+                    break;
+                };
+                let result = f(url);
+                results.lock().unwrap()[i] = Some(result);
+            });
+        }
+    });
+
+    results
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .map(|result| result.expect("every URL is visited"))
+        .collect()
+}
+
+fn probe(agent: &ureq::Agent, url: &str) -> Option<String> {
+    let response = match agent.head(url).call() {
+        Err(ureq::Error::Status(405 | 501, _)) => agent.get(url).call(),
+        response => response,
+    };
+    response.err().map(reason)
+}
+
+fn reason(error: ureq::Error) -> String {
+    match error {
+        ureq::Error::Status(code, _) => format!("HTTP {}", code),
+        // The transport error's own message repeats the URL
+        ureq::Error::Transport(e) => {
+            let mut reason = e.kind().to_string();
+            if let Some(source) = std::error::Error::source(&e) {
+                reason = format!("{}: {}", reason, source);
+            }
+            reason
+        }
+    }
+}
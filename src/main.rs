@@ -1,171 +1,455 @@
-use chrono::Utc;
-use clap::{Parser, Subcommand};
-use serde::{Deserialize, Serialize};
-use serde_yaml::{Mapping, Value};
+use clap::{ArgGroup, Args, Parser, Subcommand, ValueEnum};
+use dialoguer::{Confirm, Input, Select};
+use helm_repo_updater::{
+    add_entry, canonicalize, changelog, chart_shard, chart_versions, checksum_path, dedup_entries,
+    diff, expand_url_template, find_entry, find_profile, format_timestamp, gzip, gzip_path,
+    has_version, host_allowed, index_to_string, is_http_url, layer_constants, load_index,
+    merge_values, normalize_entry_versions, package, pair_inputs, parse_index, parse_index_as,
+    parse_input, patch_entries, prune_index, remove_versions, sort_charts, sort_versions,
+    stored_version, touch_generated, url_host, value_list, verify, writer, AddOutcome, ChartFilter,
+    ChartYaml, Constants, InputFormat, Maintainer, Parameters, UpdateOptions, VersionLock,
+    Warnings, DEFAULT_TIMESTAMP_FORMAT,
+};
+use indicatif::{ProgressBar, ProgressStyle};
+use serde_json::json;
+use serde_yaml::Value;
+#[cfg(feature = "links")]
+mod links;
+#[cfg(feature = "oci")]
+mod oci;
+#[cfg(feature = "s3")]
+mod s3;
+#[cfg(feature = "server")]
+mod server;
+mod table;
+
 use std::{
+    env,
     fs::{self, File},
-    io::Write,
+    io::{self, IsTerminal, Write},
     path::{Path, PathBuf},
 };
 
-#[derive(Debug, Serialize, Deserialize)]
-struct ChartYaml {
-    #[serde(rename = "apiVersion")]
-    api_version: Option<String>,
-    entries: Mapping,
-}
-
-impl Default for ChartYaml {
-    fn default() -> Self {
-        Self {
-            api_version: Some("v1".to_string()),
-            entries: Mapping::new(),
-        }
-    }
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-struct ChartEntry {
-    #[serde(rename = "apiVersion")]
-    api_version: String,
-    #[serde(rename = "appVersion")]
-    app_version: String,
-    created: String,
-    description: String,
-    digest: String,
-    home: String,
-    icon: String,
-    keywords: Vec<String>,
-    maintainers: Vec<Maintainer>,
-    name: String,
-    sources: Vec<String>,
-    #[serde(rename = "type")]
-    entry_type: String,
-    urls: Vec<String>,
-    version: String,
-}
-
-#[derive(Debug, Serialize, Deserialize, Clone)]
-struct Maintainer {
-    email: String,
-    name: String,
-    url: String,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-struct Constants {
-    #[serde(rename = "apiVersion")]
-    api_version: String,
-    #[serde(rename = "appVersion")]
-    app_version: String,
-    description: String,
-    home: String,
-    icon: String,
-    keywords: Vec<String>,
-    maintainers: Vec<Maintainer>,
-    name: String,
-    sources: Vec<String>,
-    #[serde(rename = "type")]
-    entry_type: String,
-}
-
-impl Default for Constants {
-    fn default() -> Self {
-        Self {
-            api_version: "v2".to_string(),
-            app_version: "1.0.0".to_string(),
-            description: "Test Chart".to_string(),
-            home: "https://example.com".to_string(),
-            icon: "https://example.com/icon.png".to_string(),
-            keywords: vec!["test".to_string(), "chart".to_string()],
-            maintainers: vec![Maintainer {
-                email: "test@example.com".to_string(),
-                name: "Abdulrhman Alkhodiry".to_string(),
-                url: "https://example.com".to_string(),
-            }],
-            name: "test-chart".to_string(),
-            sources: vec!["https://github.com/test/chart".to_string()],
-            entry_type: "application".to_string(),
-        }
-    }
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-struct Parameters {
-    #[serde(rename = "appVersion")]
-    app_version: Option<String>,
-    digest: String,
-    version: String,
-    urls: Vec<String>,
-}
-
-impl Default for Parameters {
-    fn default() -> Self {
-        Self {
-            app_version: None,
-            digest: "abc123".to_string(),
-            version: "0.1.0".to_string(),
-            urls: vec!["https://example.com/test-chart-0.1.0.tgz".to_string()],
-        }
-    }
-}
-
-fn update_yaml(
-    file_path: &str,
-    constants: &Constants,
-    parameters: &Parameters,
-) -> Result<String, Box<dyn std::error::Error>> {
-    let mut data: ChartYaml = if Path::new(file_path).exists() {
-        let contents = fs::read_to_string(file_path)?;
-
-        let contents = if contents.trim().is_empty() {
-            "apiVersion: v1\nentries: {}\n"
-        } else {
-            &contents
+/// Fallback for `index --url` when the flag isn't given
+const BASE_URL_VAR: &str = "HELM_REPO_BASE_URL";
+/// Fallback for `update --index-url` when the flag isn't given
+const INDEX_URL_VAR: &str = "HELM_REPO_INDEX_URL";
+
+/// Asks the user to confirm on stdin; refuses when stdin isn't a terminal
+fn confirm(prompt: &str) -> Result<bool, Box<dyn std::error::Error>> {
+    if !io::stdin().is_terminal() {
+        return Err("Refusing to prompt without a terminal; pass --yes to confirm".into());
+    }
+    eprint!("{} [y/N] ", prompt);
+    io::stderr().flush()?;
+
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+/// Where `update` reads the index from and writes it back to
+enum IndexFile<'a> {
+    Local(&'a Path),
+    /// An `s3://bucket/key` object and the ETag it was read at, if it exists
+    #[cfg(feature = "s3")]
+    S3 {
+        location: s3::Location,
+        etag: Option<String>,
+    },
+}
+
+impl<'a> IndexFile<'a> {
+    fn open(file: &'a Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let Some(url) = file.to_str().filter(|f| f.starts_with("s3://")) else {
+            return Ok(Self::Local(file));
+        };
+
+        #[cfg(feature = "s3")]
+        {
+            let location = s3::Location::parse(url)
+                .ok_or_else(|| format!("Expected s3://bucket/key, got '{}'", url))?;
+            Ok(Self::S3 {
+                location,
+                etag: None,
+            })
+        }
+        #[cfg(not(feature = "s3"))]
+        Err(format!("Updating {} requires building with the `s3` feature", url).into())
+    }
+
+    fn is_local(&self) -> bool {
+        matches!(self, Self::Local(_))
+    }
+
+    /// Reads the index, returning it along with its current contents (empty
+    /// if it doesn't exist yet). It's sniffed as YAML or JSON unless `format`
+    /// is given.
+    fn load(
+        &mut self,
+        format: Option<InputFormat>,
+        tolerate_duplicate_keys: bool,
+        warnings: &mut Warnings,
+        output: &Output,
+    ) -> Result<(ChartYaml, String), Box<dyn std::error::Error>> {
+        let contents = match self {
+            Self::Local(path) if path.exists() => fs::read_to_string(path)?,
+            Self::Local(_) => String::new(),
+            #[cfg(feature = "s3")]
+            Self::S3 { location, etag } => match s3::get(location)? {
+                Some((contents, current)) => {
+                    *etag = Some(current);
+                    contents
+                }
+                None => String::new(),
+            },
+        };
+        if contents.trim().is_empty() {
+            return Ok((ChartYaml::default(), contents));
+        }
+
+        let (data, format) = parse_index_as(&contents, format, tolerate_duplicate_keys, warnings)?;
+        output.debug(format_args!("The index parsed as {}", format));
+        Ok((data, contents))
+    }
+
+    /// Writes the index back; S3 objects are only overwritten if nobody else
+    /// changed them since `load`
+    fn store(&self, contents: &str) -> Result<(), Box<dyn std::error::Error>> {
+        match self {
+            Self::Local(path) => Ok(fs::write(path, contents)?),
+            #[cfg(feature = "s3")]
+            Self::S3 { location, etag } => s3::put(location, contents, etag.as_deref()),
+        }
+    }
+}
+
+/// Separates an index's file name from the timestamp of its backups
+const BACKUP_INFIX: &str = ".bak.";
+
+/// Where `--backup` copies `file`: next to it, or in `dir`, named with a
+/// timestamp that sorts in the order they're taken
+fn backup_path(file: &Path, dir: Option<&Path>) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let name = file.file_name().ok_or("The index path has no file name")?;
+    let dir = dir.or(file.parent()).unwrap_or(Path::new(""));
+    Ok(dir.join(format!(
+        "{}{}{}",
+        name.to_string_lossy(),
+        BACKUP_INFIX,
+        chrono::Utc::now().format("%Y%m%dT%H%M%S%.3fZ")
+    )))
+}
+
+/// The newest backup of `file`, looked up where [`backup_path`] puts them
+fn latest_backup(file: &Path, dir: Option<&Path>) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let name = file.file_name().ok_or("The index path has no file name")?;
+    let prefix = format!("{}{}", name.to_string_lossy(), BACKUP_INFIX);
+    let dir = dir
+        .or(file.parent())
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+
+    let mut newest: Option<PathBuf> = None;
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let is_backup = path
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with(&prefix));
+        if is_backup && newest.as_ref().is_none_or(|newest| path > *newest) {
+            newest = Some(path);
+        }
+    }
+
+    newest
+        .ok_or_else(|| format!("No backup of {} found in {}", file.display(), dir.display()).into())
+}
+
+/// Every `chart@version` in an index
+fn entry_names(data: &ChartYaml) -> std::collections::BTreeSet<String> {
+    data.entries
+        .keys()
+        .filter_map(Value::as_str)
+        .flat_map(|chart| {
+            chart_versions(data, chart)
+                .into_iter()
+                .map(move |version| format!("{}@{}", chart, version))
+        })
+        .collect()
+}
+
+/// Rewrites the `--emit-split` shard of each chart an update changed
+fn write_shards(
+    data: &ChartYaml,
+    dir: &Path,
+    outcomes: &[AddOutcome],
+) -> Result<(), Box<dyn std::error::Error>> {
+    fs::create_dir_all(dir)?;
+    let mut written: Vec<&str> = Vec::new();
+    for outcome in outcomes.iter().filter(|outcome| outcome.changed) {
+        let chart = outcome.chart.as_str();
+        if written.contains(&chart) {
+            continue;
+        }
+        if chart.is_empty() || chart.contains(['/', '\\']) || chart.starts_with('.') {
+            return Err(
+                format!("Chart name '{}' can't be used as a shard file name", chart).into(),
+            );
+        }
+        let shard = index_to_string(&chart_shard(data, chart))?;
+        fs::write(dir.join(format!("{}.yaml", chart)), shard)?;
+        written.push(chart);
+    }
+
+    Ok(())
+}
+
+/// Splits a `--set` argument at its first `=`
+fn parse_assignment(arg: &str) -> Result<(String, String), String> {
+    match arg.split_once('=') {
+        Some((path, value)) if !path.is_empty() => Ok((path.to_string(), value.to_string())),
+        _ => Err(format!("expected PATH=VALUE, got '{}'", arg)),
+    }
+}
+
+/// Prints a unified diff, colored when stdout is a terminal
+fn print_diff(diff: &str) {
+    let color = io::stdout().is_terminal();
+    for line in diff.lines() {
+        let code = match line.as_bytes().first() {
+            _ if !color => None,
+            Some(b'+') => Some("32"),
+            Some(b'-') => Some("31"),
+            Some(b'@') => Some("36"),
+            _ => None,
         };
-        serde_yaml::from_str(&contents)?
-    } else {
-        ChartYaml {
-            api_version: Some("v1".to_owned()),
-            entries: Mapping::new(),
+        match code {
+            Some(code) => println!("\x1b[{}m{}\x1b[0m", code, line),
+            None => println!("{}", line),
         }
+    }
+}
+
+/// Pulls a chart from a registry, returning it along with its blob URL
+fn pull_oci(
+    reference: &str,
+) -> Result<(package::ChartPackage, String), Box<dyn std::error::Error>> {
+    #[cfg(feature = "oci")]
+    {
+        let reference = oci::Reference::parse(reference)
+            .ok_or_else(|| format!("Expected oci://registry/chart:version, got '{}'", reference))?;
+        let package = oci::pull(&reference)
+            .map_err(|e| format!("Failed to pull {}: {}", reference.repository, e))?;
+        let url = reference.blob_url(&package.digest);
+        Ok((package, url))
+    }
+    #[cfg(not(feature = "oci"))]
+    Err(format!(
+        "Pulling {} requires building with the `oci` feature",
+        reference
+    )
+    .into())
+}
+
+/// Checks the metadata links of an index, returning a finding per dead link
+fn check_links(
+    index: &ChartYaml,
+    args: &VerifyArgs,
+) -> Result<Vec<verify::Finding>, Box<dyn std::error::Error>> {
+    let links = verify::metadata_links(index);
+    #[cfg(feature = "links")]
+    {
+        let mut urls: Vec<&str> = links.iter().map(|link| link.url.as_str()).collect();
+        urls.sort_unstable();
+        urls.dedup();
+        let results = links::check(
+            &urls,
+            std::time::Duration::from_secs(args.link_timeout),
+            args.link_concurrency,
+        );
+
+        Ok(links
+            .iter()
+            .filter_map(|link| {
+                let at = urls.binary_search(&link.url.as_str()).ok()?;
+                let reason = results[at].as_deref()?;
+                Some(link.dead(reason, args.ignore_link_errors))
+            })
+            .collect())
+    }
+    #[cfg(not(feature = "links"))]
+    {
+        let _ = (links, args);
+        Err("--check-links requires building with the `links` feature".into())
+    }
+}
+
+/// Downloads the entries' artifacts, returning a finding per digest mismatch
+/// or failed download
+fn validate_digests(
+    index: &ChartYaml,
+    args: &VerifyArgs,
+) -> Result<Vec<verify::Finding>, Box<dyn std::error::Error>> {
+    let artifacts = verify::artifacts(index);
+    #[cfg(feature = "links")]
+    {
+        let urls: Vec<&str> = artifacts.iter().map(|a| a.url.as_str()).collect();
+        let results = links::digests(&urls, args.concurrency);
+
+        Ok(artifacts
+            .iter()
+            .zip(&results)
+            .filter_map(|(artifact, result)| artifact.check(result))
+            .collect())
+    }
+    #[cfg(not(feature = "links"))]
+    {
+        let _ = (artifacts, args);
+        Err("--validate-digests requires building with the `links` feature".into())
+    }
+}
+
+/// Reads a constants or parameters file, sniffing YAML or JSON unless
+/// `format` is given
+fn read_input(
+    path: &Path,
+    format: Option<InputFormat>,
+    output: &Output,
+) -> Result<Value, Box<dyn std::error::Error>> {
+    let contents = fs::read_to_string(path)?;
+    let (value, format) =
+        parse_input(&contents, format).map_err(|e| format!("{}: {}", path.display(), e))?;
+    output.debug(format_args!("{} parsed as {}", path.display(), format));
+    Ok(value)
+}
+
+/// Reads a parameters file; with `prompt_missing`, required fields it lacks
+/// are asked for on the terminal instead of failing to parse
+fn read_parameters(
+    path: &Path,
+    prompt_missing: bool,
+    format: Option<InputFormat>,
+    output: &Output,
+) -> Result<Vec<Parameters>, Box<dyn std::error::Error>> {
+    let value = read_input(path, format, output)?;
+    if !prompt_missing {
+        return value_list(value);
+    }
+
+    let items = match value {
+        Value::Sequence(items) => items,
+        Value::Null => vec![Value::Mapping(Default::default())],
+        value => vec![value],
     };
+    let mut parameters = Vec::new();
+    for mut item in items {
+        let Value::Mapping(fields) = &mut item else {
+            return Err(format!("{} must contain a mapping of parameters", path.display()).into());
+        };
+        for (field, prompt) in [
+            ("version", "Chart version"),
+            ("digest", "Package digest (sha256)"),
+            ("urls", "Package URLs (comma-separated)"),
+        ] {
+            if fields.contains_key(field) {
+                continue;
+            }
+            if !io::stdin().is_terminal() {
+                return Err(format!(
+                    "{} is missing `{}` and there's no terminal to prompt for it",
+                    path.display(),
+                    field
+                )
+                .into());
+            }
+            let answer = Input::<String>::new().with_prompt(prompt).interact_text()?;
+            let value = if field == "urls" {
+                answer
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|url| !url.is_empty())
+                    .map(|url| Value::String(url.to_string()))
+                    .collect()
+            } else {
+                Value::String(answer.trim().to_string())
+            };
+            fields.insert(Value::String(field.to_string()), value);
+        }
+        parameters.push(serde_yaml::from_value(item)?);
+    }
 
-    let created = Utc::now().format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string();
-
-    let new_entry = ChartEntry {
-        api_version: constants.api_version.clone(),
-        app_version: parameters
-            .app_version
-            .clone()
-            .unwrap_or_else(|| constants.app_version.clone()),
-        created,
-        description: constants.description.clone(),
-        digest: parameters.digest.clone(),
-        home: constants.home.clone(),
-        icon: constants.icon.clone(),
-        keywords: constants.keywords.clone(),
-        maintainers: constants.maintainers.clone(),
-        name: constants.name.clone(),
-        sources: constants.sources.clone(),
-        entry_type: constants.entry_type.clone(),
-        urls: parameters.urls.clone(),
-        version: parameters.version.clone(),
+    Ok(parameters)
+}
+
+/// Prompts for the fields new users most often need to change, offering the
+/// current values as defaults
+fn prompt_templates(
+    constants: &mut Constants,
+    parameters: &mut Parameters,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let text = |prompt: &str, default: &str| {
+        Input::<String>::new()
+            .with_prompt(prompt)
+            .default(default.to_string())
+            .allow_empty(true)
+            .interact_text()
+    };
+    let list = |prompt: &str, default: &[String]| -> Result<Vec<String>, dialoguer::Error> {
+        Ok(text(prompt, &default.join(", "))?
+            .split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+            .map(str::to_string)
+            .collect())
     };
 
-    let entries_key = Value::String(constants.name.clone());
-    let entries = data
-        .entries
-        .entry(entries_key)
-        .or_insert(Value::Sequence(Vec::new()));
+    constants.name = text("Chart name", &constants.name)?;
+    constants.description = text("Description", &constants.description)?;
+    constants.home = text("Home page", &constants.home)?;
+    constants.icon = text("Icon URL", &constants.icon)?;
+    constants.app_version = text("App version", &constants.app_version)?;
+    let types = ["application", "library"];
+    let selected = Select::new()
+        .with_prompt("Chart type")
+        .items(types)
+        .default(
+            types
+                .iter()
+                .position(|t| *t == constants.entry_type)
+                .unwrap_or(0),
+        )
+        .interact()?;
+    constants.entry_type = types[selected].to_string();
+    constants.keywords = list("Keywords (comma-separated)", &constants.keywords)?;
+    constants.sources = list("Source URLs (comma-separated)", &constants.sources)?;
 
-    if let Value::Sequence(ref mut vec) = entries {
-        vec.push(serde_yaml::to_value(&new_entry)?);
-    } else {
-        return Err("Unexpected value type for entries".into());
+    let defaults = std::mem::take(&mut constants.maintainers);
+    loop {
+        let default = defaults.get(constants.maintainers.len());
+        let field = |get: fn(&Maintainer) -> &String| default.map(get).cloned().unwrap_or_default();
+        constants.maintainers.push(Maintainer {
+            name: text("Maintainer name", &field(|m| &m.name))?,
+            email: text("Maintainer email", &field(|m| &m.email))?,
+            url: text("Maintainer URL", &field(|m| &m.url))?,
+        });
+        let more = Confirm::new()
+            .with_prompt("Add another maintainer?")
+            .default(constants.maintainers.len() < defaults.len())
+            .interact()?;
+        if !more {
+            break;
+        }
     }
 
-    serde_yaml::to_string(&data).map_err(Into::into)
+    parameters.version = text("Chart version", &parameters.version)?;
+    let url = format!(
+        "{}/{}-{}.tgz",
+        constants.home.trim_end_matches('/'),
+        constants.name,
+        parameters.version
+    );
+    parameters.urls = vec![text("Package URL", &url)?];
+
+    Ok(())
 }
 
 #[derive(Parser)]
@@ -173,182 +457,2364 @@ fn update_yaml(
 struct Cli {
     #[command(subcommand)]
     command: Commands,
+
+    /// Exit with an error if any warnings were emitted
+    #[arg(long, global = true)]
+    fail_on_warnings: bool,
+
+    /// Merge the entries of chart names listed more than once in an index
+    /// instead of failing
+    #[arg(long, global = true)]
+    tolerate_duplicate_keys: bool,
+
+    /// How to report the result: prose messages, or a single JSON object
+    #[arg(long, global = true, value_enum, default_value_t = OutputFormat::Text)]
+    output_format: OutputFormat,
+
+    /// Suppress the prose messages printed in text mode
+    #[arg(short, long, global = true)]
+    quiet: bool,
+
+    /// Print debug details, such as where settings were read from, to stderr
+    #[arg(long, global = true)]
+    verbose: bool,
+}
+
+/// How much of the index `update` verifies before writing it
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum ValidateScope {
+    /// Only the entries just added
+    Changed,
+    /// Every entry, like `verify`
+    All,
+}
+
+/// How `update` parses its input files
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum InputFormatArg {
+    /// Try YAML, then JSON, whatever the file extension
+    Auto,
+    Yaml,
+    Json,
+}
+
+impl From<InputFormatArg> for Option<InputFormat> {
+    fn from(format: InputFormatArg) -> Self {
+        match format {
+            InputFormatArg::Auto => None,
+            InputFormatArg::Yaml => Some(InputFormat::Yaml),
+            InputFormatArg::Json => Some(InputFormat::Json),
+        }
+    }
+}
+
+/// Which bundled JSON schema `schema` prints
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum SchemaKind {
+    Constants,
+    Parameters,
+    Index,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum OutputFormat {
+    Text,
+    Json,
+}
+
+/// Reports a command's result either as prose or as one JSON object at the end
+struct Output {
+    format: OutputFormat,
+    quiet: bool,
+    verbose: bool,
+    /// Write to stderr because stdout is carrying the index itself
+    to_stderr: bool,
+}
+
+impl Output {
+    /// A message only shown with --verbose, always on stderr
+    fn debug(&self, message: std::fmt::Arguments) {
+        if self.verbose {
+            eprintln!("debug: {}", message);
+        }
+    }
+
+    /// A setting's value from its flag, falling back to an environment
+    /// variable, reporting which one was used
+    fn setting(&self, name: &str, flag: Option<&String>, var: &str) -> Option<String> {
+        if let Some(value) = flag {
+            self.debug(format_args!("{} from the command line", name));
+            return Some(value.clone());
+        }
+        match env::var(var).ok().filter(|value| !value.is_empty()) {
+            Some(value) => {
+                self.debug(format_args!("{} from ${}", name, var));
+                Some(value)
+            }
+            None => {
+                self.debug(format_args!("{} not set", name));
+                None
+            }
+        }
+    }
+
+    fn say(&self, message: std::fmt::Arguments) {
+        if self.format != OutputFormat::Text || self.quiet {
+            return;
+        }
+        if self.to_stderr {
+            eprintln!("{}", message);
+        } else {
+            println!("{}", message);
+        }
+    }
+
+    /// A progress bar on stderr, hidden unless prose output goes to a terminal
+    fn progress(&self, len: usize) -> ProgressBar {
+        if self.format != OutputFormat::Text || self.quiet || !io::stderr().is_terminal() {
+            return ProgressBar::hidden();
+        }
+        ProgressBar::new(len as u64).with_style(
+            ProgressStyle::with_template("{bar:40} {pos}/{len} {msg}")
+                .expect("progress template is valid"),
+        )
+    }
+
+    fn emit(&self, result: &serde_json::Value) {
+        if self.to_stderr {
+            eprintln!("{}", result);
+        } else {
+            println!("{}", result);
+        }
+    }
+}
+
+#[derive(Args)]
+#[command(group(ArgGroup::new("packages").args(["package_manifest", "chart_package"])))]
+#[command(group(ArgGroup::new("chart_urls").args(["chart_url", "url_template"])))]
+#[command(group(ArgGroup::new("chart_yaml").args(["package_manifest", "chart_package", "from_chart"])))]
+struct UpdateArgs {
+    /// Path to the YAML file to update
+    #[arg(short, long)]
+    file: PathBuf,
+
+    /// How the constants, parameters and index files are parsed
+    #[arg(long, value_enum, default_value_t = InputFormatArg::Auto)]
+    input_format: InputFormatArg,
+
+    /// Path to the constants YAML file; repeat to layer files, each
+    /// deep-merged over the ones before it
+    #[arg(short, long, required_unless_present_any = ["packages", "from_oci", "from_chart"])]
+    constants: Vec<PathBuf>,
+
+    /// When layering constants files, append lists (e.g. keywords,
+    /// maintainers) instead of replacing them
+    #[arg(long)]
+    merge_lists: bool,
+
+    /// Print the merged constants as YAML and exit without updating the index
+    #[arg(long)]
+    print_config: bool,
+
+    /// Path to the parameters YAML file
+    #[arg(short, long, required_unless_present_any = ["packages", "from_oci", "print_config"])]
+    parameters: Option<PathBuf>,
+
+    /// Path to a YAML/JSON list of `{ package, url }` items to add instead
+    /// of a parameters file; metadata is read from each package's Chart.yaml
+    #[arg(long, conflicts_with = "parameters")]
+    package_manifest: Option<PathBuf>,
+
+    /// Path to a single `.tgz` package to add, or `-` to read it from stdin;
+    /// like a one-item --package-manifest
+    #[arg(long, conflicts_with_all = ["parameters", "package_manifest"], requires = "chart_urls")]
+    chart_package: Option<PathBuf>,
+
+    /// Download URL recorded for the --chart-package
+    #[arg(long, requires = "chart_package")]
+    chart_url: Option<String>,
+
+    /// Build the --chart-package URL from its Chart.yaml instead, e.g.
+    /// `https://charts.example.com/{name}-{version}.tgz`; `{app_version}` is
+    /// also available
+    #[arg(long, requires = "chart_package")]
+    url_template: Option<String>,
+
+    /// Build the entry from a chart pushed to a registry
+    /// (`oci://registry/chart:version`) instead of a parameters file
+    /// (requires the `oci` feature)
+    #[arg(long, conflicts_with_all = ["parameters", "packages"])]
+    from_oci: Option<String>,
+
+    /// Download URL recorded for a `--from-oci` chart [default: the
+    /// registry's blob URL for the chart layer]
+    #[arg(long, requires = "from_oci")]
+    oci_url: Option<String>,
+
+    /// Read the entry metadata from an unpackaged chart directory's
+    /// Chart.yaml; the version, digest and URLs still come from --parameters.
+    /// An `index-constants.yaml` there is layered beneath the --constants
+    #[arg(long, conflicts_with_all = ["packages", "from_oci"])]
+    from_chart: Option<PathBuf>,
+
+    /// Drop pre-release and build metadata from the version (e.g.
+    /// `1.2.3-ci.45` becomes `1.2.3`) before inserting
+    #[arg(long)]
+    strip_prerelease: bool,
+
+    /// Fail if the chart would have more than this many versions; the cap is
+    /// per chart, not index-wide
+    #[arg(long)]
+    max_entries: Option<usize>,
+
+    /// Remove the lowest versions instead of failing when --max-entries is
+    /// exceeded; a new version lower than all the kept ones is rejected
+    #[arg(long, requires = "max_entries")]
+    auto_prune: bool,
+
+    /// Write the updated index here instead of overwriting `file`
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+
+    /// Print the updated index to stdout instead of writing it
+    #[arg(long, conflicts_with = "output")]
+    stdout: bool,
+
+    /// Copy the index to `<file>.bak.<timestamp>` before overwriting it, so
+    /// `rollback` can restore it
+    #[arg(long, conflicts_with_all = ["output", "stdout"])]
+    backup: bool,
+
+    /// Keep the --backup copies in this directory instead of next to the index
+    #[arg(long, value_name = "DIR", requires = "backup")]
+    backup_dir: Option<PathBuf>,
+
+    /// Verify only the added entries, or the whole updated index, before
+    /// writing it
+    #[arg(long, value_enum, default_value_t = ValidateScope::Changed)]
+    validate_scope: ValidateScope,
+
+    /// Also write a deterministic gzip-compressed `<file>.gz` copy of the
+    /// updated index
+    #[arg(long, conflicts_with = "stdout")]
+    also_gzip: bool,
+
+    /// Skip entries of a multi-entry update that fail instead of aborting:
+    /// the rest are written together, then the failures are listed and the
+    /// command exits non-zero
+    #[arg(long)]
+    continue_on_error: bool,
+
+    /// Serialize the index with the faster built-in writer; the output is
+    /// byte-for-byte the same
+    #[arg(long)]
+    fast_write: bool,
+
+    /// Also rewrite `<DIR>/<chart>.yaml`, a per-chart shard of the updated
+    /// index, for each chart that changed
+    #[arg(long, value_name = "DIR", conflicts_with = "stdout")]
+    emit_split: Option<PathBuf>,
+
+    /// Check and report what would be added without writing anything
+    #[arg(long, conflicts_with = "stdout")]
+    dry_run: bool,
+
+    /// With --dry-run, print a unified diff of the index changes
+    #[arg(long, requires = "dry_run")]
+    diff: bool,
+
+    /// Leave hunks that only change `created`/`generated` out of --diff
+    #[arg(long, requires = "diff")]
+    ignore_timestamps: bool,
+
+    /// Also write a `<file>.sha256` checksum of the updated index
+    #[arg(long, conflicts_with = "stdout")]
+    write_checksum: bool,
+
+    /// Sort the entry's maintainers by name, then email
+    #[arg(long)]
+    sort_maintainers: bool,
+
+    /// Sort the entry's URLs lexicographically
+    #[arg(long)]
+    sort_urls: bool,
+
+    /// Pin this URL first in the entry's URLs (applied after --sort-urls)
+    #[arg(long, value_name = "URL")]
+    primary_url: Option<String>,
+
+    /// Accept a version like `v1.2.3` by stripping the leading `v` (with a
+    /// warning) instead of rejecting it
+    #[arg(long)]
+    normalize_version: bool,
+
+    /// Set a field of the new entry to a string, e.g. `kubeVersion=>=1.28.0`
+    /// or `annotations.team=cache`; escape dots in keys as `\.` (repeatable)
+    #[arg(long = "set", value_name = "PATH=VALUE", value_parser = parse_assignment)]
+    set_fields: Vec<(String, String)>,
+
+    /// Like --set with a JSON value, e.g. `deprecated=true`; applied after
+    /// --set (repeatable)
+    #[arg(long = "set-json", value_name = "PATH=JSON", value_parser = parse_assignment)]
+    set_json_fields: Vec<(String, String)>,
+
+    /// Fail if the entry would have more than this many URLs
+    #[arg(long, value_name = "N")]
+    max_urls: Option<usize>,
+
+    /// Keep only the first --max-urls URLs instead of failing
+    #[arg(long, requires = "max_urls")]
+    truncate_urls: bool,
+
+    /// Write the whole index in canonical form for diff-stable output, as
+    /// `canonicalize` does; implies --sort, --sort-charts, --sort-maintainers,
+    /// --sort-urls and --normalize-keywords
+    #[arg(long, conflicts_with_all = ["prepend", "preserve_existing_order", "primary_url"])]
+    canonical: bool,
+
+    /// Sort the chart's versions newest first after inserting
+    #[arg(long)]
+    sort: bool,
+
+    /// Sort the charts in `entries` by name, so adding a chart doesn't
+    /// reorder the others between runs
+    #[arg(long)]
+    sort_charts: bool,
+
+    /// Refuse to add a version listed in this file (one `name@version` per
+    /// line), e.g. a release pulled for security reasons
+    #[arg(long, value_name = "FILE")]
+    yanked_file: Option<PathBuf>,
+
+    /// Refuse to add a chart whose name doesn't match a name or glob (e.g.
+    /// `platform-*`) listed in this file, one per line
+    #[arg(long, value_name = "FILE")]
+    allowed_names_file: Option<PathBuf>,
+
+    /// Refuse to add a chart with an absolute URL whose host isn't one of
+    /// these, e.g. `charts.example.com,mirror.example.com`
+    #[arg(long, value_name = "HOSTS", value_delimiter = ',')]
+    allowed_hosts: Vec<String>,
+
+    /// Record the highest version inserted per chart in this file, and
+    /// refuse to insert a lower one
+    #[arg(long, value_name = "PATH")]
+    version_lock: Option<PathBuf>,
+
+    /// With --version-lock, allow inserting a version lower than the
+    /// recorded one
+    #[arg(long, requires = "version_lock")]
+    allow_downgrade: bool,
+
+    /// With --sort, insert the new entry at its position by binary search
+    /// when the chart's versions are already sorted; falls back to a full
+    /// sort, with a warning, if they turn out not to be
+    #[arg(long, requires = "sort")]
+    assume_sorted: bool,
+
+    /// Fail instead of warning when no URL's file name contains the version
+    #[arg(long)]
+    strict_urls: bool,
+
+    /// Populate the `artifacthub.io/changes` annotation from the commit
+    /// subjects in this git range (e.g. `v1.0.0..HEAD`)
+    #[arg(long, value_name = "RANGE")]
+    changelog_from_git: Option<String>,
+
+    /// Read a pre-formatted `artifacthub.io/changes` YAML list from this file
+    #[arg(long, conflicts_with = "changelog_from_git")]
+    changelog_file: Option<PathBuf>,
+
+    /// Warn when the entry has more than this many keywords
+    #[arg(long)]
+    max_keywords: Option<usize>,
+
+    /// Warn about keywords longer than this many characters
+    #[arg(long)]
+    max_keyword_length: Option<usize>,
+
+    /// Trim, lowercase and de-duplicate the entry's keywords
+    #[arg(long)]
+    normalize_keywords: bool,
+
+    /// Strip trailing slashes and needless percent-encoding from the entry's
+    /// URLs, then drop duplicates
+    #[arg(long)]
+    compact_urls: bool,
+
+    /// Insert the new entry first in the chart's sequence, for consumers that
+    /// treat the first entry as the latest
+    #[arg(long, conflicts_with = "sort")]
+    prepend: bool,
+
+    /// Leave existing entries exactly where they are and append the new one,
+    /// as `helm repo index --merge` does
+    #[arg(long, conflicts_with_all = ["sort", "prepend"])]
+    preserve_existing_order: bool,
+
+    /// Record each package's newest file modification time as `created`
+    /// instead of the current time
+    #[arg(long, requires = "packages")]
+    created_from_package: bool,
+
+    /// Fill fields Chart.yaml doesn't set from `hru.io/<field>` annotations
+    /// (e.g. `hru.io/home`, `hru.io/icon`) before falling back to constants
+    #[arg(long, requires = "packages")]
+    annotation_hints: bool,
+
+    /// Use the Chart.yaml `type` even when the constants file sets a
+    /// different one, instead of failing
+    #[arg(long, requires = "chart_yaml")]
+    from_chart_wins: bool,
+
+    /// Succeed without changes when the version is already present with the
+    /// same digest, so a retried release step is harmless
+    #[arg(long)]
+    idempotent: bool,
+
+    /// Merge the parameters' `annotations` over the constants' ones key by
+    /// key instead of replacing them
+    #[arg(long)]
+    merge_annotations: bool,
+
+    /// Write the digest as `sha256:<hex>`, as OCI tooling expects, instead of
+    /// Helm's bare hex
+    #[arg(long)]
+    digest_prefix: bool,
+
+    /// Read the maintainers from this YAML list instead of the constants, so
+    /// one shared file lists them for every chart
+    #[arg(long, value_name = "PATH", requires = "constants")]
+    maintainers_file: Option<PathBuf>,
+
+    /// Collapse whitespace and newlines in the description to single spaces
+    #[arg(long)]
+    trim_description: bool,
+
+    /// Strip basic markdown (emphasis, code, links, headings) from the
+    /// description
+    #[arg(long)]
+    strip_markdown: bool,
+
+    /// chrono format string for `created`, e.g. `%Y-%m-%dT%H:%M:%SZ` to drop
+    /// the milliseconds [default: %Y-%m-%dT%H:%M:%S%.3fZ]
+    #[arg(long, value_name = "FMT")]
+    timestamp_format: Option<String>,
+
+    /// Record the canonical URL of the index in its top-level `indexUrl`
+    /// [env: HELM_REPO_INDEX_URL]
+    #[arg(long, value_name = "URL")]
+    index_url: Option<String>,
+
+    /// Record the repository's name in its top-level `repoName`, so the
+    /// index identifies itself to aggregators
+    #[arg(long, value_name = "NAME")]
+    repo_name: Option<String>,
+
+    /// Truncate the description to this many characters, ending with `…`
+    #[arg(long, value_name = "N")]
+    max_description_length: Option<usize>,
+
+    /// Prompt on the terminal for `version`, `digest` or `urls` when the
+    /// parameters file lacks them, instead of failing
+    #[arg(long, requires = "parameters")]
+    prompt_missing: bool,
+
+    #[command(flatten)]
+    summary: SummaryArgs,
+
+    #[command(flatten)]
+    jsonl: JsonlArgs,
+
+    /// Refuse to write an index without any entries
+    #[arg(long)]
+    fail_if_empty: bool,
+
+    /// Fail when neither the parameters nor the constants give a non-empty
+    /// app version, instead of warning
+    #[arg(long)]
+    require_app_version: bool,
+
+    /// Fail when the resolved entry type is empty
+    #[arg(long)]
+    require_type: bool,
+}
+
+impl UpdateArgs {
+    fn options(&self) -> Result<UpdateOptions, Box<dyn std::error::Error>> {
+        let mut set_fields: Vec<(String, Value)> = self
+            .set_fields
+            .iter()
+            .map(|(path, value)| (path.clone(), Value::from(value.as_str())))
+            .collect();
+        for (path, json) in &self.set_json_fields {
+            let value: serde_json::Value = serde_json::from_str(json)
+                .map_err(|e| format!("Invalid JSON for --set-json {}: {}", path, e))?;
+            set_fields.push((path.clone(), serde_yaml::to_value(value)?));
+        }
+
+        Ok(UpdateOptions {
+            strip_prerelease: self.strip_prerelease,
+            merge_annotations: self.merge_annotations,
+            max_entries: self.max_entries,
+            auto_prune: self.auto_prune,
+            sort_maintainers: self.sort_maintainers || self.canonical,
+            strict_urls: self.strict_urls,
+            prepend: self.prepend,
+            sort_versions: self.sort || self.canonical,
+            assume_sorted: self.assume_sorted,
+            changelog: None,
+            max_keywords: self.max_keywords,
+            max_keyword_length: self.max_keyword_length,
+            normalize_keywords: self.normalize_keywords || self.canonical,
+            compact_urls: self.compact_urls,
+            idempotent: self.idempotent,
+            digest_prefix: self.digest_prefix,
+            trim_description: self.trim_description,
+            strip_markdown: self.strip_markdown,
+            max_description_length: self.max_description_length,
+            timestamp_format: self.timestamp_format.clone(),
+            require_app_version: self.require_app_version,
+            require_type: self.require_type,
+            sort_urls: self.sort_urls || self.canonical,
+            primary_url: self.primary_url.clone(),
+            max_urls: self.max_urls,
+            truncate_urls: self.truncate_urls,
+            normalize_version: self.normalize_version,
+            set_fields,
+        })
+    }
+}
+
+#[derive(Args)]
+struct VerifyArgs {
+    /// Path to the YAML file to verify
+    #[arg(short, long)]
+    file: PathBuf,
+
+    /// Also check the file against its `<file>.sha256` sibling
+    #[arg(long)]
+    checksum: bool,
+
+    /// Also validate the index against the bundled Helm index JSON schema
+    #[arg(long)]
+    against_schema: bool,
+
+    /// Validate against this JSON schema instead of the bundled one
+    /// (implies --against-schema)
+    #[arg(long)]
+    schema: Option<PathBuf>,
+
+    /// Treat these warnings as errors: versions of a chart sharing a digest,
+    /// URLs shared between entries, versions differing only in build
+    /// metadata, entries mixing URL schemes and v2-only fields in a `v1`
+    /// index
+    #[arg(long)]
+    strict: bool,
+
+    /// Fail unless every chart listed in this file (one per line, optionally
+    /// with a minimum version: `name>=1.0.0`) is in the index
+    #[arg(long, value_name = "FILE")]
+    require_charts: Option<PathBuf>,
+
+    /// Fail if any version listed in this file (one `name@version` per line)
+    /// is in the index
+    #[arg(long, value_name = "FILE")]
+    yanked_file: Option<PathBuf>,
+
+    /// Report entries without a `type` or with an empty one
+    #[arg(long)]
+    require_type: bool,
+
+    /// Report each absolute URL whose host isn't one of these, e.g.
+    /// `charts.example.com,mirror.example.com`
+    #[arg(long, value_name = "HOSTS", value_delimiter = ',')]
+    allowed_hosts: Vec<String>,
+
+    /// Drop findings with this code, e.g. `HRU005` (repeatable)
+    #[arg(long, value_name = "CODE")]
+    ignore: Vec<String>,
+
+    /// Send HEAD requests to every `home`, `icon` and maintainer `url` and
+    /// report dead links (requires the `links` feature)
+    #[arg(long)]
+    check_links: bool,
+
+    /// Seconds to wait for each link before counting it as dead
+    #[arg(
+        long,
+        value_name = "SECS",
+        default_value_t = 10,
+        requires = "check_links"
+    )]
+    link_timeout: u64,
+
+    /// How many links to check at once
+    #[arg(long, value_name = "N", default_value_t = 8, requires = "check_links")]
+    link_concurrency: usize,
+
+    /// Report dead links as warnings instead of errors, e.g. for air-gapped
+    /// runs
+    #[arg(long, requires = "check_links")]
+    ignore_link_errors: bool,
+
+    /// Download each entry's first URL and report artifacts whose SHA-256
+    /// doesn't match the recorded digest (requires the `links` feature)
+    #[arg(long)]
+    validate_digests: bool,
+
+    /// How many artifacts to download at once
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = 4,
+        requires = "validate_digests"
+    )]
+    concurrency: usize,
+
+    /// Score each chart by how many recommended metadata fields (icon,
+    /// description, keywords, sources, home) its newest version sets, and
+    /// list the missing ones
+    #[arg(long)]
+    completeness: bool,
+
+    /// Fail if a chart's completeness score is below this percentage
+    #[arg(
+        long,
+        value_name = "PERCENT",
+        requires = "completeness",
+        value_parser = clap::value_parser!(u32).range(0..=100)
+    )]
+    min_completeness: Option<u32>,
+
+    /// Warn about charts whose newest version has no icon
+    #[arg(long)]
+    warn_missing_icon: bool,
+
+    #[command(flatten)]
+    filter: FilterArgs,
+}
+
+/// Chart name globs selecting which charts a command operates on
+#[derive(Args)]
+struct FilterArgs {
+    /// Only operate on charts matching this glob (repeatable)
+    #[arg(long, value_name = "GLOB")]
+    include: Vec<String>,
+
+    /// Skip charts matching this glob (repeatable); wins over --include
+    #[arg(long, value_name = "GLOB")]
+    exclude: Vec<String>,
+}
+
+impl FilterArgs {
+    fn filter(&self) -> ChartFilter {
+        ChartFilter {
+            include: self.include.clone(),
+            exclude: self.exclude.clone(),
+        }
+    }
+}
+
+/// Where to keep an audit record of a run
+#[derive(Args)]
+struct SummaryArgs {
+    /// Append a record of the run (time, action, entries added or removed,
+    /// entry counts, outcome) to this file: a JSON line for `.json`/`.jsonl`,
+    /// a YAML list item otherwise
+    #[arg(long, value_name = "PATH")]
+    summary_file: Option<PathBuf>,
+}
+
+impl SummaryArgs {
+    /// Appends the record for a run that touched `entries` (`(chart, version,
+    /// digest)`), if a summary file was asked for
+    fn record(
+        &self,
+        action: &str,
+        file: &Path,
+        counts: (usize, usize),
+        entries: &[serde_json::Value],
+        outcome: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let Some(path) = &self.summary_file else {
+            return Ok(());
+        };
+        let record = json!({
+            "timestamp": chrono::Utc::now().to_rfc3339(),
+            "action": action,
+            "file": file,
+            "entries": entries,
+            "entries_before": counts.0,
+            "entries_after": counts.1,
+            "outcome": outcome,
+        });
+        let json_lines = path
+            .extension()
+            .is_some_and(|ext| ext == "json" || ext == "jsonl");
+        let line = if json_lines {
+            format!("{}\n", record)
+        } else {
+            serde_yaml::to_string(&[record])?
+        };
+
+        let mut log = File::options().create(true).append(true).open(path)?;
+        log.write_all(line.as_bytes())
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e).into())
+    }
+}
+
+/// Where to stream an event per added entry
+#[derive(Args)]
+struct JsonlArgs {
+    /// Append a JSON line per added entry (action, chart, version, digest,
+    /// urls, created) to this file, or `-` for stdout, for event consumers
+    #[arg(long, value_name = "PATH|-")]
+    jsonl_out: Option<PathBuf>,
+}
+
+impl JsonlArgs {
+    fn to_stdout(&self) -> bool {
+        self.jsonl_out.as_deref() == Some(Path::new("-"))
+    }
+
+    /// Writes a line for each `(chart, version)` in `entries`, flushing each
+    /// so a consumer can read them as they come
+    fn emit(
+        &self,
+        data: &ChartYaml,
+        action: &str,
+        entries: &[(&str, &str)],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let Some(path) = &self.jsonl_out else {
+            return Ok(());
+        };
+        let mut out: Box<dyn Write> = match self.to_stdout() {
+            true => Box::new(io::stdout().lock()),
+            false => Box::new(File::options().create(true).append(true).open(path)?),
+        };
+        for (chart, version) in entries {
+            let entry = find_entry(data, chart, Some(version));
+            let field = |name| entry.and_then(|entry| entry.get(name));
+            let event = json!({
+                "action": action,
+                "chart": chart,
+                "version": version,
+                "digest": field("digest"),
+                "urls": field("urls"),
+                "created": field("created"),
+            });
+            writeln!(out, "{}", event)
+                .and_then(|()| out.flush())
+                .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+        }
+        Ok(())
+    }
+}
+
+/// The number of entries across all charts
+fn count_entries(data: &ChartYaml) -> usize {
+    data.entries
+        .values()
+        .filter_map(Value::as_sequence)
+        .map(Vec::len)
+        .sum()
+}
+
+/// Guards against accidentally wiping a repository
+fn ensure_not_empty(
+    data: &ChartYaml,
+    file: &Path,
+    flag: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if count_entries(data) == 0 {
+        return Err(format!(
+            "Refusing to write {} without any entries ({})",
+            file.display(),
+            flag
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// A `{ chart, version, digest }` summary of an entry of `data`
+fn entry_summary(data: &ChartYaml, chart: &str, version: &str) -> serde_json::Value {
+    let digest = find_entry(data, chart, Some(version))
+        .and_then(|entry| entry.get("digest"))
+        .and_then(Value::as_str);
+    json!({ "chart": chart, "version": version, "digest": digest })
 }
 
 #[derive(Subcommand)]
 enum Commands {
     /// Update the YAML file
-    Update {
+    Update(Box<UpdateArgs>),
+    /// Generate a new YAML file templates
+    Generate {
+        /// Partial constants YAML merged over the built-in defaults
+        #[arg(long, alias = "template-values")]
+        values: Option<PathBuf>,
+
+        /// Prompt for the main fields, showing the defaults; skipped when
+        /// stdin isn't a terminal
+        #[arg(short, long)]
+        interactive: bool,
+    },
+    /// Build an index from a directory of packaged charts
+    Index {
+        /// Directory containing the `.tgz` packages
+        #[arg(short, long)]
+        dir: PathBuf,
+
+        /// Base URL the packages are served from [env: HELM_REPO_BASE_URL]
+        #[arg(short, long, visible_alias = "base-url")]
+        url: Option<String>,
+
+        /// Existing index to merge the packages into; versions it already
+        /// has are left untouched
+        #[arg(short, long)]
+        merge: Option<PathBuf>,
+
+        /// Where to write the index [default: <dir>/index.yaml]
+        #[arg(short, long)]
+        out: Option<PathBuf>,
+
+        #[command(flatten)]
+        jsonl: JsonlArgs,
+    },
+    /// Compare an index against a directory of packages, reporting entries
+    /// without a package and packages without an entry
+    Reconcile {
+        /// Path to the YAML file
+        #[arg(short, long)]
+        file: PathBuf,
+
+        /// Directory containing the `.tgz` packages
+        #[arg(short, long)]
+        dir: PathBuf,
+
+        /// Base URL the packages are served from, for --add-missing
+        /// [env: HELM_REPO_BASE_URL]
+        #[arg(short, long)]
+        url: Option<String>,
+
+        /// Add the packages missing from the index instead of only reporting
+        /// them. Entries without a package are never removed.
+        #[arg(long)]
+        add_missing: bool,
+    },
+    /// Remove versions of a chart from the index
+    Remove {
         /// Path to the YAML file to update
         #[arg(short, long)]
         file: PathBuf,
 
-        /// Path to the constants YAML file
+        /// Name of the chart
+        #[arg(short, long)]
+        name: String,
+
+        /// Exact version to remove
+        #[arg(short, long, required_unless_present = "version_range")]
+        version: Option<String>,
+
+        /// Remove every version matching a semver requirement, e.g.
+        /// `>=1.2.0-0, <1.2.0` for all 1.2.0 pre-releases
+        #[arg(long, value_name = "REQ", conflicts_with = "version")]
+        version_range: Option<String>,
+
+        /// Don't ask for confirmation before removing a range
+        #[arg(short, long)]
+        yes: bool,
+
+        /// Write the index even if no entries are left
+        #[arg(long)]
+        allow_empty: bool,
+
+        #[command(flatten)]
+        summary: SummaryArgs,
+    },
+    /// List the charts in the index and their versions
+    List {
+        /// Path to the YAML file
+        #[arg(short, long)]
+        file: PathBuf,
+
+        #[command(flatten)]
+        filter: FilterArgs,
+
+        /// Show one row per version in a table fitted to the terminal; plain
+        /// output is used when stdout isn't a terminal
+        #[arg(long, overrides_with = "no_table")]
+        table: bool,
+
+        /// Use plain output even with --table
+        #[arg(long)]
+        no_table: bool,
+    },
+    /// Keep only the highest versions of each chart
+    Prune {
+        /// Path to the YAML file to prune
+        #[arg(short, long)]
+        file: PathBuf,
+
+        /// Number of versions to keep per chart
+        #[arg(short, long)]
+        keep: usize,
+
+        /// Never remove each chart's highest non-prerelease version, even
+        /// beyond --keep
+        #[arg(long)]
+        keep_latest_stable: bool,
+
+        /// Write the index even if no entries are left
+        #[arg(long)]
+        allow_empty: bool,
+
+        #[command(flatten)]
+        summary: SummaryArgs,
+
+        #[command(flatten)]
+        filter: FilterArgs,
+    },
+    /// Change metadata of published entries without touching their digest,
+    /// URLs, version or created time
+    Patch {
+        /// Path to the YAML file to patch
+        #[arg(short, long)]
+        file: PathBuf,
+
+        /// Name of the chart
+        #[arg(short, long)]
+        name: String,
+
+        /// Only patch this version [default: every version]
+        #[arg(short, long)]
+        version: Option<String>,
+
+        /// Partial entry YAML (e.g. `description`, `maintainers`) merged into
+        /// the entries
         #[arg(short, long)]
         constants: PathBuf,
+    },
+    /// Collapse duplicate entries of the same version, keeping the newest
+    Dedup {
+        /// Path to the YAML file to repair
+        #[arg(short, long)]
+        file: PathBuf,
+
+        /// Fail if duplicates of a version have different digests
+        #[arg(long)]
+        strict: bool,
+    },
+    /// Sort each chart's versions by semver, newest first
+    Sort {
+        /// Path to the YAML file to sort
+        #[arg(short, long)]
+        file: PathBuf,
 
-        /// Path to the parameters YAML file
+        /// Sort oldest first instead
+        #[arg(long)]
+        ascending: bool,
+
+        /// Rewrite versions like `v1.2.3` to `1.2.3` before sorting
+        #[arg(long)]
+        normalize_version: bool,
+    },
+    /// Rewrite an index in canonical form: charts sorted by name, versions
+    /// newest first, URLs and maintainers sorted, keywords normalized
+    Canonicalize {
+        /// Path to the YAML file to rewrite
         #[arg(short, long)]
-        parameters: PathBuf,
+        file: PathBuf,
+    },
+    /// Refresh only the top-level `generated` timestamp, e.g. to bust a CDN
+    /// cache, leaving the entries byte-identical
+    Touch {
+        /// Path to the YAML file to touch
+        #[arg(short, long)]
+        file: PathBuf,
+    },
+    /// Restore an index from its newest `update --backup` copy
+    Rollback {
+        /// Path to the YAML file to restore
+        #[arg(short, long)]
+        file: PathBuf,
+
+        /// Directory the backups were written to with `update --backup-dir`
+        #[arg(long, value_name = "DIR")]
+        backup_dir: Option<PathBuf>,
+    },
+    /// Print the JSON schema of a constants file, parameters file or index,
+    /// e.g. for editor validation
+    Schema {
+        #[arg(value_enum)]
+        which: SchemaKind,
+    },
+    /// Check an index for invalid or inconsistent entries
+    Verify(VerifyArgs),
+    /// Print one entry of the index as YAML
+    Show {
+        /// Path to the YAML file
+        #[arg(short, long)]
+        file: PathBuf,
+
+        /// Name of the chart
+        #[arg(short, long)]
+        name: String,
+
+        /// Version to show [default: the highest semver version]
+        #[arg(short, long)]
+        version: Option<String>,
+    },
+    /// Print the digest Helm records in the index for a packaged chart
+    Digest {
+        /// Path to the `.tgz` package, or `-` to read it from stdin
+        package: PathBuf,
+    },
+    /// Serve index verification over HTTP: POST an index to get a report
+    #[cfg(feature = "server")]
+    Serve {
+        /// Port to listen on
+        #[arg(short, long, default_value_t = 8080)]
+        port: u16,
+
+        /// Address to listen on; use 0.0.0.0 to accept connections from
+        /// other hosts
+        #[arg(long, default_value = "127.0.0.1")]
+        bind: String,
     },
-    /// Generate a new YAML file templates
-    Generate {},
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let cli = Cli::parse();
+    let mut warnings = Warnings::default();
+    let mut output = Output {
+        format: cli.output_format,
+        quiet: cli.quiet,
+        verbose: cli.verbose,
+        to_stderr: false,
+    };
+    let mut failure = None;
 
-    match &cli.command {
-        Commands::Update {
-            file,
-            constants,
-            parameters,
-        } => {
-            let constants: Constants = serde_yaml::from_str(&fs::read_to_string(constants)?)?;
-            let parameters: Parameters = serde_yaml::from_str(&fs::read_to_string(parameters)?)?;
+    let result = match &cli.command {
+        Commands::Update(args) => {
+            let file = &args.file;
+            if args.stdout && args.jsonl.to_stdout() {
+                return Err("--jsonl-out - and --stdout can't both write to stdout".into());
+            }
+            let mut options = args.options()?;
+            if let Some(range) = &args.changelog_from_git {
+                options.changelog = Some(changelog::from_git(range)?);
+            } else if let Some(path) = &args.changelog_file {
+                options.changelog = Some(changelog::from_file(path)?);
+            }
+            let chart_constants = args
+                .from_chart
+                .as_ref()
+                .map(|dir| dir.join(package::CHART_CONSTANTS_FILE))
+                .filter(|path| path.is_file());
+            let layers = chart_constants
+                .iter()
+                .chain(&args.constants)
+                .map(|path| read_input(path, args.input_format.into(), &output))
+                .collect::<Result<Vec<_>, _>>()?;
+            let profiles = match layers.is_empty() {
+                true => Vec::new(),
+                false => layer_constants(layers, args.merge_lists)?,
+            };
+            let profiles = match &args.maintainers_file {
+                Some(path) => {
+                    let maintainers: Vec<Maintainer> =
+                        serde_yaml::from_str(&fs::read_to_string(path)?)
+                            .map_err(|e| format!("{}: {}", path.display(), e))?;
+                    profiles
+                        .into_iter()
+                        .map(|profile| Constants {
+                            maintainers: maintainers.clone(),
+                            ..profile
+                        })
+                        .collect()
+                }
+                None => profiles,
+            };
+            if args.print_config {
+                if profiles.is_empty() {
+                    return Err("--print-config needs at least one --constants file".into());
+                }
+                match profiles.as_slice() {
+                    [profile] => print!("{}", serde_yaml::to_string(profile)?),
+                    profiles => print!("{}", serde_yaml::to_string(profiles)?),
+                }
+                return Ok(());
+            }
+
+            let items = match (&args.package_manifest, &args.chart_package) {
+                (Some(manifest), _) => Some(package::read_manifest(manifest)?),
+                (None, Some(package)) => Some(vec![package::ManifestItem {
+                    package: package.clone(),
+                    url: args.chart_url.clone().unwrap_or_default(),
+                }]),
+                (None, None) => None,
+            };
+            let inputs = if let Some(items) = items {
+                let mut inputs = Vec::new();
+                for item in items {
+                    let mut package = package::read_package(&item.package)?;
+                    if args.annotation_hints {
+                        package.apply_annotation_hints();
+                    }
+                    let defaults = find_profile(&profiles, Some(&package.metadata.name));
+                    if !args.from_chart_wins {
+                        package.check_type(defaults)?;
+                    }
+                    let url = match &args.url_template {
+                        Some(template) => {
+                            let chart = &package.metadata;
+                            expand_url_template(
+                                template,
+                                &[
+                                    ("name", &chart.name),
+                                    ("version", &chart.version),
+                                    ("app_version", chart.app_version.as_deref().unwrap_or("")),
+                                ],
+                            )?
+                        }
+                        None => item.url,
+                    };
+                    let mut parameters = package.parameters(vec![url]);
+                    if args.created_from_package {
+                        match package.built {
+                            Some(built) => parameters.created = Some(built),
+                            None => warnings.warn(format!(
+                                "{} has zeroed file times, using the current time for `created`",
+                                item.package.display()
+                            )),
+                        }
+                    }
+                    inputs.push((package.constants(defaults), parameters));
+                }
+                inputs
+            } else if let Some(reference) = &args.from_oci {
+                let (package, blob_url) = pull_oci(reference)?;
+                let defaults = find_profile(&profiles, Some(&package.metadata.name));
+                package.check_type(defaults)?;
+                let url = args.oci_url.clone().unwrap_or(blob_url);
+                vec![(package.constants(defaults), package.parameters(vec![url]))]
+            } else if let Some(dir) = &args.from_chart {
+                let chart = package::read_chart_dir(dir)?;
+                let defaults = find_profile(&profiles, Some(&chart.name));
+                if !args.from_chart_wins {
+                    chart.check_type(defaults)?;
+                }
+                let constants = chart.constants(defaults);
+                let parameters = read_parameters(
+                    args.parameters.as_ref().ok_or("--parameters is required")?,
+                    args.prompt_missing,
+                    args.input_format.into(),
+                    &output,
+                )?;
+                if let Some(parameters) = parameters.iter().find(|p| p.version != chart.version) {
+                    return Err(format!(
+                        "Parameters version '{}' doesn't match the Chart.yaml version '{}' in {}",
+                        parameters.version,
+                        chart.version,
+                        dir.display()
+                    )
+                    .into());
+                }
+                parameters
+                    .into_iter()
+                    .map(|parameters| (constants.clone(), parameters))
+                    .collect()
+            } else {
+                let parameters = read_parameters(
+                    args.parameters.as_ref().ok_or("--parameters is required")?,
+                    args.prompt_missing,
+                    args.input_format.into(),
+                    &output,
+                )?;
+                pair_inputs(&profiles, parameters)?
+            };
+
+            let mut index_file = IndexFile::open(file)?;
+            if args.output.is_none() && !index_file.is_local() {
+                if args.write_checksum {
+                    return Err("--write-checksum needs a local file or --output".into());
+                }
+                if args.also_gzip {
+                    return Err("--also-gzip needs a local file or --output".into());
+                }
+            }
+            let (mut data, current_yaml) = index_file.load(
+                args.input_format.into(),
+                cli.tolerate_duplicate_keys,
+                &mut warnings,
+                &output,
+            )?;
+            let entries_before = count_entries(&data);
+            let mut top_level_changed = false;
+            let index_url = output.setting("--index-url", args.index_url.as_ref(), INDEX_URL_VAR);
+            if let Some(index_url) = &index_url {
+                if !is_http_url(index_url) {
+                    warnings.warn(format!("Index URL '{}' is not an http(s) URL", index_url));
+                }
+                top_level_changed = data.set_index_url(index_url);
+            }
+            if let Some(name) = &args.repo_name {
+                top_level_changed |= data.set_repo_name(name);
+            }
+            if let Some(path) = &args.allowed_names_file {
+                let allowed = ChartFilter::allowlist(&fs::read_to_string(path)?)
+                    .map_err(|e| format!("{}: {}", path.display(), e))?;
+                if let Some((constants, _)) = inputs
+                    .iter()
+                    .find(|(constants, _)| !allowed.matches(&constants.name))
+                {
+                    return Err(format!(
+                        "Refusing to add '{}': it isn't an allowed chart name in {}",
+                        constants.name,
+                        path.display()
+                    )
+                    .into());
+                }
+            }
+            if !args.allowed_hosts.is_empty() {
+                for (constants, parameters) in &inputs {
+                    if let Some(url) = parameters
+                        .urls
+                        .iter()
+                        .find(|url| !host_allowed(url, &args.allowed_hosts))
+                    {
+                        return Err(format!(
+                            "Refusing to add '{}': URL {} has host '{}', which isn't in \
+                             --allowed-hosts",
+                            constants.name,
+                            url,
+                            url_host(url).unwrap_or_default()
+                        )
+                        .into());
+                    }
+                }
+            }
+            let mut outcomes = Vec::new();
+            let yanked = match &args.yanked_file {
+                Some(path) => verify::Yanked::parse_list(&fs::read_to_string(path)?)?,
+                None => Vec::new(),
+            };
+            let mut lock: VersionLock = match &args.version_lock {
+                Some(path) if path.exists() => serde_yaml::from_str(&fs::read_to_string(path)?)
+                    .map_err(|e| format!("{}: {}", path.display(), e))?,
+                _ => VersionLock::default(),
+            };
+            // Checked before each add so that, like a failed add, a refusal
+            // only skips that entry under --continue-on-error. Versions are
+            // checked as they'd be stored, after any rewriting.
+            let admit = |chart: &str, version: &str| -> Result<(), Box<dyn std::error::Error>> {
+                let stored = stored_version(version, &options)?.to_string();
+                if yanked.iter().any(|yanked| yanked.matches(chart, &stored)) {
+                    return Err(format!(
+                        "Refusing to add {}@{}: it is listed in {} as yanked",
+                        chart,
+                        stored,
+                        args.yanked_file.as_ref().unwrap().display()
+                    )
+                    .into());
+                }
+                if !args.allow_downgrade {
+                    lock.check(chart, &stored)?;
+                }
+                Ok(())
+            };
+            // With --continue-on-error, `chart@version` and why it wasn't added
+            let mut failed: Vec<(String, String)> = Vec::new();
+            for (constants, parameters) in &inputs {
+                if !args.continue_on_error {
+                    admit(&constants.name, &parameters.version)?;
+                    outcomes.push(add_entry(
+                        &mut data,
+                        constants,
+                        parameters,
+                        &options,
+                        &mut warnings,
+                    )?);
+                    continue;
+                }
+                // A failed add can leave the chart half-touched, so roll it back
+                let before = data.clone();
+                match admit(&constants.name, &parameters.version).and_then(|()| {
+                    add_entry(&mut data, constants, parameters, &options, &mut warnings)
+                }) {
+                    Ok(outcome) => outcomes.push(outcome),
+                    Err(e) => {
+                        data = before;
+                        let entry = format!("{}@{}", constants.name, parameters.version);
+                        failed.push((entry, e.to_string()));
+                    }
+                }
+            }
+            if args.canonical {
+                for version in canonicalize(&mut data) {
+                    warnings.warn(format!("{} is not valid semver, sorted last", version));
+                }
+            } else if args.sort_charts {
+                sort_charts(&mut data);
+            }
+            let added: Vec<(&str, &str)> = outcomes
+                .iter()
+                .filter(|outcome| outcome.changed)
+                .map(|outcome| (outcome.chart.as_str(), outcome.version.as_str()))
+                .collect();
+            let findings = match args.validate_scope {
+                ValidateScope::All => verify(&data),
+                ValidateScope::Changed => verify::verify_entries(&data, &added),
+            };
+            // The added entries' warnings were already reported by add_entry
+            if args.validate_scope == ValidateScope::All {
+                for finding in &findings {
+                    let added_entry = finding
+                        .version
+                        .as_deref()
+                        .is_some_and(|version| added.contains(&(finding.chart.as_str(), version)));
+                    if finding.severity == verify::Severity::Warning && !added_entry {
+                        warnings.warn(finding.to_string());
+                    }
+                }
+            }
+            let report = verify::Report::from(findings);
+            if !report.is_valid() {
+                return Err(format!(
+                    "The updated index failed verification:\n  {}",
+                    report.errors.join("\n  ")
+                )
+                .into());
+            }
+            let changed = top_level_changed || outcomes.iter().any(|outcome| outcome.changed);
+            if args.fail_if_empty {
+                ensure_not_empty(
+                    &data,
+                    args.output.as_ref().unwrap_or(file),
+                    "--fail-if-empty",
+                )?;
+            }
+            let updated_yaml = match args.fast_write {
+                true => writer::to_string(&data)?,
+                false => index_to_string(&data)?,
+            };
+            let target = args.output.as_ref().unwrap_or(file);
+            if args.dry_run {
+                if args.diff {
+                    let name = file.display().to_string();
+                    let diff = diff::unified(
+                        &current_yaml,
+                        &updated_yaml,
+                        &name,
+                        &target.display().to_string(),
+                        args.ignore_timestamps,
+                    );
+                    print_diff(&diff);
+                    output.to_stderr = true;
+                }
+            } else if args.stdout {
+                // Keep stdout clean for the index itself
+                print!("{}", updated_yaml);
+                output.to_stderr = true;
+            } else if changed || args.output.is_some() {
+                if args.backup && !index_file.is_local() {
+                    return Err("--backup only works with a local index".into());
+                }
+                if args.backup && !current_yaml.is_empty() {
+                    let backup = backup_path(file, args.backup_dir.as_deref())?;
+                    if let Some(dir) = &args.backup_dir {
+                        fs::create_dir_all(dir)?;
+                    }
+                    fs::write(&backup, &current_yaml)?;
+                    output.debug(format_args!(
+                        "Backed up {} to {}",
+                        file.display(),
+                        backup.display()
+                    ));
+                }
+                match &args.output {
+                    Some(output) => fs::write(output, &updated_yaml)?,
+                    None => index_file.store(&updated_yaml)?,
+                }
+                // Only once the index is in place, so a failed write never
+                // leaves a checksum for contents that aren't there
+                if args.write_checksum {
+                    fs::write(
+                        checksum_path(target),
+                        format!(
+                            "{}  {}\n",
+                            package::digest(updated_yaml.as_bytes()),
+                            target.file_name().unwrap_or_default().to_string_lossy()
+                        ),
+                    )?;
+                }
+                if args.also_gzip {
+                    fs::write(gzip_path(target), gzip(&updated_yaml)?)?;
+                }
+                if let Some(dir) = &args.emit_split {
+                    write_shards(&data, dir, &outcomes)?;
+                }
+            }
+            if !args.dry_run {
+                let added: Vec<(&str, &str)> = outcomes
+                    .iter()
+                    .filter(|outcome| outcome.changed)
+                    .map(|outcome| (outcome.chart.as_str(), outcome.version.as_str()))
+                    .collect();
+                args.jsonl.emit(&data, "add", &added)?;
+                output.to_stderr |= args.jsonl.to_stdout();
+            }
+            if let (Some(path), false) = (&args.version_lock, args.dry_run) {
+                let mut recorded = false;
+                for outcome in outcomes.iter().filter(|outcome| outcome.changed) {
+                    recorded |= lock.record(&outcome.chart, &outcome.version);
+                }
+                if recorded {
+                    fs::write(path, serde_yaml::to_string(&lock)?)?;
+                }
+            }
 
-            let updated_yaml = update_yaml(file.to_str().unwrap(), &constants, &parameters)?;
-            fs::write(file, updated_yaml)?;
+            let added: Vec<_> = outcomes
+                .iter()
+                .filter(|outcome| outcome.changed)
+                .map(|outcome| entry_summary(&data, &outcome.chart, &outcome.version))
+                .collect();
+            args.summary.record(
+                "update",
+                file,
+                (entries_before, count_entries(&data)),
+                &added,
+                match (args.dry_run, changed) {
+                    (true, _) => "dry-run",
+                    (false, true) => "changed",
+                    (false, false) => "unchanged",
+                },
+            )?;
 
-            println!("Added new entry to {}", file.display());
+            for outcome in &outcomes {
+                if !outcome.changed {
+                    output.say(format_args!(
+                        "{} {} is already present, no change",
+                        outcome.chart, outcome.version
+                    ));
+                    continue;
+                }
+                if args.dry_run {
+                    output.say(format_args!(
+                        "Would add {} {} to {}",
+                        outcome.chart,
+                        outcome.version,
+                        target.display()
+                    ));
+                } else if args.stdout {
+                    output.say(format_args!("Added {} {}", outcome.chart, outcome.version));
+                } else {
+                    output.say(format_args!(
+                        "Added {} {} to {}",
+                        outcome.chart,
+                        outcome.version,
+                        target.display()
+                    ));
+                }
+                if !outcome.pruned.is_empty() {
+                    output.say(format_args!(
+                        "Pruned {} versions: {}",
+                        outcome.chart,
+                        outcome.pruned.join(", ")
+                    ));
+                }
+                if let Some(max_entries) = options.max_entries {
+                    output.say(format_args!(
+                        "{} has {} of {} allowed versions",
+                        outcome.chart, outcome.total_versions, max_entries
+                    ));
+                }
+            }
+
+            if !failed.is_empty() {
+                for (entry, error) in &failed {
+                    eprintln!("error: {}: {}", entry, error);
+                }
+                failure = Some(format!(
+                    "{} of {} entries failed to be added",
+                    failed.len(),
+                    inputs.len()
+                ));
+            }
+
+            let mut result = match outcomes.as_slice() {
+                [outcome] if !args.continue_on_error => outcome.to_json(),
+                outcomes => json!({
+                    "action": "add",
+                    "changed": changed,
+                    "entries": outcomes.iter().map(AddOutcome::to_json).collect::<Vec<_>>(),
+                }),
+            };
+            // A new `repoName` or `indexUrl` rewrites the index even when
+            // the entry itself was already there
+            result["changed"] = changed.into();
+            if args.continue_on_error {
+                result["failed"] = failed
+                    .iter()
+                    .map(|(entry, error)| json!({ "entry": entry, "error": error }))
+                    .collect();
+            }
+            result
         }
-        Commands::Generate {} => {
+        Commands::Generate {
+            values,
+            interactive,
+        } => {
+            let mut constants = match values {
+                Some(path) => {
+                    let mut merged = serde_yaml::to_value(Constants::default())?;
+                    merge_values(
+                        &mut merged,
+                        serde_yaml::from_str(&fs::read_to_string(path)?)?,
+                    );
+                    serde_yaml::from_value(merged)?
+                }
+                None => Constants::default(),
+            };
+            let mut parameters = Parameters::default();
+            if *interactive {
+                if io::stdin().is_terminal() {
+                    prompt_templates(&mut constants, &mut parameters)?;
+                } else {
+                    warnings.warn("stdin isn't a terminal, writing the defaults without prompting");
+                }
+            }
+
             let mut file = File::create("index.yaml")?;
             let mut constants_file = File::create("constants.yaml")?;
             let mut parameters_file = File::create("parameters.yaml")?;
 
             let _ = file.write(serde_yaml::to_string(&ChartYaml::default())?.as_bytes());
-            let _ =
-                constants_file.write(serde_yaml::to_string(&Constants::default())?.as_bytes())?;
-            let _ =
-                parameters_file.write(serde_yaml::to_string(&Parameters::default())?.as_bytes())?;
-            println!("YAML templates generated");
+            let _ = constants_file.write(serde_yaml::to_string(&constants)?.as_bytes())?;
+            let _ = parameters_file.write(serde_yaml::to_string(&parameters)?.as_bytes())?;
+            output.say(format_args!("YAML templates generated"));
+
+            json!({
+                "action": "generate",
+                "changed": true,
+                "files": ["index.yaml", "constants.yaml", "parameters.yaml"],
+            })
         }
-    }
+        Commands::Index {
+            dir,
+            url,
+            merge,
+            out,
+            jsonl,
+        } => {
+            let mut data = match merge {
+                Some(merge) => load_index(
+                    merge.to_str().unwrap(),
+                    cli.tolerate_duplicate_keys,
+                    &mut warnings,
+                )?,
+                None => ChartYaml::default(),
+            };
 
-    Ok(())
-}
+            let url = output
+                .setting("--url", url.as_ref(), BASE_URL_VAR)
+                .ok_or_else(|| format!("--url is required unless ${} is set", BASE_URL_VAR))?;
+            let mut added = Vec::new();
+            let packages = package::find_packages(dir)?;
+            let progress = output.progress(packages.len());
+            for path in packages {
+                progress.inc(1);
+                let package = package::read_package(&path)?;
+                let chart = &package.metadata;
+                progress.set_message(format!("{} {}", chart.name, chart.version));
+                if has_version(&data, &chart.name, &chart.version) {
+                    continue;
+                }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::io::Write;
-    use tempfile::NamedTempFile;
+                let file_name = path.file_name().unwrap().to_string_lossy();
+                let package_url = format!("{}/{}", url.trim_end_matches('/'), file_name);
+                // Keep warnings from being drawn over by the bar
+                progress.suspend(|| {
+                    add_entry(
+                        &mut data,
+                        &package.constants(None),
+                        &package.parameters(vec![package_url]),
+                        &UpdateOptions::default(),
+                        &mut warnings,
+                    )
+                })?;
+                added.push((chart.name.clone(), chart.version.clone()));
+            }
+            progress.finish_and_clear();
 
-    fn create_test_constants() -> Constants {
-        Constants {
-            api_version: "v2".to_string(),
-            app_version: "1.0.0".to_string(),
-            description: "Test Chart".to_string(),
-            home: "https://example.com".to_string(),
-            icon: "https://example.com/icon.png".to_string(),
-            keywords: vec!["test".to_string(), "chart".to_string()],
-            maintainers: vec![Maintainer {
-                email: "test@example.com".to_string(),
-                name: "Test Maintainer".to_string(),
-                url: "https://example.com".to_string(),
-            }],
-            name: "test-chart".to_string(),
-            sources: vec!["https://github.com/test/chart".to_string()],
-            entry_type: "application".to_string(),
+            let out = out.clone().unwrap_or_else(|| dir.join("index.yaml"));
+            fs::write(&out, index_to_string(&data)?)?;
+            let entries: Vec<(&str, &str)> = added
+                .iter()
+                .map(|(c, v)| (c.as_str(), v.as_str()))
+                .collect();
+            jsonl.emit(&data, "add", &entries)?;
+            output.to_stderr |= jsonl.to_stdout();
+
+            output.say(format_args!(
+                "Added {} new entries to {}",
+                added.len(),
+                out.display()
+            ));
+            json!({
+                "action": "index",
+                "file": out,
+                "added": added.len(),
+                "changed": !added.is_empty(),
+            })
         }
-    }
+        Commands::Reconcile {
+            file,
+            dir,
+            url,
+            add_missing,
+        } => {
+            let mut data = load_index(
+                file.to_str().unwrap(),
+                cli.tolerate_duplicate_keys,
+                &mut warnings,
+            )?;
+            let url = match add_missing {
+                true => Some(
+                    output
+                        .setting("--url", url.as_ref(), BASE_URL_VAR)
+                        .ok_or_else(|| format!("--add-missing needs --url or ${}", BASE_URL_VAR))?,
+                ),
+                false => None,
+            };
+
+            let mut packaged = std::collections::BTreeSet::new();
+            let mut unindexed = Vec::new();
+            for path in package::find_packages(dir)? {
+                let package = package::read_package(&path)?;
+                let chart = &package.metadata;
+                packaged.insert(format!("{}@{}", chart.name, chart.version));
+                if !has_version(&data, &chart.name, &chart.version) {
+                    unindexed.push((path, package));
+                }
+            }
+            let orphaned: Vec<String> = entry_names(&data)
+                .into_iter()
+                .filter(|entry| !packaged.contains(entry))
+                .collect();
+
+            for entry in &orphaned {
+                output.say(format_args!("{}: no package in {}", entry, dir.display()));
+            }
+            let mut missing = Vec::new();
+            for (path, package) in &unindexed {
+                let chart = &package.metadata;
+                let entry = format!("{}@{}", chart.name, chart.version);
+                match &url {
+                    Some(url) => {
+                        let file_name = path.file_name().unwrap().to_string_lossy();
+                        let package_url = format!("{}/{}", url.trim_end_matches('/'), file_name);
+                        add_entry(
+                            &mut data,
+                            &package.constants(None),
+                            &package.parameters(vec![package_url]),
+                            &UpdateOptions::default(),
+                            &mut warnings,
+                        )?;
+                        output.say(format_args!("{}: added from {}", entry, path.display()));
+                    }
+                    None => output.say(format_args!(
+                        "{}: {} is missing from the index",
+                        entry,
+                        path.display()
+                    )),
+                }
+                missing.push(entry);
+            }
+            if *add_missing && !missing.is_empty() {
+                fs::write(file, index_to_string(&data)?)?;
+            }
 
-    fn create_test_parameters() -> Parameters {
-        Parameters {
-            app_version: Some("1.0.1".to_string()),
-            digest: "abc123".to_string(),
-            version: "0.1.0".to_string(),
-            urls: vec!["https://example.com/test-chart-0.1.0.tgz".to_string()],
+            let unresolved = orphaned.len() + if *add_missing { 0 } else { missing.len() };
+            if unresolved > 0 {
+                failure = Some(format!(
+                    "{} and {} are out of sync: {} unmatched entries or packages",
+                    file.display(),
+                    dir.display(),
+                    unresolved
+                ));
+            } else {
+                output.say(format_args!(
+                    "{} and {} are in sync",
+                    file.display(),
+                    dir.display()
+                ));
+            }
+            json!({
+                "action": "reconcile",
+                "file": file,
+                "orphaned": orphaned,
+                "missing": missing,
+                "added": *add_missing,
+                "changed": *add_missing && !missing.is_empty(),
+            })
         }
-    }
+        Commands::Remove {
+            file,
+            name,
+            version,
+            version_range,
+            yes,
+            allow_empty,
+            summary,
+        } => {
+            let mut data = load_index(
+                file.to_str().unwrap(),
+                cli.tolerate_duplicate_keys,
+                &mut warnings,
+            )?;
+            let before = data.clone();
 
-    #[test]
-    fn test_update_yaml_new_file() -> Result<(), Box<dyn std::error::Error>> {
-        let temp_file = NamedTempFile::new()?;
-        let file_path = temp_file.path().to_str().unwrap();
+            let removed = if let Some(range) = version_range {
+                let req = semver::VersionReq::parse(range)
+                    .map_err(|e| format!("Invalid version range '{}': {}", range, e))?;
+                let matching: Vec<&str> = chart_versions(&data, name)
+                    .into_iter()
+                    .filter(|v| semver::Version::parse(v).is_ok_and(|v| req.matches(&v)))
+                    .collect();
+                if matching.is_empty() {
+                    return Err(format!("No versions of '{}' match '{}'", name, range).into());
+                }
+                let prompt = format!("Remove {} {}?", name, matching.join(", "));
+                if !*yes && !confirm(&prompt)? {
+                    return Err("Aborted".into());
+                }
+                let matching: Vec<String> = matching.into_iter().map(str::to_string).collect();
+                remove_versions(&mut data, name, |v| matching.iter().any(|m| m == v))?
+            } else {
+                let version = version.as_deref().unwrap_or_default();
+                let removed = remove_versions(&mut data, name, |v| v == version)?;
+                if removed.is_empty() {
+                    return Err(format!("Version {} of '{}' not found", version, name).into());
+                }
+                removed
+            };
+            if !allow_empty {
+                ensure_not_empty(&data, file, "pass --allow-empty to proceed")?;
+            }
+            fs::write(file, index_to_string(&data)?)?;
+            let entries: Vec<_> = removed
+                .iter()
+                .map(|version| entry_summary(&before, name, version))
+                .collect();
+            summary.record(
+                "remove",
+                file,
+                (count_entries(&before), count_entries(&data)),
+                &entries,
+                "changed",
+            )?;
+
+            for version in &removed {
+                output.say(format_args!(
+                    "Removed {} {} from {}",
+                    name,
+                    version,
+                    file.display()
+                ));
+            }
+            json!({
+                "action": "remove",
+                "chart": name,
+                "removed": removed,
+                "changed": true,
+            })
+        }
+        Commands::List {
+            file,
+            filter,
+            table,
+            no_table,
+        } => {
+            let mut data = load_index(
+                file.to_str().unwrap(),
+                cli.tolerate_duplicate_keys,
+                &mut warnings,
+            )?;
+            filter.filter().retain(&mut data.entries);
+            let table = *table && !no_table && io::stdout().is_terminal();
+
+            let mut charts = serde_json::Map::new();
+            let mut rows = Vec::new();
+            for (name, entries) in &data.entries {
+                let Some(name) = name.as_str() else {
+                    continue;
+                };
+                let versions = chart_versions(&data, name);
+                if table {
+                    let field = |entry: &Value, key: &str| {
+                        entry
+                            .get(key)
+                            .and_then(Value::as_str)
+                            .unwrap_or("")
+                            .to_string()
+                    };
+                    for entry in entries.as_sequence().into_iter().flatten() {
+                        let digest = field(entry, "digest");
+                        rows.push(vec![
+                            name.to_string(),
+                            field(entry, "version"),
+                            field(entry, "created"),
+                            digest.chars().take(12).collect(),
+                            field(entry, "appVersion"),
+                        ]);
+                    }
+                } else {
+                    output.say(format_args!("{}: {}", name, versions.join(", ")));
+                }
+                charts.insert(name.to_string(), json!(versions));
+            }
+            if table {
+                let width = console::Term::stdout()
+                    .size_checked()
+                    .map(|(_, w)| w.into());
+                output.say(format_args!(
+                    "{}",
+                    table::render(
+                        &["Chart", "Version", "Created", "Digest", "App Version"],
+                        &rows,
+                        width
+                    )
+                ));
+            }
+            json!({
+                "action": "list",
+                "charts": charts,
+                "changed": false,
+            })
+        }
+        Commands::Prune {
+            file,
+            keep,
+            keep_latest_stable,
+            allow_empty,
+            filter,
+            summary,
+        } => {
+            let mut data = load_index(
+                file.to_str().unwrap(),
+                cli.tolerate_duplicate_keys,
+                &mut warnings,
+            )?;
+            let before = data.clone();
+            let removed = prune_index(
+                &mut data,
+                *keep,
+                &filter.filter(),
+                *keep_latest_stable,
+                &mut warnings,
+            )?;
+            if !removed.is_empty() {
+                if !allow_empty {
+                    ensure_not_empty(&data, file, "pass --allow-empty to proceed")?;
+                }
+                fs::write(file, index_to_string(&data)?)?;
+            }
+            let entries: Vec<_> = removed
+                .iter()
+                .filter_map(|entry| entry.rsplit_once('@'))
+                .map(|(chart, version)| entry_summary(&before, chart, version))
+                .collect();
+            summary.record(
+                "prune",
+                file,
+                (count_entries(&before), count_entries(&data)),
+                &entries,
+                if removed.is_empty() {
+                    "unchanged"
+                } else {
+                    "changed"
+                },
+            )?;
+
+            for entry in &removed {
+                output.say(format_args!("Pruned {}", entry));
+            }
+            output.say(format_args!(
+                "Removed {} entries from {}",
+                removed.len(),
+                file.display()
+            ));
+            json!({
+                "action": "prune",
+                "file": file,
+                "removed": removed,
+                "changed": !removed.is_empty(),
+            })
+        }
+        Commands::Patch {
+            file,
+            name,
+            version,
+            constants,
+        } => {
+            let mut data = load_index(
+                file.to_str().unwrap(),
+                cli.tolerate_duplicate_keys,
+                &mut warnings,
+            )?;
+            let patch: Value = serde_yaml::from_str(&fs::read_to_string(constants)?)?;
+            let patched = patch_entries(&mut data, name, version.as_deref(), &patch)?;
+            fs::write(file, index_to_string(&data)?)?;
 
-        let constants = create_test_constants();
-        let parameters = create_test_parameters();
+            output.say(format_args!(
+                "Patched {} {} in {}",
+                name,
+                patched.join(", "),
+                file.display()
+            ));
+            json!({
+                "action": "patch",
+                "chart": name,
+                "patched": patched,
+                "changed": true,
+            })
+        }
+        Commands::Dedup { file, strict } => {
+            // Duplicate chart keys are the same kind of corruption, so merge
+            // them rather than refusing the file
+            let mut data = load_index(file.to_str().unwrap(), true, &mut warnings)?;
+            let removed = dedup_entries(&mut data, *strict, &mut warnings)?;
+            if !removed.is_empty() {
+                fs::write(file, index_to_string(&data)?)?;
+            }
 
-        let updated_yaml = update_yaml(file_path, &constants, &parameters)?;
-        let parsed: ChartYaml = serde_yaml::from_str(&updated_yaml)?;
+            output.say(format_args!(
+                "Removed {} duplicate entries from {}",
+                removed.len(),
+                file.display()
+            ));
+            json!({
+                "action": "dedup",
+                "file": file,
+                "removed": removed,
+                "changed": !removed.is_empty(),
+            })
+        }
+        Commands::Sort {
+            file,
+            ascending,
+            normalize_version,
+        } => {
+            let mut data = load_index(
+                file.to_str().unwrap(),
+                cli.tolerate_duplicate_keys,
+                &mut warnings,
+            )?;
+            for (name, entries) in data.entries.iter_mut() {
+                let name = name.as_str().unwrap_or("<non-string key>");
+                let Value::Sequence(entries) = entries else {
+                    return Err(format!("Unexpected value type for entries of '{}'", name).into());
+                };
+                if *normalize_version {
+                    for version in normalize_entry_versions(entries) {
+                        warnings.warn(format!(
+                            "{}: rewrote version '{}' to '{}'",
+                            name,
+                            version,
+                            &version[1..]
+                        ));
+                    }
+                }
+                for version in sort_versions(entries, *ascending) {
+                    warnings.warn(format!(
+                        "{}: version '{}' is not valid semver, sorted last",
+                        name, version
+                    ));
+                }
+            }
+            fs::write(file, index_to_string(&data)?)?;
 
-        assert_eq!(parsed.api_version, Some("v1".to_string()));
-        assert_eq!(parsed.entries.len(), 1);
+            output.say(format_args!(
+                "Sorted {} charts in {}",
+                data.entries.len(),
+                file.display()
+            ));
+            json!({
+                "action": "sort",
+                "file": file,
+                "charts": data.entries.len(),
+                "changed": true,
+            })
+        }
+        Commands::Canonicalize { file } => {
+            let contents = fs::read_to_string(file)?;
+            let mut data = parse_index(&contents, cli.tolerate_duplicate_keys, &mut warnings)?;
+            for version in canonicalize(&mut data) {
+                warnings.warn(format!("{} is not valid semver, sorted last", version));
+            }
+            let canonical = index_to_string(&data)?;
+            let changed = canonical != contents;
+            if changed {
+                fs::write(file, &canonical)?;
+                output.say(format_args!("Rewrote {} in canonical form", file.display()));
+            } else {
+                output.say(format_args!("{} is already canonical", file.display()));
+            }
+            json!({
+                "action": "canonicalize",
+                "file": file,
+                "charts": data.entries.len(),
+                "changed": changed,
+            })
+        }
+        Commands::Rollback { file, backup_dir } => {
+            let backup = latest_backup(file, backup_dir.as_deref())?;
+            let restored = fs::read_to_string(&backup)?;
+            let after = entry_names(
+                &parse_index(&restored, cli.tolerate_duplicate_keys, &mut warnings)
+                    .map_err(|e| format!("{}: {}", backup.display(), e))?,
+            );
+            // A missing or corrupt index is what a rollback is for, so it only
+            // counts as having no entries
+            let current = fs::read_to_string(file).ok();
+            let before = match &current {
+                Some(current) => {
+                    match parse_index(current, cli.tolerate_duplicate_keys, &mut warnings) {
+                        Ok(data) => entry_names(&data),
+                        Err(e) => {
+                            warnings.warn(format!(
+                                "{} isn't a valid index ({}), restoring over it",
+                                file.display(),
+                                e
+                            ));
+                            std::collections::BTreeSet::new()
+                        }
+                    }
+                }
+                None => std::collections::BTreeSet::new(),
+            };
+            fs::write(file, &restored)?;
 
-        let entries = parsed
-            .entries
-            .get(&Value::String("test-chart".to_string()))
-            .unwrap();
-        let entries: Vec<ChartEntry> = serde_yaml::from_value(entries.clone())?;
-        assert_eq!(entries.len(), 1);
+            let removed: Vec<&String> = before.difference(&after).collect();
+            let added: Vec<&String> = after.difference(&before).collect();
+            output.say(format_args!(
+                "Restored {} from {}",
+                file.display(),
+                backup.display()
+            ));
+            for entry in &removed {
+                output.say(format_args!("  - {}", entry));
+            }
+            for entry in &added {
+                output.say(format_args!("  + {}", entry));
+            }
+            json!({
+                "action": "rollback",
+                "file": file,
+                "backup": backup,
+                "removed": removed,
+                "added": added,
+                "changed": current.as_deref() != Some(&restored),
+            })
+        }
+        Commands::Schema { which } => {
+            print!(
+                "{}",
+                match which {
+                    SchemaKind::Constants => verify::CONSTANTS_SCHEMA,
+                    SchemaKind::Parameters => verify::PARAMETERS_SCHEMA,
+                    SchemaKind::Index => verify::INDEX_SCHEMA,
+                }
+            );
+            return Ok(());
+        }
+        Commands::Touch { file } => {
+            let contents = fs::read_to_string(file)?;
+            // Only to reject a file that isn't an index
+            parse_index(&contents, cli.tolerate_duplicate_keys, &mut warnings)?;
+            let generated = format_timestamp(chrono::Utc::now(), DEFAULT_TIMESTAMP_FORMAT)?;
+            fs::write(file, touch_generated(&contents, &generated)?)?;
+            output.say(format_args!(
+                "Set generated to {} in {}",
+                generated,
+                file.display()
+            ));
+            json!({
+                "action": "touch",
+                "file": file,
+                "generated": generated,
+                "changed": true,
+            })
+        }
+        Commands::Verify(args) => {
+            let file = &args.file;
+            let mut index = load_index(
+                file.to_str().unwrap(),
+                cli.tolerate_duplicate_keys,
+                &mut warnings,
+            )?;
+            if let Some(code) = args
+                .ignore
+                .iter()
+                .find(|code| !verify::CODES.contains(&code.as_str()))
+            {
+                return Err(format!("Unknown finding code '{}'", code).into());
+            }
+            let mut findings = match &args.require_charts {
+                Some(path) => verify::check_required(
+                    &index,
+                    &verify::Requirement::parse_list(&fs::read_to_string(path)?)?,
+                ),
+                None => Vec::new(),
+            };
+            if let Some(path) = &args.yanked_file {
+                findings.extend(verify::check_yanked(
+                    &index,
+                    &verify::Yanked::parse_list(&fs::read_to_string(path)?)?,
+                ));
+            }
+            let filter = args.filter.filter();
+            filter.retain(&mut index.entries);
+            findings.extend(verify::verify_with(&index, args.strict));
+            if !args.allowed_hosts.is_empty() {
+                findings.extend(verify::check_hosts(&index, &args.allowed_hosts));
+            }
+            if args.require_type {
+                findings.extend(verify::check_types(&index));
+            }
+            let completeness = if args.completeness || args.warn_missing_icon {
+                verify::completeness(&index)
+            } else {
+                Vec::new()
+            };
+            for score in &completeness {
+                findings.extend(score.findings(args.min_completeness, args.warn_missing_icon));
+                if args.completeness {
+                    match score.missing.as_slice() {
+                        [] => output.say(format_args!("{}: {}%", score.chart, score.score)),
+                        missing => output.say(format_args!(
+                            "{}: {}% (missing {})",
+                            score.chart,
+                            score.score,
+                            missing.join(", ")
+                        )),
+                    }
+                }
+            }
+            if args.check_links {
+                findings.extend(check_links(&index, args)?);
+            }
+            if args.validate_digests {
+                findings.extend(validate_digests(&index, args)?);
+            }
+            findings.retain(|finding| !args.ignore.iter().any(|code| code == finding.code));
+            let mut report = verify::Report::from(findings.clone());
+            if args.checksum {
+                let path = checksum_path(file);
+                let contents = fs::read_to_string(&path)
+                    .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+                let expected = contents.split_whitespace().next().unwrap_or_default();
+                let actual = package::digest(&fs::read(file)?);
+                if !expected.eq_ignore_ascii_case(&actual) {
+                    report.errors.push(format!(
+                        "checksum mismatch: {} records {}, file hashes to {}",
+                        path.display(),
+                        expected,
+                        actual
+                    ));
+                }
+            }
+            if args.against_schema || args.schema.is_some() {
+                let schema = match &args.schema {
+                    Some(path) => fs::read_to_string(path)?,
+                    None => verify::INDEX_SCHEMA.to_string(),
+                };
+                let mut raw: Value = serde_yaml::from_str(&fs::read_to_string(file)?)?;
+                if let Some(Value::Mapping(entries)) = raw.get_mut("entries") {
+                    filter.retain(entries);
+                }
+                report.errors.extend(verify::validate_against_schema(
+                    &raw,
+                    &serde_json::from_str(&schema)?,
+                )?);
+            }
+            for warning in &report.warnings {
+                warnings.warn(warning.clone());
+            }
+            if output.format == OutputFormat::Text {
+                for error in &report.errors {
+                    eprintln!("error: {}", error);
+                }
+            }
+            if report.is_valid() {
+                output.say(format_args!("{} is valid", file.display()));
+            } else {
+                failure = Some(format!("{} failed verification", file.display()));
+            }
 
-        let entry = &entries[0];
-        assert_eq!(entry.api_version, "v2");
-        assert_eq!(entry.app_version, "1.0.1");
-        assert_eq!(entry.description, "Test Chart");
-        assert_eq!(entry.digest, "abc123");
-        assert_eq!(entry.version, "0.1.0");
+            json!({
+                "action": "verify",
+                "file": file,
+                "valid": report.is_valid(),
+                "errors": report.errors,
+                "findings": findings,
+                "completeness": args.completeness.then_some(completeness),
+                "changed": false,
+            })
+        }
+        Commands::Show {
+            file,
+            name,
+            version,
+        } => {
+            let data = load_index(
+                file.to_str().unwrap(),
+                cli.tolerate_duplicate_keys,
+                &mut warnings,
+            )?;
+            let entry =
+                find_entry(&data, name, version.as_deref()).ok_or_else(|| match version {
+                    Some(version) => format!("Version {} of '{}' not found", version, name),
+                    None => format!("No semver versions of '{}' found", name),
+                })?;
+            // The entry is the result itself, so --quiet doesn't hide it
+            if output.format == OutputFormat::Text {
+                print!("{}", serde_yaml::to_string(entry)?);
+            }
+
+            json!({
+                "action": "show",
+                "chart": name,
+                "entry": entry,
+                "changed": false,
+            })
+        }
+        Commands::Digest { package } => {
+            let digest = package::digest(
+                &package::read_bytes(package)
+                    .map_err(|e| format!("Failed to read {}: {}", package.display(), e))?,
+            );
+            // The digest is the result itself, so --quiet doesn't hide it
+            if output.format == OutputFormat::Text {
+                println!("{}", digest);
+            }
+
+            json!({
+                "action": "digest",
+                "package": package,
+                "digest": digest,
+                "changed": false,
+            })
+        }
+        #[cfg(feature = "server")]
+        Commands::Serve { port, bind } => {
+            server::serve(bind, *port)?;
+            json!({ "action": "serve", "changed": false })
+        }
+    };
+
+    if !warnings.is_empty() {
+        eprintln!("{} warning(s) emitted", warnings.len());
+        if cli.fail_on_warnings && failure.is_none() {
+            failure = Some("Warnings are fatal (--fail-on-warnings)".to_string());
+        }
+    }
+    if output.format == OutputFormat::Json {
+        let mut result = result;
+        result["warnings"] = json!(warnings.messages);
+        output.emit(&result);
+    }
+    if let Some(failure) = failure {
+        return Err(failure.into());
+    }
+
+    Ok(())
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cli_definition() {
+        // Catches clashing short flags, e.g. a global one shadowing `-v`
+        <Cli as clap::CommandFactory>::command().debug_assert();
+    }
+
+    #[test]
+    fn test_latest_backup() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempfile::tempdir()?;
+        let file = dir.path().join("index.yaml");
+        assert!(latest_backup(&file, None).is_err());
+
+        for name in [
+            "index.yaml.bak.20260101T000000.000Z",
+            "index.yaml.bak.20260102T000000.000Z",
+            "other.yaml.bak.20260103T000000.000Z",
+        ] {
+            fs::write(dir.path().join(name), "")?;
+        }
+        assert_eq!(
+            latest_backup(&file, None)?,
+            dir.path().join("index.yaml.bak.20260102T000000.000Z")
+        );
+        assert!(backup_path(&file, None)? > latest_backup(&file, None)?);
 
         Ok(())
     }
 
     #[test]
-    fn test_update_yaml_existing_file() -> Result<(), Box<dyn std::error::Error>> {
-        let mut temp_file = NamedTempFile::new()?;
+    fn test_preserve_existing_order() -> Result<(), Box<dyn std::error::Error>> {
+        let cli = Cli::try_parse_from([
+            "helm_repo_updater",
+            "update",
+            "-f",
+            "index.yaml",
+            "-c",
+            "constants.yaml",
+            "-p",
+            "parameters.yaml",
+            "--preserve-existing-order",
+        ])?;
+        let Commands::Update(args) = cli.command else {
+            panic!("expected the update command");
+        };
+        assert!(Cli::try_parse_from([
+            "helm_repo_updater",
+            "update",
+            "-f",
+            "index.yaml",
+            "-c",
+            "constants.yaml",
+            "-p",
+            "parameters.yaml",
+            "--preserve-existing-order",
+            "--sort",
+        ])
+        .is_err());
 
-        let initial_content = r#"
+        let mut data: ChartYaml = serde_yaml::from_str(
+            r#"
 apiVersion: v1
 entries:
   test-chart:
-    - apiVersion: v2
-      appVersion: 1.0.0
-      created: "2023-01-01T00:00:00.000Z"
-      description: Initial Test Chart
-      digest: def456
-      home: https://example.com
-      icon: https://example.com/icon.png
-      keywords:
-        - test
-        - chart
-      maintainers:
-        - email: test@example.com
-          name: Test Maintainer
-          url: https://example.com
-      name: test-chart
-      sources:
-        - https://github.com/test/chart
-      type: application
-      urls:
-        - https://example.com/test-chart-0.0.1.tgz
-      version: 0.0.1
-"#;
-        write!(temp_file, "{}", initial_content)?;
-        let file_path = temp_file.path().to_str().unwrap();
-
-        let constants = create_test_constants();
-        let parameters = create_test_parameters();
-
-        let updated_yaml = update_yaml(file_path, &constants, &parameters)?;
-        let parsed: ChartYaml = serde_yaml::from_str(&updated_yaml)?;
-
-        assert_eq!(parsed.api_version, Some("v1".to_string()));
-        assert_eq!(parsed.entries.len(), 1);
-
-        let entries = parsed
-            .entries
-            .get(&Value::String("test-chart".to_string()))
-            .unwrap();
-        let entries: Vec<ChartEntry> = serde_yaml::from_value(entries.clone())?;
-        assert_eq!(entries.len(), 2);
-
-        let new_entry = &entries[1];
-        assert_eq!(new_entry.api_version, "v2");
-        assert_eq!(new_entry.app_version, "1.0.1");
-        assert_eq!(new_entry.description, "Test Chart");
-        assert_eq!(new_entry.digest, "abc123");
-        assert_eq!(new_entry.version, "0.1.0");
+    - version: 0.2.0
+    - version: 0.3.0
+    - version: 0.0.1
+"#,
+        )?;
+        add_entry(
+            &mut data,
+            &Constants::default(),
+            &Parameters::default(),
+            &args.options()?,
+            &mut Warnings::default(),
+        )?;
+        let versions = chart_versions(&data, "test-chart");
+        assert_eq!(versions, ["0.2.0", "0.3.0", "0.0.1", "0.1.0"]);
 
         Ok(())
     }
@@ -0,0 +1,216 @@
+use helm_repo_updater::package::{self, ChartPackage};
+use serde::Deserialize;
+use std::{collections::BTreeMap, io::Read};
+
+/// Media type of the layer holding the packaged chart
+const CHART_LAYER: &str = "application/vnd.cncf.helm.chart.content.v1.tar+gzip";
+const MANIFEST: &str = "application/vnd.oci.image.manifest.v1+json";
+
+/// An `oci://registry/repository:tag` chart reference
+#[derive(Debug, PartialEq)]
+pub struct Reference {
+    pub registry: String,
+    pub repository: String,
+    pub tag: String,
+}
+
+impl Reference {
+    pub fn parse(url: &str) -> Option<Self> {
+        let (registry, rest) = url.strip_prefix("oci://")?.split_once('/')?;
+        let (repository, tag) = rest.rsplit_once(':')?;
+        if registry.is_empty() || repository.is_empty() || tag.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            registry: registry.to_string(),
+            repository: repository.to_string(),
+            // OCI tags can't contain `+`, so Helm pushes `1.2.3+build` as `1.2.3_build`
+            tag: tag.replace('+', "_"),
+        })
+    }
+
+    fn url(&self, kind: &str, name: &str) -> String {
+        format!(
+            "https://{}/v2/{}/{}/{}",
+            self.registry, self.repository, kind, name
+        )
+    }
+
+    /// Where the chart layer can be downloaded over plain HTTP
+    pub fn blob_url(&self, digest: &str) -> String {
+        self.url("blobs", &format!("sha256:{}", digest))
+    }
+}
+
+#[derive(Deserialize)]
+struct Manifest {
+    layers: Vec<Descriptor>,
+}
+
+#[derive(Deserialize)]
+struct Descriptor {
+    #[serde(rename = "mediaType")]
+    media_type: String,
+    digest: String,
+}
+
+/// A token server's response; Docker Hub and distribution send both fields
+#[derive(Deserialize)]
+struct Token {
+    token: Option<String>,
+    access_token: Option<String>,
+}
+
+/// Reads the bearer token out of a token server's JSON response
+fn parse_token(body: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let token: Token = serde_json::from_str(body)?;
+    token
+        .token
+        .or(token.access_token)
+        .ok_or_else(|| "the token server's response has no token".into())
+}
+
+/// Parses the parameters of a `Bearer realm="...",service="...",scope="..."`
+/// challenge
+fn parse_challenge(header: &str) -> Option<BTreeMap<String, String>> {
+    let params = header.strip_prefix("Bearer ")?;
+    let mut parsed = BTreeMap::new();
+    for param in params.split(',') {
+        let (key, value) = param.trim().split_once('=')?;
+        parsed.insert(key.to_string(), value.trim_matches('"').to_string());
+    }
+    Some(parsed)
+}
+
+fn describe(error: ureq::Error) -> String {
+    match error {
+        ureq::Error::Status(code, response) => {
+            let body = response.into_string().unwrap_or_default();
+            format!("registry returned {}: {}", code, body.trim())
+        }
+        error => error.to_string(),
+    }
+}
+
+/// A registry session, holding the anonymous pull token once one was needed
+struct Client {
+    token: Option<String>,
+}
+
+impl Client {
+    fn get(
+        &mut self,
+        url: &str,
+        accept: &str,
+    ) -> Result<ureq::Response, Box<dyn std::error::Error>> {
+        let request = |token: &Option<String>| {
+            let request = ureq::get(url).set("Accept", accept);
+            match token {
+                Some(token) => request.set("Authorization", &format!("Bearer {}", token)),
+                None => request,
+            }
+        };
+
+        match request(&self.token).call() {
+            Ok(response) => Ok(response),
+            Err(ureq::Error::Status(401, response)) if self.token.is_none() => {
+                let challenge = response
+                    .header("www-authenticate")
+                    .and_then(parse_challenge)
+                    .ok_or("registry requires authentication it didn't describe")?;
+                let realm = challenge
+                    .get("realm")
+                    .ok_or("auth challenge has no realm")?;
+                let mut token_request = ureq::get(realm);
+                for key in ["service", "scope"] {
+                    if let Some(value) = challenge.get(key) {
+                        token_request = token_request.query(key, value);
+                    }
+                }
+                let body = token_request.call().map_err(describe)?.into_string()?;
+                self.token = Some(parse_token(&body)?);
+
+                request(&self.token).call().map_err(|e| describe(e).into())
+            }
+            Err(e) => Err(describe(e).into()),
+        }
+    }
+}
+
+/// Pulls a chart's manifest and chart layer, verifying the layer against its
+/// manifest digest, and reads the chart metadata from it
+pub fn pull(reference: &Reference) -> Result<ChartPackage, Box<dyn std::error::Error>> {
+    let mut client = Client { token: None };
+    let manifest: Manifest = serde_json::from_str(
+        &client
+            .get(&reference.url("manifests", &reference.tag), MANIFEST)?
+            .into_string()?,
+    )?;
+    let layer = manifest
+        .layers
+        .iter()
+        .find(|layer| layer.media_type == CHART_LAYER)
+        .ok_or("the manifest has no Helm chart layer")?;
+
+    let mut bytes = Vec::new();
+    client
+        .get(&reference.url("blobs", &layer.digest), CHART_LAYER)?
+        .into_reader()
+        .read_to_end(&mut bytes)?;
+    let chart = package::read_archive(&bytes)?;
+    if layer.digest != format!("sha256:{}", chart.digest) {
+        return Err(format!(
+            "chart layer digest is sha256:{}, the manifest says {}",
+            chart.digest, layer.digest
+        )
+        .into());
+    }
+
+    Ok(chart)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reference_parse() {
+        let reference = Reference::parse("oci://ghcr.io/acme/charts/mychart:1.2.3+build.4");
+        assert_eq!(
+            reference,
+            Some(Reference {
+                registry: "ghcr.io".to_string(),
+                repository: "acme/charts/mychart".to_string(),
+                tag: "1.2.3_build.4".to_string(),
+            })
+        );
+        assert_eq!(
+            reference.unwrap().blob_url("abc"),
+            "https://ghcr.io/v2/acme/charts/mychart/blobs/sha256:abc"
+        );
+        assert_eq!(Reference::parse("oci://ghcr.io/mychart"), None);
+        assert_eq!(Reference::parse("https://ghcr.io/mychart:1.0.0"), None);
+    }
+
+    #[test]
+    fn test_parse_challenge() {
+        let challenge = parse_challenge(
+            r#"Bearer realm="https://ghcr.io/token",service="ghcr.io",scope="repository:acme/mychart:pull""#,
+        )
+        .unwrap();
+        assert_eq!(challenge["realm"], "https://ghcr.io/token");
+        assert_eq!(challenge["scope"], "repository:acme/mychart:pull");
+    }
+
+    #[test]
+    fn test_parse_token() {
+        assert_eq!(
+            parse_token(r#"{"token": "abc", "access_token": "abc", "expires_in": 300}"#).unwrap(),
+            "abc"
+        );
+        assert_eq!(parse_token(r#"{"access_token": "def"}"#).unwrap(), "def");
+        assert_eq!(parse_token(r#"{"token": "ghi"}"#).unwrap(), "ghi");
+        assert!(parse_token(r#"{"expires_in": 300}"#).is_err());
+    }
+}
@@ -0,0 +1,486 @@
+use chrono::{DateTime, Utc};
+use flate2::read::GzDecoder;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::{collections::BTreeMap, io::Read, path::PathBuf};
+#[cfg(feature = "io")]
+use std::{fs, path::Path};
+
+use crate::{Constants, Maintainer, Parameters};
+
+/// The subset of a chart's `Chart.yaml` used to build index entries
+#[derive(Debug, Deserialize)]
+pub struct ChartMetadata {
+    #[serde(rename = "apiVersion")]
+    pub api_version: String,
+    #[serde(rename = "appVersion")]
+    pub app_version: Option<String>,
+    pub description: Option<String>,
+    pub home: Option<String>,
+    pub icon: Option<String>,
+    #[serde(default)]
+    pub keywords: Vec<String>,
+    #[serde(default)]
+    pub maintainers: Vec<Maintainer>,
+    pub name: String,
+    #[serde(default)]
+    pub sources: Vec<String>,
+    #[serde(rename = "type")]
+    pub chart_type: Option<String>,
+    pub version: String,
+    #[serde(default)]
+    pub annotations: BTreeMap<String, String>,
+}
+
+/// Prefix of Chart.yaml annotations carrying index metadata, e.g. `hru.io/home`
+pub const HINT_PREFIX: &str = "hru.io/";
+
+/// A packaged chart (`.tgz`) along with its digest
+#[derive(Debug)]
+pub struct ChartPackage {
+    pub digest: String,
+    pub metadata: ChartMetadata,
+    /// Newest modification time of the files in the package, or `None` when
+    /// they're all zeroed (as reproducible builds do)
+    pub built: Option<DateTime<Utc>>,
+    /// Size of the package in bytes
+    pub size: u64,
+}
+
+/// One package listed in a `--package-manifest` file
+#[derive(Debug, Deserialize)]
+pub struct ManifestItem {
+    pub package: PathBuf,
+    pub url: String,
+}
+
+/// Computes the SHA-256 digest of a package the way Helm records it in the index
+pub fn digest(bytes: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(bytes))
+}
+
+/// Reads a package file, or the whole of stdin when `path` is `-`, so a
+/// piped package can be digested and unpacked from the same buffer
+#[cfg(feature = "io")]
+pub fn read_bytes(path: &Path) -> std::io::Result<Vec<u8>> {
+    if path != Path::new("-") {
+        return fs::read(path);
+    }
+    let mut bytes = Vec::new();
+    std::io::stdin().lock().read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+#[cfg(feature = "io")]
+pub fn read_package(path: &Path) -> Result<ChartPackage, Box<dyn std::error::Error>> {
+    let bytes = read_bytes(path)?;
+    read_archive(&bytes)
+        .map_err(|e| format!("Failed to read Chart.yaml from {}: {}", path.display(), e).into())
+}
+
+/// Reads `Chart.yaml` and the newest file modification time from a package.
+/// The archive is streamed from memory and nothing is extracted to disk, so
+/// a failed read leaves no temporary files behind, however large the chart.
+pub fn read_archive(bytes: &[u8]) -> Result<ChartPackage, Box<dyn std::error::Error>> {
+    let mut archive = tar::Archive::new(GzDecoder::new(bytes));
+    let mut metadata = None;
+    let mut newest = 0;
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        newest = newest.max(entry.header().mtime()?);
+        let path = entry.path()?;
+        // Helm packages contain a single top-level directory named after the chart
+        let is_chart_yaml =
+            path.components().count() == 2 && path.file_name() == Some("Chart.yaml".as_ref());
+
+        if is_chart_yaml && metadata.is_none() {
+            let mut contents = String::new();
+            entry.read_to_string(&mut contents)?;
+            metadata = Some(serde_yaml::from_str(&contents)?);
+        }
+    }
+
+    let metadata = metadata.ok_or("no Chart.yaml found")?;
+    let built = i64::try_from(newest)
+        .ok()
+        .filter(|&secs| secs > 0)
+        .and_then(|secs| DateTime::from_timestamp(secs, 0));
+
+    Ok(ChartPackage {
+        digest: digest(bytes),
+        metadata,
+        built,
+        size: bytes.len() as u64,
+    })
+}
+
+/// Per-chart default constants looked for in an unpackaged chart directory,
+/// layered beneath any `--constants` files
+pub const CHART_CONSTANTS_FILE: &str = "index-constants.yaml";
+
+/// Reads `Chart.yaml` from an unpackaged chart directory
+#[cfg(feature = "io")]
+pub fn read_chart_dir(dir: &Path) -> Result<ChartMetadata, Box<dyn std::error::Error>> {
+    let path = dir.join("Chart.yaml");
+    let contents = fs::read_to_string(&path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    serde_yaml::from_str(&contents).map_err(|e| format!("{}: {}", path.display(), e).into())
+}
+
+/// Lists the `.tgz` packages directly inside `dir`, sorted by file name
+#[cfg(feature = "io")]
+pub fn find_packages(dir: &Path) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+    let mut packages = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_file() && path.extension().is_some_and(|ext| ext == "tgz") {
+            packages.push(path);
+        }
+    }
+    packages.sort();
+
+    Ok(packages)
+}
+
+/// Reads a package manifest. Relative package paths are resolved against the
+/// manifest's directory.
+#[cfg(feature = "io")]
+pub fn read_manifest(path: &Path) -> Result<Vec<ManifestItem>, Box<dyn std::error::Error>> {
+    let mut items: Vec<ManifestItem> = serde_yaml::from_str(&fs::read_to_string(path)?)?;
+    let base = path.parent().unwrap_or(Path::new(""));
+
+    let stdin = Path::new("-");
+    if items.iter().filter(|item| item.package == stdin).count() > 1 {
+        return Err(format!("{} reads more than one package from stdin", path.display()).into());
+    }
+    for item in &mut items {
+        if item.package.is_relative() && item.package != stdin {
+            item.package = base.join(&item.package);
+        }
+    }
+
+    Ok(items)
+}
+
+impl ChartMetadata {
+    /// Builds the entry constants from Chart.yaml, using `defaults` for any
+    /// fields the chart doesn't set
+    pub fn constants(&self, defaults: Option<&Constants>) -> Constants {
+        let fallback = |value: &Option<String>, default: fn(&Constants) -> &String| {
+            value
+                .clone()
+                .or_else(|| defaults.map(|c| default(c).clone()))
+                .unwrap_or_default()
+        };
+        let fallback_list =
+            |value: &Vec<String>, default: fn(&Constants) -> &Vec<String>| match defaults {
+                Some(c) if value.is_empty() => default(c).clone(),
+                _ => value.clone(),
+            };
+
+        Constants {
+            api_version: self.api_version.clone(),
+            app_version: fallback(&self.app_version, |c| &c.app_version),
+            description: fallback(&self.description, |c| &c.description),
+            home: fallback(&self.home, |c| &c.home),
+            icon: fallback(&self.icon, |c| &c.icon),
+            keywords: fallback_list(&self.keywords, |c| &c.keywords),
+            maintainers: match defaults {
+                Some(c) if self.maintainers.is_empty() => c.maintainers.clone(),
+                _ => self.maintainers.clone(),
+            },
+            name: self.name.clone(),
+            sources: fallback_list(&self.sources, |c| &c.sources),
+            entry_type: self
+                .chart_type
+                .clone()
+                .or_else(|| defaults.map(|c| c.entry_type.clone()))
+                .unwrap_or_else(|| "application".to_string()),
+            sign_key: defaults.and_then(|c| c.sign_key.clone()),
+            annotations: defaults.and_then(|c| c.annotations.clone()),
+        }
+    }
+
+    /// Fails when both Chart.yaml and `defaults` set a chart type and they
+    /// disagree, e.g. a `library` chart with stale `application` constants
+    pub fn check_type(
+        &self,
+        defaults: Option<&Constants>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if let (Some(chart_type), Some(defaults)) = (&self.chart_type, defaults) {
+            if *chart_type != defaults.entry_type {
+                return Err(format!(
+                    "{}: Chart.yaml type '{}' conflicts with constants type '{}' \
+                     (pass --from-chart-wins to use the Chart.yaml type)",
+                    self.name, chart_type, defaults.entry_type
+                )
+                .into());
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ChartPackage {
+    /// Builds the entry constants from Chart.yaml; see [`ChartMetadata::constants`]
+    pub fn constants(&self, defaults: Option<&Constants>) -> Constants {
+        self.metadata.constants(defaults)
+    }
+
+    /// See [`ChartMetadata::check_type`]
+    pub fn check_type(
+        &self,
+        defaults: Option<&Constants>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.metadata.check_type(defaults)
+    }
+
+    /// Fills fields Chart.yaml leaves unset from `hru.io/<field>` annotations
+    /// (`home`, `icon`, `description`, `appVersion`, and comma-separated
+    /// `keywords` and `sources`), returning the fields that were filled
+    pub fn apply_annotation_hints(&mut self) -> Vec<&'static str> {
+        let chart = &mut self.metadata;
+        let hint = |field: &str| {
+            chart
+                .annotations
+                .get(&format!("{}{}", HINT_PREFIX, field))
+                .map(|value| value.trim().to_string())
+                .filter(|value| !value.is_empty())
+        };
+        let list = |value: String| -> Vec<String> {
+            value
+                .split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(str::to_string)
+                .collect()
+        };
+        let (home, icon, description, app_version, keywords, sources) = (
+            hint("home"),
+            hint("icon"),
+            hint("description"),
+            hint("appVersion"),
+            hint("keywords").map(list),
+            hint("sources").map(list),
+        );
+
+        let mut filled = Vec::new();
+        for (field, target, value) in [
+            ("home", &mut chart.home, home),
+            ("icon", &mut chart.icon, icon),
+            ("description", &mut chart.description, description),
+            ("appVersion", &mut chart.app_version, app_version),
+        ] {
+            if target.is_none() && value.is_some() {
+                *target = value;
+                filled.push(field);
+            }
+        }
+        for (field, target, value) in [
+            ("keywords", &mut chart.keywords, keywords),
+            ("sources", &mut chart.sources, sources),
+        ] {
+            if let (true, Some(value)) = (target.is_empty(), value) {
+                *target = value;
+                filled.push(field);
+            }
+        }
+
+        filled
+    }
+
+    pub fn parameters(&self, urls: Vec<String>) -> Parameters {
+        Parameters {
+            app_version: self.metadata.app_version.clone(),
+            digest: self.digest.clone(),
+            version: self.metadata.version.clone(),
+            urls,
+            digests: None,
+            chart: Some(self.metadata.name.clone()),
+            created: None,
+            url_integrity: None,
+            url_labels: None,
+            size: Some(self.size),
+            annotations: None,
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use flate2::{write::GzEncoder, Compression};
+
+    /// Builds an in-memory chart package containing only `<name>/Chart.yaml`
+    pub(crate) fn build_package(name: &str, chart_yaml: &str) -> Vec<u8> {
+        build_package_at(name, chart_yaml, 0)
+    }
+
+    /// Like `build_package`, with the file's modification time set to `mtime`
+    pub(crate) fn build_package_at(name: &str, chart_yaml: &str, mtime: u64) -> Vec<u8> {
+        let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+        let mut header = tar::Header::new_gnu();
+        header.set_size(chart_yaml.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(mtime);
+        header.set_cksum();
+        builder
+            .append_data(
+                &mut header,
+                format!("{}/Chart.yaml", name),
+                chart_yaml.as_bytes(),
+            )
+            .unwrap();
+        builder.into_inner().unwrap().finish().unwrap()
+    }
+
+    #[test]
+    fn test_read_package() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempfile::tempdir()?;
+        let bytes = build_package(
+            "mychart",
+            "apiVersion: v2\nname: mychart\nversion: 1.2.3\ndescription: My chart\n",
+        );
+        fs::write(dir.path().join("mychart-1.2.3.tgz"), &bytes)?;
+        fs::write(
+            dir.path().join("manifest.yaml"),
+            "- package: mychart-1.2.3.tgz\n  url: https://example.com/mychart-1.2.3.tgz\n",
+        )?;
+
+        let items = read_manifest(&dir.path().join("manifest.yaml"))?;
+        assert_eq!(items.len(), 1);
+
+        let package = read_package(&items[0].package)?;
+        assert_eq!(package.digest, digest(&bytes));
+        assert_eq!(package.digest.len(), 64);
+        assert_eq!(package.built, None);
+
+        let constants = package.constants(Some(&Constants::default()));
+        assert_eq!(constants.name, "mychart");
+        assert_eq!(constants.description, "My chart");
+        assert_eq!(constants.home, "https://example.com");
+        assert_eq!(package.parameters(vec![]).version, "1.2.3");
+
+        fs::write(
+            dir.path().join("stdin.yaml"),
+            "- package: '-'\n  url: https://example.com/a.tgz\n",
+        )?;
+        let items = read_manifest(&dir.path().join("stdin.yaml"))?;
+        assert_eq!(items[0].package, Path::new("-"));
+        fs::write(
+            dir.path().join("stdin.yaml"),
+            "- package: '-'\n  url: https://example.com/a.tgz\n\
+             - package: '-'\n  url: https://example.com/b.tgz\n",
+        )?;
+        assert!(read_manifest(&dir.path().join("stdin.yaml")).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_archive_errors() {
+        assert!(read_archive(&build_package("mychart", "name: [unclosed\n")).is_err());
+        assert!(read_archive(&build_package("mychart", "apiVersion: v2\n")).is_err());
+        assert!(read_archive(b"not a tarball").is_err());
+    }
+
+    #[test]
+    fn test_read_chart_dir() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempfile::tempdir()?;
+        assert!(read_chart_dir(dir.path()).is_err());
+
+        fs::write(
+            dir.path().join("Chart.yaml"),
+            "apiVersion: v2\nname: mychart\nversion: 1.2.3\n",
+        )?;
+        let chart = read_chart_dir(dir.path())?;
+        assert_eq!(
+            (chart.name.as_str(), chart.version.as_str()),
+            ("mychart", "1.2.3")
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_type() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("mylib-1.0.0.tgz");
+        fs::write(
+            &path,
+            build_package(
+                "mylib",
+                "apiVersion: v2\nname: mylib\nversion: 1.0.0\ntype: library\n",
+            ),
+        )?;
+        let package = read_package(&path)?;
+
+        assert!(package.check_type(None).is_ok());
+        let error = package.check_type(Some(&Constants::default())).unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("'library' conflicts with constants type 'application'"));
+        // Chart.yaml still wins when the check is skipped
+        assert_eq!(
+            package.constants(Some(&Constants::default())).entry_type,
+            "library"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_annotation_hints() -> Result<(), Box<dyn std::error::Error>> {
+        let mut package = read_archive(&build_package(
+            "mychart",
+            "apiVersion: v2\nname: mychart\nversion: 1.2.3\nhome: https://chart.example.com\n\
+             annotations:\n  hru.io/home: https://hint.example.com\n  \
+             hru.io/icon: https://example.com/icon.png\n  hru.io/keywords: cache, redis\n",
+        ))?;
+
+        assert_eq!(package.apply_annotation_hints(), ["icon", "keywords"]);
+        let constants = package.constants(None);
+        assert_eq!(constants.home, "https://chart.example.com");
+        assert_eq!(constants.icon, "https://example.com/icon.png");
+        assert_eq!(constants.keywords, ["cache", "redis"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_package_built() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("mychart-1.2.3.tgz");
+        fs::write(
+            &path,
+            build_package_at(
+                "mychart",
+                "apiVersion: v2\nname: mychart\nversion: 1.2.3\n",
+                1_700_000_000,
+            ),
+        )?;
+
+        let package = read_package(&path)?;
+        assert_eq!(
+            package.built.map(|built| built.to_rfc3339()),
+            Some("2023-11-14T22:13:20+00:00".to_string())
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_packages() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempfile::tempdir()?;
+        for name in ["b-1.0.0.tgz", "a-1.0.0.tgz", "index.yaml", "README.md"] {
+            fs::write(dir.path().join(name), "")?;
+        }
+
+        let packages = find_packages(dir.path())?;
+        let names: Vec<_> = packages.iter().map(|p| p.file_name().unwrap()).collect();
+        assert_eq!(names, ["a-1.0.0.tgz", "b-1.0.0.tgz"]);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,79 @@
+use std::io::Read;
+
+use tiny_http::{Header, Method, Response, Server};
+
+use helm_repo_updater::{parse_index, verify, Warnings};
+
+/// Largest request body accepted, in bytes; anything longer gets a 413
+const MAX_BODY: u64 = 16 * 1024 * 1024;
+
+/// Verifies a POSTed index, returning the HTTP status and JSON body to send
+fn handle(body: &str) -> (u16, String) {
+    let report = match parse_index(body, false, &mut Warnings::default()) {
+        Ok(index) => verify::Report::from(verify::verify(&index)),
+        Err(e) => verify::Report {
+            errors: vec![format!("Failed to parse index: {}", e)],
+            warnings: Vec::new(),
+        },
+    };
+    let status = if report.is_valid() { 200 } else { 422 };
+
+    (status, serde_json::to_string(&report).unwrap_or_default())
+}
+
+/// Serves verification on `bind`:`port` until the process is stopped; a
+/// client that goes away mid-response is logged and doesn't stop the server
+pub fn serve(bind: &str, port: u16) -> Result<(), Box<dyn std::error::Error>> {
+    let server = Server::http((bind, port)).map_err(|e| e.to_string())?;
+    let content_type = Header::from_bytes("Content-Type", "application/json").unwrap();
+    println!("Listening on {}:{}", bind, port);
+
+    for mut request in server.incoming_requests() {
+        let response = if *request.method() != Method::Post {
+            Response::from_string("").with_status_code(405)
+        } else {
+            let mut body = String::new();
+            let (status, json) = match request
+                .as_reader()
+                .take(MAX_BODY + 1)
+                .read_to_string(&mut body)
+            {
+                Ok(read) if read as u64 > MAX_BODY => (
+                    413,
+                    serde_json::json!({
+                        "errors": [format!("Request body is larger than {} bytes", MAX_BODY)]
+                    })
+                    .to_string(),
+                ),
+                Ok(_) => handle(&body),
+                Err(e) => (
+                    400,
+                    serde_json::json!({ "errors": [e.to_string()] }).to_string(),
+                ),
+            };
+            Response::from_string(json)
+                .with_status_code(status)
+                .with_header(content_type.clone())
+        };
+        if let Err(e) = request.respond(response) {
+            eprintln!("Failed to send a response: {}", e);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_handle() {
+        let (status, _) = handle("apiVersion: v1\nentries: {}\n");
+        assert_eq!(status, 200);
+
+        let (status, body) = handle("apiVersion: v1\nentries:\n  test-chart: {}\n");
+        assert_eq!(status, 422);
+        assert!(body.contains("entries must be a sequence"));
+    }
+}
@@ -0,0 +1,73 @@
+use console::{measure_text_width, truncate_str};
+
+/// Narrowest a column is shrunk to when the table doesn't fit
+const MIN_COLUMN_WIDTH: usize = 6;
+
+/// Renders a bordered table. With `max_width`, the widest columns are
+/// truncated (ending in `…`) until the table fits, if it can.
+pub fn render(headers: &[&str], rows: &[Vec<String>], max_width: Option<usize>) -> String {
+    let mut widths: Vec<usize> = headers.iter().map(|h| measure_text_width(h)).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(measure_text_width(cell));
+        }
+    }
+    if let Some(max_width) = max_width {
+        // Each column adds its padding and one border, plus the closing one
+        let borders = 3 * widths.len() + 1;
+        while widths.iter().sum::<usize>() + borders > max_width {
+            let widest = (0..widths.len()).max_by_key(|&i| widths[i]).unwrap_or(0);
+            if widths.get(widest).is_none_or(|&w| w <= MIN_COLUMN_WIDTH) {
+                break;
+            }
+            widths[widest] -= 1;
+        }
+    }
+
+    let rule = |left: &str, middle: &str, right: &str| {
+        let segments: Vec<String> = widths.iter().map(|w| "─".repeat(w + 2)).collect();
+        format!("{}{}{}", left, segments.join(middle), right)
+    };
+    let line = |cells: &[&str]| {
+        let cells: Vec<String> = widths
+            .iter()
+            .zip(cells.iter().chain(std::iter::repeat(&"")))
+            .map(|(&width, cell)| {
+                let cell = truncate_str(cell, width, "…");
+                let padding = width - measure_text_width(&cell);
+                format!(" {}{} ", cell, " ".repeat(padding))
+            })
+            .collect();
+        format!("│{}│", cells.join("│"))
+    };
+
+    let mut lines = vec![rule("┌", "┬", "┐"), line(headers), rule("├", "┼", "┤")];
+    for row in rows {
+        lines.push(line(&row.iter().map(String::as_str).collect::<Vec<_>>()));
+    }
+    lines.push(rule("└", "┴", "┘"));
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let rows = vec![vec!["web".to_string(), "1.2.3".to_string()]];
+        assert_eq!(
+            render(&["Chart", "Version"], &rows, None),
+            "┌───────┬─────────┐\n\
+             │ Chart │ Version │\n\
+             ├───────┼─────────┤\n\
+             │ web   │ 1.2.3   │\n\
+             └───────┴─────────┘"
+        );
+
+        let rows = vec![vec!["a-very-long-chart-name".to_string(), "1".to_string()]];
+        let table = render(&["Chart", "Version"], &rows, Some(24));
+        assert!(table.lines().all(|line| measure_text_width(line) <= 24));
+        assert!(table.contains("│ a-very-lo… │"));
+    }
+}
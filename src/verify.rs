@@ -0,0 +1,1309 @@
+use serde::Serialize;
+use serde_yaml::Value;
+use std::{collections::BTreeMap, fmt};
+
+use crate::{
+    bare_digest, find_entry, has_version, host_allowed, is_http_url, mixed_url_schemes, url_host,
+    urls_match_version, validate_hex, version_identity, ChartYaml,
+};
+
+/// The outcome of verifying an index
+#[derive(Debug, Default, Serialize)]
+pub struct Report {
+    pub errors: Vec<String>,
+    pub warnings: Vec<String>,
+}
+
+impl Report {
+    pub fn is_valid(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+/// Bundled JSON schema describing Helm's `index.yaml` format
+#[cfg(feature = "schema")]
+pub const INDEX_SCHEMA: &str = include_str!("index.schema.json");
+
+/// Bundled JSON schema of the constants files `update` reads
+#[cfg(feature = "schema")]
+pub const CONSTANTS_SCHEMA: &str = include_str!("constants.schema.json");
+
+/// Bundled JSON schema of the parameters files `update` reads
+#[cfg(feature = "schema")]
+pub const PARAMETERS_SCHEMA: &str = include_str!("parameters.schema.json");
+
+/// Validates a raw index document against a JSON schema, returning one message
+/// per violation prefixed with the JSON pointer of the offending value
+#[cfg(feature = "schema")]
+pub fn validate_against_schema(
+    index: &Value,
+    schema: &serde_json::Value,
+) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let validator =
+        jsonschema::validator_for(schema).map_err(|e| format!("Invalid schema: {}", e))?;
+    let instance = serde_json::to_value(index)?;
+
+    Ok(validator
+        .iter_errors(&instance)
+        .map(|error| {
+            let pointer = error.instance_path().to_string();
+            let pointer = if pointer.is_empty() { "/" } else { &pointer };
+            format!("schema: {}: {}", pointer, error)
+        })
+        .collect())
+}
+
+/// How serious a finding is: errors make the index invalid
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// Stable codes identifying each kind of finding, so they can be categorized
+/// or ignored
+pub const INVALID_VERSION: &str = "HRU001";
+pub const INVALID_DIGEST: &str = "HRU002";
+pub const DUPLICATE_VERSION: &str = "HRU003";
+pub const MISSING_DIGEST: &str = "HRU004";
+pub const NO_URLS: &str = "HRU005";
+pub const NON_HTTP_URL: &str = "HRU006";
+pub const URL_VERSION_MISMATCH: &str = "HRU007";
+pub const SHARED_DIGEST: &str = "HRU008";
+pub const ENTRIES_NOT_SEQUENCE: &str = "HRU009";
+pub const MISSING_VERSION: &str = "HRU010";
+pub const MISSING_REQUIRED_CHART: &str = "HRU011";
+pub const MIXED_URL_SCHEMES: &str = "HRU012";
+pub const YANKED_VERSION: &str = "HRU013";
+pub const DEAD_LINK: &str = "HRU014";
+pub const API_VERSION_MISMATCH: &str = "HRU015";
+pub const BUILD_METADATA_CONFLICT: &str = "HRU016";
+pub const SHARED_URL: &str = "HRU017";
+pub const INCOMPLETE_METADATA: &str = "HRU018";
+pub const MISSING_ICON: &str = "HRU019";
+pub const DIGEST_MISMATCH: &str = "HRU020";
+pub const DISALLOWED_HOST: &str = "HRU021";
+pub const MISSING_TYPE: &str = "HRU022";
+
+/// Every finding code
+pub const CODES: [&str; 22] = [
+    INVALID_VERSION,
+    INVALID_DIGEST,
+    DUPLICATE_VERSION,
+    MISSING_DIGEST,
+    NO_URLS,
+    NON_HTTP_URL,
+    URL_VERSION_MISMATCH,
+    SHARED_DIGEST,
+    ENTRIES_NOT_SEQUENCE,
+    MISSING_VERSION,
+    MISSING_REQUIRED_CHART,
+    MIXED_URL_SCHEMES,
+    YANKED_VERSION,
+    DEAD_LINK,
+    API_VERSION_MISMATCH,
+    BUILD_METADATA_CONFLICT,
+    SHARED_URL,
+    INCOMPLETE_METADATA,
+    MISSING_ICON,
+    DIGEST_MISMATCH,
+    DISALLOWED_HOST,
+    MISSING_TYPE,
+];
+
+/// Metadata fields catalogs such as Artifact Hub show, counted by
+/// [`completeness`]
+pub const RECOMMENDED_FIELDS: [&str; 5] = ["icon", "description", "keywords", "sources", "home"];
+
+/// Entry fields only chart API v2 has, which confuse older clients reading a
+/// `v1` index
+const V2_ONLY_FIELDS: [&str; 3] = ["type", "dependencies", "kubeVersion"];
+
+/// One problem found in an index
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Finding {
+    pub severity: Severity,
+    /// One of [`CODES`]
+    pub code: &'static str,
+    pub chart: String,
+    /// The offending entry's version, if the finding concerns a single entry
+    pub version: Option<String>,
+    pub message: String,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.version {
+            Some(version) => write!(
+                f,
+                "{} {}@{}: {}",
+                self.code, self.chart, version, self.message
+            ),
+            None => write!(f, "{} {}: {}", self.code, self.chart, self.message),
+        }
+    }
+}
+
+impl From<Vec<Finding>> for Report {
+    fn from(findings: Vec<Finding>) -> Self {
+        let mut report = Report::default();
+        for finding in findings {
+            match finding.severity {
+                Severity::Error => report.errors.push(finding.to_string()),
+                Severity::Warning => report.warnings.push(finding.to_string()),
+            }
+        }
+        report
+    }
+}
+
+/// Checks every entry in the index: versions must be valid semver and unique
+/// per chart, digests must be hex and not shared between versions, and URLs
+/// should be http(s)
+pub fn verify(index: &ChartYaml) -> Vec<Finding> {
+    verify_with(index, false)
+}
+
+/// Like [`verify`], but with `strict` digests shared between versions, URLs
+/// shared between entries, versions differing only in build metadata,
+/// entries mixing URL schemes and v2-only fields in a `v1` index are errors
+/// instead of warnings
+pub fn verify_with(index: &ChartYaml, strict: bool) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    let v1_index = index.api_version.as_deref() == Some("v1");
+
+    for (name, entries) in &index.entries {
+        let name = name.as_str().unwrap_or("<non-string key>");
+        let mut report = |severity, code, version: Option<&str>, message: String| {
+            findings.push(Finding {
+                severity,
+                code,
+                chart: name.to_string(),
+                version: version.map(str::to_string),
+                message,
+            })
+        };
+        let Value::Sequence(entries) = entries else {
+            report(
+                Severity::Error,
+                ENTRIES_NOT_SEQUENCE,
+                None,
+                "entries must be a sequence".to_string(),
+            );
+            continue;
+        };
+
+        let mut seen_versions = Vec::new();
+        let mut versions_by_identity: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+        let mut versions_by_digest: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+        for entry in entries {
+            let Some(version) = entry.get("version").and_then(Value::as_str) else {
+                report(
+                    Severity::Error,
+                    MISSING_VERSION,
+                    None,
+                    "entry is missing a version".to_string(),
+                );
+                continue;
+            };
+            let at = Some(version);
+
+            if let Err(e) = semver::Version::parse(version) {
+                report(
+                    Severity::Error,
+                    INVALID_VERSION,
+                    at,
+                    format!("invalid version: {}", e),
+                );
+            }
+            if seen_versions.contains(&version) {
+                report(
+                    Severity::Error,
+                    DUPLICATE_VERSION,
+                    at,
+                    "duplicate version".to_string(),
+                );
+            } else {
+                seen_versions.push(version);
+                versions_by_identity
+                    .entry(version_identity(version))
+                    .or_default()
+                    .push(version);
+            }
+
+            match entry.get("digest").and_then(Value::as_str) {
+                Some(digest) => {
+                    let digest = bare_digest(digest);
+                    if let Err(e) = validate_hex("digest", digest) {
+                        report(Severity::Error, INVALID_DIGEST, at, e.to_string());
+                    }
+                    let versions = versions_by_digest.entry(digest).or_default();
+                    if !versions.contains(&version) {
+                        versions.push(version);
+                    }
+                }
+                None => report(
+                    Severity::Error,
+                    MISSING_DIGEST,
+                    at,
+                    "missing digest".to_string(),
+                ),
+            }
+
+            let urls = entry.get("urls").and_then(Value::as_sequence);
+            if urls.is_none_or(|urls| urls.is_empty()) {
+                report(Severity::Error, NO_URLS, at, "no urls".to_string());
+            }
+            let urls: Vec<String> = urls
+                .into_iter()
+                .flatten()
+                .filter_map(Value::as_str)
+                .map(str::to_string)
+                .collect();
+            for url in &urls {
+                if !is_http_url(url) {
+                    report(
+                        Severity::Warning,
+                        NON_HTTP_URL,
+                        at,
+                        format!("URL '{}' is not an http(s) URL", url),
+                    );
+                }
+            }
+            if let Some(schemes) = mixed_url_schemes(&urls) {
+                report(
+                    if strict {
+                        Severity::Error
+                    } else {
+                        Severity::Warning
+                    },
+                    MIXED_URL_SCHEMES,
+                    at,
+                    format!(
+                        "URLs mix {} schemes: {}",
+                        schemes.join(" and "),
+                        urls.join(", ")
+                    ),
+                );
+            }
+            let v2_fields: Vec<&str> = V2_ONLY_FIELDS
+                .into_iter()
+                .filter(|field| v1_index && entry.get(field).is_some())
+                .collect();
+            if !v2_fields.is_empty() {
+                report(
+                    if strict {
+                        Severity::Error
+                    } else {
+                        Severity::Warning
+                    },
+                    API_VERSION_MISMATCH,
+                    at,
+                    format!(
+                        "uses v2-only fields ({}) in a v1 index; bump the root apiVersion to v2",
+                        v2_fields.join(", ")
+                    ),
+                );
+            }
+            if !urls.is_empty() && !urls_match_version(&urls, version) {
+                report(
+                    Severity::Warning,
+                    URL_VERSION_MISMATCH,
+                    at,
+                    format!("no URL file name contains the version: {}", urls.join(", ")),
+                );
+            }
+        }
+
+        // Helm ignores build metadata, so pulling either version is ambiguous
+        for versions in versions_by_identity.into_values() {
+            if versions.len() > 1 {
+                report(
+                    if strict {
+                        Severity::Error
+                    } else {
+                        Severity::Warning
+                    },
+                    BUILD_METADATA_CONFLICT,
+                    None,
+                    format!(
+                        "versions {} differ only in build metadata, which Helm ignores",
+                        versions.join(", ")
+                    ),
+                );
+            }
+        }
+
+        // Reuse across charts is fine; within a chart it usually means the
+        // same artifact was re-tagged
+        for (digest, versions) in versions_by_digest {
+            if versions.len() > 1 {
+                let severity = if strict {
+                    Severity::Error
+                } else {
+                    Severity::Warning
+                };
+                report(
+                    severity,
+                    SHARED_DIGEST,
+                    None,
+                    format!("versions {} share digest {}", versions.join(", "), digest),
+                );
+            }
+        }
+    }
+
+    findings.extend(shared_urls(index, strict));
+    findings
+}
+
+/// URLs referenced by more than one entry anywhere in the index, usually a
+/// URL copied from another version. Redirecting setups can do this on
+/// purpose, so it's only an error with `strict`.
+fn shared_urls(index: &ChartYaml, strict: bool) -> Vec<Finding> {
+    let mut entries_by_url: BTreeMap<&str, Vec<(&str, String)>> = BTreeMap::new();
+    for (name, entries) in &index.entries {
+        let name = name.as_str().unwrap_or("<non-string key>");
+        for entry in entries.as_sequence().into_iter().flatten() {
+            let version = entry.get("version").and_then(Value::as_str).unwrap_or("?");
+            let urls = entry.get("urls").and_then(Value::as_sequence);
+            for url in urls.into_iter().flatten().filter_map(Value::as_str) {
+                let referencing = entries_by_url.entry(url).or_default();
+                let entry = (name, format!("{}@{}", name, version));
+                if !referencing.contains(&entry) {
+                    referencing.push(entry);
+                }
+            }
+        }
+    }
+
+    entries_by_url
+        .into_iter()
+        .filter(|(_, referencing)| referencing.len() > 1)
+        .map(|(url, referencing)| Finding {
+            severity: if strict {
+                Severity::Error
+            } else {
+                Severity::Warning
+            },
+            code: SHARED_URL,
+            chart: referencing[0].0.to_string(),
+            version: None,
+            message: format!(
+                "URL {} is used by {}",
+                url,
+                referencing
+                    .iter()
+                    .map(|(_, entry)| entry.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        })
+        .collect()
+}
+
+/// Like [`verify`], limited to the given `(chart, version)` entries: only
+/// their charts are checked, and only findings about those versions or their
+/// charts as a whole are kept
+pub fn verify_entries(index: &ChartYaml, entries: &[(&str, &str)]) -> Vec<Finding> {
+    let mut scope = ChartYaml {
+        api_version: index.api_version.clone(),
+        ..ChartYaml::default()
+    };
+    for (chart, _) in entries {
+        if let Some(chart_entries) = index.entries.get(*chart) {
+            scope
+                .entries
+                .insert(Value::from(*chart), chart_entries.clone());
+        }
+    }
+
+    verify(&scope)
+        .into_iter()
+        .filter(|finding| {
+            entries.iter().any(|(chart, version)| {
+                finding.chart == *chart && finding.version.as_deref().is_none_or(|v| v == *version)
+            })
+        })
+        .collect()
+}
+
+/// A chart that must be present in the index, optionally with a version
+/// matching a requirement
+#[derive(Debug)]
+pub struct Requirement {
+    pub chart: String,
+    pub version: Option<semver::VersionReq>,
+}
+
+impl Requirement {
+    /// Parses `name` or `name<requirement>`, e.g. `ingress>=1.0.0`
+    pub fn parse(line: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let line = line.trim();
+        let (chart, version) = match line.find(['<', '>', '=', '^', '~']) {
+            Some(at) => {
+                let version = semver::VersionReq::parse(&line[at..])
+                    .map_err(|e| format!("Invalid requirement '{}': {}", line, e))?;
+                (line[..at].trim(), Some(version))
+            }
+            None => (line, None),
+        };
+        if chart.is_empty() {
+            return Err(format!("Requirement '{}' has no chart name", line).into());
+        }
+
+        Ok(Self {
+            chart: chart.to_string(),
+            version,
+        })
+    }
+
+    /// Parses one requirement per line, skipping blank lines and `#` comments
+    pub fn parse_list(contents: &str) -> Result<Vec<Self>, Box<dyn std::error::Error>> {
+        contents
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(Self::parse)
+            .collect()
+    }
+}
+
+/// Reports each requirement the index doesn't satisfy
+pub fn check_required(index: &ChartYaml, requirements: &[Requirement]) -> Vec<Finding> {
+    let mut findings = Vec::new();
+
+    for requirement in requirements {
+        let entries = index
+            .entries
+            .get(requirement.chart.as_str())
+            .and_then(Value::as_sequence);
+        let message = match (entries, &requirement.version) {
+            (None, _) => "required chart is missing".to_string(),
+            (Some(_), None) => continue,
+            (Some(entries), Some(required)) => {
+                let satisfied = entries
+                    .iter()
+                    .filter_map(|entry| entry.get("version").and_then(Value::as_str))
+                    .filter_map(|version| semver::Version::parse(version).ok())
+                    .any(|version| required.matches(&version));
+                if satisfied {
+                    continue;
+                }
+                format!("no version matches required {}", required)
+            }
+        };
+        findings.push(Finding {
+            severity: Severity::Error,
+            code: MISSING_REQUIRED_CHART,
+            chart: requirement.chart.clone(),
+            version: None,
+            message,
+        });
+    }
+
+    findings
+}
+
+/// A `name@version` that was pulled and must never reappear in the index
+#[derive(Debug, PartialEq)]
+pub struct Yanked {
+    pub chart: String,
+    pub version: String,
+}
+
+impl Yanked {
+    /// Parses one `name@version` per line, skipping blank lines and `#`
+    /// comments
+    pub fn parse_list(contents: &str) -> Result<Vec<Self>, Box<dyn std::error::Error>> {
+        contents
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| match line.split_once('@') {
+                Some((chart, version))
+                    if !chart.trim().is_empty() && !version.trim().is_empty() =>
+                {
+                    Ok(Self {
+                        chart: chart.trim().to_string(),
+                        version: version.trim().to_string(),
+                    })
+                }
+                _ => Err(format!("Expected `name@version`, got '{}'", line).into()),
+            })
+            .collect()
+    }
+
+    pub fn matches(&self, chart: &str, version: &str) -> bool {
+        self.chart == chart && self.version == version
+    }
+}
+
+/// Reports each yanked version still present in the index
+pub fn check_yanked(index: &ChartYaml, yanked: &[Yanked]) -> Vec<Finding> {
+    yanked
+        .iter()
+        .filter(|yanked| has_version(index, &yanked.chart, &yanked.version))
+        .map(|yanked| Finding {
+            severity: Severity::Error,
+            code: YANKED_VERSION,
+            chart: yanked.chart.clone(),
+            version: Some(yanked.version.clone()),
+            message: "version is yanked and must be removed".to_string(),
+        })
+        .collect()
+}
+
+/// Reports each URL whose host isn't one of `allowed`; relative URLs pass
+pub fn check_hosts(index: &ChartYaml, allowed: &[String]) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    for (name, entries) in &index.entries {
+        let chart = name.as_str().unwrap_or("<non-string key>");
+        for entry in entries.as_sequence().into_iter().flatten() {
+            let version = entry.get("version").and_then(Value::as_str);
+            let urls = entry.get("urls").and_then(Value::as_sequence);
+            for url in urls.into_iter().flatten().filter_map(Value::as_str) {
+                let Some(host) = url_host(url) else {
+                    continue;
+                };
+                if !host_allowed(url, allowed) {
+                    findings.push(Finding {
+                        severity: Severity::Error,
+                        code: DISALLOWED_HOST,
+                        chart: chart.to_string(),
+                        version: version.map(str::to_string),
+                        message: format!("URL {} has host '{}', which isn't allowed", url, host),
+                    });
+                }
+            }
+        }
+    }
+    findings
+}
+
+/// Reports each entry without a `type`, or with an empty one, for consumers
+/// that expect every entry to have it
+pub fn check_types(index: &ChartYaml) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    for (name, entries) in &index.entries {
+        let chart = name.as_str().unwrap_or("<non-string key>");
+        for entry in entries.as_sequence().into_iter().flatten() {
+            let entry_type = entry.get("type").and_then(Value::as_str);
+            if entry_type.is_none_or(|entry_type| entry_type.trim().is_empty()) {
+                findings.push(Finding {
+                    severity: Severity::Error,
+                    code: MISSING_TYPE,
+                    chart: chart.to_string(),
+                    version: entry
+                        .get("version")
+                        .and_then(Value::as_str)
+                        .map(str::to_string),
+                    message: "entry has no type".to_string(),
+                });
+            }
+        }
+    }
+    findings
+}
+
+/// An http(s) link in a chart's metadata
+#[derive(Debug, PartialEq)]
+pub struct Link {
+    pub chart: String,
+    /// `home`, `icon` or `maintainers.url`
+    pub field: &'static str,
+    pub url: String,
+}
+
+impl Link {
+    /// The finding for this link being unreachable, an error unless
+    /// `lenient`
+    pub fn dead(&self, reason: &str, lenient: bool) -> Finding {
+        Finding {
+            severity: if lenient {
+                Severity::Warning
+            } else {
+                Severity::Error
+            },
+            code: DEAD_LINK,
+            chart: self.chart.clone(),
+            version: None,
+            message: format!("dead link in {}: {} ({})", self.field, self.url, reason),
+        }
+    }
+}
+
+/// The distinct `home`, `icon` and maintainer `url` links of each chart, in
+/// index order
+pub fn metadata_links(index: &ChartYaml) -> Vec<Link> {
+    let mut links: Vec<Link> = Vec::new();
+    for (name, entries) in &index.entries {
+        let chart = name.as_str().unwrap_or("<non-string key>");
+        for entry in entries.as_sequence().into_iter().flatten() {
+            let maintainers = entry.get("maintainers").and_then(Value::as_sequence);
+            let found = [("home", entry.get("home")), ("icon", entry.get("icon"))]
+                .into_iter()
+                .chain(
+                    maintainers
+                        .into_iter()
+                        .flatten()
+                        .map(|maintainer| ("maintainers.url", maintainer.get("url"))),
+                );
+            for (field, url) in found {
+                let Some(url) = url.and_then(Value::as_str).filter(|url| is_http_url(url)) else {
+                    continue;
+                };
+                let link = Link {
+                    chart: chart.to_string(),
+                    field,
+                    url: url.to_string(),
+                };
+                if !links.contains(&link) {
+                    links.push(link);
+                }
+            }
+        }
+    }
+    links
+}
+
+/// An entry's first URL and the digest recorded for it
+#[derive(Debug, PartialEq)]
+pub struct Artifact {
+    pub chart: String,
+    pub version: String,
+    pub url: String,
+    pub digest: String,
+}
+
+impl Artifact {
+    /// The `HRU020` finding if the downloaded artifact's digest (or the
+    /// reason it couldn't be downloaded) doesn't match the recorded one
+    pub fn check(&self, downloaded: &Result<String, String>) -> Option<Finding> {
+        let message = match downloaded {
+            Ok(actual) if actual.eq_ignore_ascii_case(&self.digest) => return None,
+            Ok(actual) => format!(
+                "{} hashes to {}, not the recorded {}",
+                self.url, actual, self.digest
+            ),
+            Err(reason) => format!("couldn't download {} ({})", self.url, reason),
+        };
+        Some(Finding {
+            severity: Severity::Error,
+            code: DIGEST_MISMATCH,
+            chart: self.chart.clone(),
+            version: Some(self.version.clone()),
+            message,
+        })
+    }
+}
+
+/// The entries with a digest and an http(s) first URL, whose artifacts can
+/// be downloaded to check the digest
+pub fn artifacts(index: &ChartYaml) -> Vec<Artifact> {
+    let mut artifacts = Vec::new();
+    for (name, entries) in &index.entries {
+        let chart = name.as_str().unwrap_or("<non-string key>");
+        for entry in entries.as_sequence().into_iter().flatten() {
+            let field = |name| entry.get(name).and_then(Value::as_str);
+            let url = entry
+                .get("urls")
+                .and_then(Value::as_sequence)
+                .and_then(|urls| urls.first())
+                .and_then(Value::as_str);
+            let (Some(version), Some(digest), Some(url)) = (field("version"), field("digest"), url)
+            else {
+                continue;
+            };
+            if is_http_url(url) {
+                artifacts.push(Artifact {
+                    chart: chart.to_string(),
+                    version: version.to_string(),
+                    url: url.to_string(),
+                    digest: bare_digest(digest).to_string(),
+                });
+            }
+        }
+    }
+    artifacts
+}
+
+/// How much of the recommended metadata a chart's newest version has
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Completeness {
+    pub chart: String,
+    /// The percentage of [`RECOMMENDED_FIELDS`] that are set
+    pub score: u32,
+    pub missing: Vec<&'static str>,
+}
+
+impl Completeness {
+    /// `HRU018` if the score is below `min`, and `HRU019` for a missing icon
+    /// when `warn_missing_icon`
+    pub fn findings(&self, min: Option<u32>, warn_missing_icon: bool) -> Vec<Finding> {
+        let mut findings = Vec::new();
+        if let Some(min) = min.filter(|&min| self.score < min) {
+            findings.push(Finding {
+                severity: Severity::Error,
+                code: INCOMPLETE_METADATA,
+                chart: self.chart.clone(),
+                version: None,
+                message: format!(
+                    "metadata is {}% complete, below {}% (missing {})",
+                    self.score,
+                    min,
+                    self.missing.join(", ")
+                ),
+            });
+        }
+        if warn_missing_icon && self.missing.contains(&"icon") {
+            findings.push(Finding {
+                severity: Severity::Warning,
+                code: MISSING_ICON,
+                chart: self.chart.clone(),
+                version: None,
+                message: "chart has no icon".to_string(),
+            });
+        }
+        findings
+    }
+}
+
+/// Scores each chart by which [`RECOMMENDED_FIELDS`] its newest version sets;
+/// empty strings and lists count as missing
+pub fn completeness(index: &ChartYaml) -> Vec<Completeness> {
+    let mut scores = Vec::new();
+    for name in index.entries.keys() {
+        let chart = name.as_str().unwrap_or("<non-string key>");
+        let entry = find_entry(index, chart, None).or_else(|| {
+            index
+                .entries
+                .get(name)
+                .and_then(Value::as_sequence)
+                .and_then(|entries| entries.first())
+        });
+        let missing: Vec<&'static str> = RECOMMENDED_FIELDS
+            .into_iter()
+            .filter(|field| match entry.and_then(|entry| entry.get(field)) {
+                Some(Value::String(text)) => text.trim().is_empty(),
+                Some(Value::Sequence(items)) => items.is_empty(),
+                Some(Value::Null) | None => true,
+                Some(_) => false,
+            })
+            .collect();
+        let present = RECOMMENDED_FIELDS.len() - missing.len();
+        scores.push(Completeness {
+            chart: chart.to_string(),
+            score: (present * 100 / RECOMMENDED_FIELDS.len()) as u32,
+            missing,
+        });
+    }
+    scores
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verify_index() -> Result<(), Box<dyn std::error::Error>> {
+        let index: ChartYaml = serde_yaml::from_str(
+            r#"
+apiVersion: v1
+entries:
+  test-chart:
+    - version: 0.1.0
+      digest: abc123
+      urls:
+        - https://example.com/test-chart-0.1.0.tgz
+    - version: 0.1.0
+      digest: not-hex
+      urls:
+        - example.com/test-chart-0.1.0.tgz
+    - version: latest
+      digest: abc123
+      urls: []
+"#,
+        )?;
+
+        let findings = verify(&index);
+        assert!(findings.contains(&Finding {
+            severity: Severity::Error,
+            code: DUPLICATE_VERSION,
+            chart: "test-chart".to_string(),
+            version: Some("0.1.0".to_string()),
+            message: "duplicate version".to_string(),
+        }));
+
+        let report = Report::from(findings);
+        assert_eq!(report.errors.len(), 4);
+        assert_eq!(report.warnings.len(), 2);
+        assert!(report.warnings.contains(
+            &"HRU008 test-chart: versions 0.1.0, latest share digest abc123".to_string()
+        ));
+        assert!(!report.is_valid());
+
+        let report = Report::from(verify_with(&index, true));
+        assert_eq!(report.errors.len(), 5);
+        assert_eq!(report.warnings.len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_index_digest_reuse_across_charts() -> Result<(), Box<dyn std::error::Error>> {
+        let index: ChartYaml = serde_yaml::from_str(
+            r#"
+apiVersion: v1
+entries:
+  first:
+    - version: 0.1.0
+      digest: abc123
+      urls:
+        - https://example.com/first-0.1.0.tgz
+  second:
+    - version: 0.1.0
+      digest: abc123
+      urls:
+        - https://example.com/second-0.1.0.tgz
+"#,
+        )?;
+
+        assert!(verify_with(&index, true).is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_index_prefixed_digest() -> Result<(), Box<dyn std::error::Error>> {
+        let index: ChartYaml = serde_yaml::from_str(
+            r#"
+apiVersion: v1
+entries:
+  test-chart:
+    - version: 0.1.0
+      digest: sha256:abc123
+      urls:
+        - https://example.com/test-chart-0.1.0.tgz
+    - version: 0.2.0
+      digest: abc123
+      urls:
+        - https://example.com/test-chart-0.2.0.tgz
+"#,
+        )?;
+
+        // Both forms are valid and recognized as the same digest
+        let codes: Vec<_> = verify(&index).iter().map(|finding| finding.code).collect();
+        assert_eq!(codes, [SHARED_DIGEST]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_index_v2_fields_in_v1_index() -> Result<(), Box<dyn std::error::Error>> {
+        let mut index: ChartYaml = serde_yaml::from_str(
+            r#"
+apiVersion: v1
+entries:
+  test-chart:
+    - version: 0.1.0
+      digest: abc123
+      type: application
+      kubeVersion: ">=1.25.0"
+      urls:
+        - https://example.com/test-chart-0.1.0.tgz
+"#,
+        )?;
+
+        let report = Report::from(verify(&index));
+        assert_eq!(
+            report.warnings,
+            [
+                "HRU015 test-chart@0.1.0: uses v2-only fields (type, kubeVersion) in a v1 index; \
+              bump the root apiVersion to v2"
+            ]
+        );
+        assert_eq!(Report::from(verify_with(&index, true)).errors.len(), 1);
+
+        index.api_version = Some("v2".to_string());
+        assert!(verify_with(&index, true).is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_index_build_metadata_conflict() -> Result<(), Box<dyn std::error::Error>> {
+        let index: ChartYaml = serde_yaml::from_str(
+            r#"
+apiVersion: v2
+entries:
+  test-chart:
+    - version: 1.0.0+a
+      digest: abc123
+      urls:
+        - https://example.com/test-chart-1.0.0+a.tgz
+    - version: 1.0.0+b
+      digest: def456
+      urls:
+        - https://example.com/test-chart-1.0.0+b.tgz
+"#,
+        )?;
+
+        let report = Report::from(verify(&index));
+        assert_eq!(
+            report.warnings,
+            [
+                "HRU016 test-chart: versions 1.0.0+a, 1.0.0+b differ only in build metadata, \
+              which Helm ignores"
+            ]
+        );
+        assert_eq!(Report::from(verify_with(&index, true)).errors.len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_index_shared_url() -> Result<(), Box<dyn std::error::Error>> {
+        let index: ChartYaml = serde_yaml::from_str(
+            r#"
+apiVersion: v2
+entries:
+  api:
+    - version: 1.0.0
+      digest: abc123
+      urls:
+        - https://example.com/api-1.0.0.tgz
+  web:
+    - version: 1.0.0
+      digest: def456
+      urls:
+        - https://example.com/api-1.0.0.tgz
+"#,
+        )?;
+
+        let report = Report::from(verify(&index));
+        assert_eq!(
+            report.warnings,
+            ["HRU017 api: URL https://example.com/api-1.0.0.tgz is used by api@1.0.0, web@1.0.0"]
+        );
+        assert_eq!(Report::from(verify_with(&index, true)).errors.len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_metadata_links() -> Result<(), Box<dyn std::error::Error>> {
+        let index: ChartYaml = serde_yaml::from_str(
+            r#"
+entries:
+  test-chart:
+    - version: 0.1.0
+      home: https://example.com
+      icon: icon.png
+      maintainers:
+        - name: Ann
+          url: https://ann.example.com
+    - version: 0.2.0
+      home: https://example.com
+"#,
+        )?;
+
+        let links = metadata_links(&index);
+        let found: Vec<_> = links
+            .iter()
+            .map(|link| (link.field, link.url.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                ("home", "https://example.com"),
+                ("maintainers.url", "https://ann.example.com"),
+            ]
+        );
+        assert_eq!(
+            links[0].dead("404", false).to_string(),
+            "HRU014 test-chart: dead link in home: https://example.com (404)"
+        );
+
+        Ok(())
+    }
+
+    #[cfg(feature = "schema")]
+    #[test]
+    fn test_validate_against_schema() -> Result<(), Box<dyn std::error::Error>> {
+        let schema: serde_json::Value = serde_json::from_str(INDEX_SCHEMA)?;
+        let index: Value = serde_yaml::from_str(
+            r#"
+apiVersion: v1
+entries:
+  test-chart:
+    - name: test-chart
+      version: 1.0
+      digest: abc123
+      urls: []
+"#,
+        )?;
+
+        let violations = validate_against_schema(&index, &schema)?;
+        assert_eq!(violations.len(), 2);
+        assert!(violations
+            .iter()
+            .any(|v| v.starts_with("schema: /entries/test-chart/0/version:")));
+        assert!(violations
+            .iter()
+            .any(|v| v.starts_with("schema: /entries/test-chart/0/urls:")));
+
+        Ok(())
+    }
+
+    #[cfg(feature = "schema")]
+    #[test]
+    fn test_input_schemas_match_structs() -> Result<(), Box<dyn std::error::Error>> {
+        use crate::{Constants, Parameters};
+
+        let constants: serde_json::Value = serde_json::from_str(CONSTANTS_SCHEMA)?;
+        let mut profile = serde_yaml::to_value(Constants::default())?;
+        assert!(validate_against_schema(&profile, &constants)?.is_empty());
+        profile["type"] = Value::from("service");
+        assert_eq!(validate_against_schema(&profile, &constants)?.len(), 1);
+
+        let schema: serde_json::Value = serde_json::from_str(PARAMETERS_SCHEMA)?;
+        let contents = r#"
+- chart: mychart
+  digest: sha256:3f1c
+  version: 1.2.3
+  created: 2026-01-02T03:04:05Z
+  urls:
+    - https://charts.example.com/mychart-1.2.3.tgz
+    - url: https://mirror.example.com/mychart-1.2.3.tgz
+      name: eu
+"#;
+        let parameters: Value = serde_yaml::from_str(contents)?;
+        assert!(validate_against_schema(&parameters, &schema)?.is_empty());
+        let _: Vec<Parameters> = serde_yaml::from_str(contents)?;
+        let parameters: Value = serde_yaml::from_str("version: 1.2.3\nurls: []\n")?;
+        assert!(!validate_against_schema(&parameters, &schema)?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_required() -> Result<(), Box<dyn std::error::Error>> {
+        let index: ChartYaml = serde_yaml::from_str(
+            r#"
+apiVersion: v1
+entries:
+  ingress:
+    - version: 0.9.0
+  dns:
+    - version: 2.1.0
+"#,
+        )?;
+        let requirements =
+            Requirement::parse_list("# platform\ningress>=1.0.0\ndns >= 2.0.0\n\nlogging\n")?;
+
+        let missing: Vec<String> = check_required(&index, &requirements)
+            .iter()
+            .map(Finding::to_string)
+            .collect();
+        assert_eq!(
+            missing,
+            [
+                "HRU011 ingress: no version matches required >=1.0.0",
+                "HRU011 logging: required chart is missing",
+            ]
+        );
+        assert!(Requirement::parse(">=1.0.0").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_yanked() -> Result<(), Box<dyn std::error::Error>> {
+        let index: ChartYaml = serde_yaml::from_str(
+            r#"
+entries:
+  ingress:
+    - version: 0.9.0
+    - version: 1.0.0
+"#,
+        )?;
+        let yanked = Yanked::parse_list("# CVE-2026-0001\ningress@0.9.0\ndns@1.0.0\n")?;
+
+        let present: Vec<String> = check_yanked(&index, &yanked)
+            .iter()
+            .map(Finding::to_string)
+            .collect();
+        assert_eq!(
+            present,
+            ["HRU013 ingress@0.9.0: version is yanked and must be removed"]
+        );
+        assert!(Yanked::parse_list("ingress").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_hosts() -> Result<(), Box<dyn std::error::Error>> {
+        let index: ChartYaml = serde_yaml::from_str(
+            r#"
+entries:
+  web:
+    - version: 1.0.0
+      urls: [https://mirror.example.com/web-1.0.0.tgz, https://evil.example.com/web-1.0.0.tgz]
+    - version: 0.9.0
+      urls: [web-0.9.0.tgz]
+"#,
+        )?;
+
+        let findings: Vec<String> = check_hosts(&index, &["Mirror.example.com".to_string()])
+            .iter()
+            .map(Finding::to_string)
+            .collect();
+        assert_eq!(
+            findings,
+            [
+                "HRU021 web@1.0.0: URL https://evil.example.com/web-1.0.0.tgz has host \
+              'evil.example.com', which isn't allowed"
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_types() -> Result<(), Box<dyn std::error::Error>> {
+        let index: ChartYaml = serde_yaml::from_str(
+            r#"
+entries:
+  web:
+    - version: 1.0.0
+      type: application
+    - version: 0.9.0
+      type: ""
+    - version: 0.8.0
+"#,
+        )?;
+
+        let findings: Vec<String> = check_types(&index).iter().map(Finding::to_string).collect();
+        assert_eq!(
+            findings,
+            [
+                "HRU022 web@0.9.0: entry has no type",
+                "HRU022 web@0.8.0: entry has no type",
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_entries() -> Result<(), Box<dyn std::error::Error>> {
+        let index: ChartYaml = serde_yaml::from_str(
+            r#"
+apiVersion: v1
+entries:
+  test-chart:
+    - version: latest
+      digest: aaa
+      urls:
+        - https://example.com/test-chart-latest.tgz
+    - version: 0.2.0
+      digest: bbb
+      urls:
+        - https://example.com/test-chart-0.2.0.tgz
+  other:
+    - version: 1.0.0
+      urls: []
+"#,
+        )?;
+
+        assert!(verify_entries(&index, &[("test-chart", "0.2.0")]).is_empty());
+        assert_eq!(verify(&index).len(), 3);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_artifacts() -> Result<(), Box<dyn std::error::Error>> {
+        let index: ChartYaml = serde_yaml::from_str(
+            r#"
+entries:
+  web:
+    - version: 1.0.0
+      digest: sha256:ABC123
+      urls: [https://example.com/web-1.0.0.tgz, https://mirror.example.com/web-1.0.0.tgz]
+    - version: 0.9.0
+      digest: def456
+      urls: [oci://registry.example.com/web]
+    - version: 0.8.0
+      urls: [https://example.com/web-0.8.0.tgz]
+"#,
+        )?;
+
+        let artifacts = artifacts(&index);
+        assert_eq!(artifacts.len(), 1);
+        assert_eq!(artifacts[0].url, "https://example.com/web-1.0.0.tgz");
+        assert_eq!(artifacts[0].check(&Ok("abc123".to_string())), None);
+        assert_eq!(
+            artifacts[0]
+                .check(&Ok("fff".to_string()))
+                .map(|finding| finding.to_string()),
+            Some(
+                "HRU020 web@1.0.0: https://example.com/web-1.0.0.tgz hashes to fff, \
+                 not the recorded ABC123"
+                    .to_string()
+            )
+        );
+        assert!(artifacts[0].check(&Err("HTTP 404".to_string())).is_some());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_completeness() -> Result<(), Box<dyn std::error::Error>> {
+        let index: ChartYaml = serde_yaml::from_str(
+            r#"
+entries:
+  web:
+    - version: 2.0.0
+      description: Web server
+      home: https://example.com
+      keywords: []
+      icon: ""
+    - version: 1.0.0
+      description: Web server
+      home: https://example.com
+      keywords: [web]
+      icon: https://example.com/icon.png
+      sources: [https://example.com/src]
+"#,
+        )?;
+
+        let scores = completeness(&index);
+        assert_eq!(
+            scores,
+            [Completeness {
+                chart: "web".to_string(),
+                score: 40,
+                missing: vec!["icon", "keywords", "sources"],
+            }]
+        );
+        let findings: Vec<String> = scores[0]
+            .findings(Some(60), true)
+            .iter()
+            .map(Finding::to_string)
+            .collect();
+        assert_eq!(
+            findings,
+            [
+                "HRU018 web: metadata is 40% complete, below 60% (missing icon, keywords, sources)",
+                "HRU019 web: chart has no icon",
+            ]
+        );
+        assert!(scores[0].findings(Some(40), false).is_empty());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,257 @@
+//! A faster writer for indexes that produces exactly what `serde_yaml` would.
+//!
+//! It emits block-style YAML directly from the values. Strings that are
+//! obviously plain are written as they are; any other scalar is formatted by
+//! `serde_yaml` on its own, so quoting rules stay identical. When the index
+//! holds something the writer doesn't model (multi-line strings, tags,
+//! complex or very long keys) the whole index is written by `serde_yaml`
+//! instead.
+
+use serde_yaml::{Mapping, Value};
+use std::borrow::Cow;
+
+use crate::{index_to_string, ChartYaml};
+
+/// Longest key libyaml writes as a simple `key: value`; longer keys use the
+/// explicit `? key` form this writer doesn't produce
+const MAX_SIMPLE_KEY: usize = 128;
+
+/// Where a node appears, which decides how it's indented
+#[derive(Clone, Copy, PartialEq)]
+enum Position {
+    /// The top-level mapping
+    Root,
+    /// The value of a mapping key, after `key:`
+    Value,
+    /// An item of a sequence, after `- `
+    Item,
+}
+
+/// Serializes an index, byte-for-byte the same as [`index_to_string`]
+pub fn to_string(data: &ChartYaml) -> Result<String, Box<dyn std::error::Error>> {
+    match write_index(data) {
+        Some(yaml) => Ok(yaml),
+        None => index_to_string(data),
+    }
+}
+
+fn write_index(data: &ChartYaml) -> Option<String> {
+    let mut out = String::with_capacity(64 * data.entries.len() + 64);
+    let api_version = data.api_version.clone().map_or(Value::Null, Value::String);
+
+    write_key(&mut out, &Value::from("apiVersion"), 0)?;
+    write_node(&mut out, &api_version, 0, Position::Value)?;
+    write_key(&mut out, &Value::from("entries"), 0)?;
+    write_mapping(&mut out, &data.entries, 0, Position::Value)?;
+    write_mapping(&mut out, &data.extra, 0, Position::Root)?;
+
+    Some(out)
+}
+
+/// Writes `key:`, padded to `indent` unless it follows a `- `
+fn write_key(out: &mut String, key: &Value, indent: usize) -> Option<()> {
+    let key = scalar(key)?;
+    if key.len() >= MAX_SIMPLE_KEY {
+        return None;
+    }
+    pad(out, indent);
+    out.push_str(&key);
+    out.push(':');
+    Some(())
+}
+
+fn write_node(out: &mut String, value: &Value, indent: usize, position: Position) -> Option<()> {
+    match value {
+        Value::Mapping(mapping) => write_mapping(out, mapping, indent, position),
+        Value::Sequence(items) => write_sequence(out, items, indent, position),
+        value => write_scalar(out, &scalar(value)?, position),
+    }
+}
+
+fn write_scalar(out: &mut String, text: &str, position: Position) -> Option<()> {
+    if position == Position::Value {
+        out.push(' ');
+    }
+    out.push_str(text);
+    out.push('\n');
+    Some(())
+}
+
+fn write_mapping(
+    out: &mut String,
+    mapping: &Mapping,
+    indent: usize,
+    position: Position,
+) -> Option<()> {
+    let child = match position {
+        Position::Root => 0,
+        Position::Value | Position::Item => indent + 2,
+    };
+    match position {
+        // Only the root's extra keys can be empty and write nothing
+        Position::Root => {}
+        _ if mapping.is_empty() => return write_scalar(out, "{}", position),
+        Position::Value => out.push('\n'),
+        Position::Item => {}
+    }
+
+    for (i, (key, value)) in mapping.iter().enumerate() {
+        // The first key of a sequence item shares the `- ` line
+        let inline = i == 0 && position == Position::Item;
+        write_key(out, key, if inline { 0 } else { child })?;
+        write_node(out, value, child, Position::Value)?;
+    }
+    Some(())
+}
+
+fn write_sequence(
+    out: &mut String,
+    items: &[Value],
+    indent: usize,
+    position: Position,
+) -> Option<()> {
+    if items.is_empty() {
+        return write_scalar(out, "[]", position);
+    }
+    // Sequences under a mapping key aren't indented; nested ones are
+    let child = match position {
+        Position::Root | Position::Value => indent,
+        Position::Item => indent + 2,
+    };
+    if position == Position::Value {
+        out.push('\n');
+    }
+
+    for (i, item) in items.iter().enumerate() {
+        if !(i == 0 && position == Position::Item) {
+            pad(out, child);
+        }
+        out.push_str("- ");
+        write_node(out, item, child, Position::Item)?;
+    }
+    Some(())
+}
+
+fn pad(out: &mut String, indent: usize) {
+    out.extend(std::iter::repeat_n(' ', indent));
+}
+
+/// A scalar as `serde_yaml` writes it, or `None` if it needs more than one line
+fn scalar(value: &Value) -> Option<Cow<'_, str>> {
+    match value {
+        Value::Null => Some(Cow::Borrowed("null")),
+        Value::Bool(true) => Some(Cow::Borrowed("true")),
+        Value::Bool(false) => Some(Cow::Borrowed("false")),
+        Value::Number(number) if !number.is_f64() => Some(Cow::Owned(number.to_string())),
+        Value::String(text) if is_plain(text) => Some(Cow::Borrowed(text)),
+        Value::Number(_) | Value::String(_) => {
+            let yaml = serde_yaml::to_string(value).ok()?;
+            let line = yaml.strip_suffix('\n')?;
+            (!line.contains('\n')).then(|| Cow::Owned(line.to_string()))
+        }
+        _ => None,
+    }
+}
+
+/// Whether `serde_yaml` certainly writes the string unquoted. Conservative:
+/// anything unusual is left to `serde_yaml` to format.
+fn is_plain(text: &str) -> bool {
+    let bytes = text.as_bytes();
+    let (Some(&first), Some(&last)) = (bytes.first(), bytes.last()) else {
+        return false;
+    };
+    let allowed = |b: &u8| b.is_ascii_alphanumeric() || b" -._/:+@=,;()'\"!?%&*".contains(b);
+    if !(first.is_ascii_alphanumeric() || first == b'/')
+        || last == b' '
+        || last == b':'
+        || !bytes.iter().all(allowed)
+        || text.contains(": ")
+    {
+        return false;
+    }
+    if matches!(
+        text,
+        "null" | "Null" | "NULL" | "true" | "True" | "TRUE" | "false" | "False" | "FALSE"
+    ) {
+        return false;
+    }
+    if first.is_ascii_digit() {
+        // Could be an int or float unless it can't parse as one: versions have
+        // two dots, digests and timestamps have letters other than exponents
+        let radix_prefix = matches!(bytes.get(1), Some(b'x' | b'o' | b'b'));
+        let has_letter = bytes
+            .iter()
+            .any(|b| b.is_ascii_alphabetic() && !matches!(b, b'e' | b'E'));
+        let dots = bytes.iter().filter(|&&b| b == b'.').count();
+        return (has_letter && !radix_prefix) || dots >= 2;
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse_index, Warnings};
+
+    #[test]
+    fn test_matches_serde_yaml() -> Result<(), Box<dyn std::error::Error>> {
+        let mut data = parse_index(
+            r#"
+apiVersion: v2
+entries:
+  mychart:
+  - name: mychart
+    version: 1.2.3-rc.1
+    appVersion: "1.0"
+    created: 2026-01-02T03:04:05.678Z
+    digest: 0b5e5b0e1c6a4f4e2d9a1f7c2e8b6a3d4c5e6f7a8b9c0d1e2f3a4b5c6d7e8f90
+    description: 'A chart: with a colon, quotes '' and a # hash'
+    deprecated: false
+    keywords: []
+    maintainers:
+    - name: Ann
+      email: ann@example.com
+    urls:
+    - https://charts.example.com/mychart-1.2.3-rc.1.tgz
+    annotations: {}
+    dependencies:
+    - - nested
+      - 007
+    - {}
+    weight: 1.5
+    count: 3
+  "true":
+  - version: "10"
+    home: null
+    icon: ~
+    sources: [" padded", "-dash", "ünïcode", "0x1f", ".5", "1e5", "null"]
+generated: 2026-01-02T03:04:05Z
+serverInfo:
+  contextPath: /v1
+"#,
+            false,
+            &mut Warnings::default(),
+        )?;
+        assert_eq!(write_index(&data), Some(index_to_string(&data)?));
+
+        data.api_version = None;
+        data.entries.clear();
+        data.extra.clear();
+        assert_eq!(write_index(&data), Some(index_to_string(&data)?));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_falls_back_for_multiline_strings() -> Result<(), Box<dyn std::error::Error>> {
+        let data = parse_index(
+            "apiVersion: v1\nentries:\n  a:\n  - description: |\n      two\n      lines\n",
+            false,
+            &mut Warnings::default(),
+        )?;
+        assert!(write_index(&data).is_none());
+        assert_eq!(to_string(&data)?, index_to_string(&data)?);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,758 @@
+//! End-to-end tests running the built command-line tool against files in a
+//! temporary directory
+#![cfg(feature = "cli")]
+
+use std::{
+    fs,
+    path::Path,
+    process::{Command, Output},
+};
+
+const CONSTANTS: &str = "apiVersion: v2
+appVersion: 1.0.0
+description: Test Chart
+home: https://example.com
+icon: https://example.com/icon.png
+keywords: [test]
+maintainers: []
+name: test-chart
+sources: []
+type: application
+";
+
+const INDEX: &str = "apiVersion: v1\nentries: {}\n";
+
+/// A parameters file for `version` of `test-chart`
+fn parameters(version: &str) -> String {
+    format!(
+        "version: {version}\ndigest: {}\nurls:\n- https://example.com/test-chart-{version}.tgz\n",
+        "ab".repeat(32)
+    )
+}
+
+/// A parameters file with one entry of `test-chart` per version
+fn parameters_list(versions: &[&str]) -> String {
+    versions
+        .iter()
+        .map(|version| format!("- {}", parameters(version).trim_end().replace('\n', "\n  ")) + "\n")
+        .collect()
+}
+
+/// Writes a chart package containing only `<name>/Chart.yaml` to `dir`
+fn write_package(dir: &Path, name: &str, version: &str) {
+    use flate2::{write::GzEncoder, Compression};
+
+    let chart_yaml = format!(
+        "apiVersion: v2\nname: {name}\nversion: {version}\nappVersion: 1.0.0\ndescription: Test\n"
+    );
+    let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+    let mut header = tar::Header::new_gnu();
+    header.set_size(chart_yaml.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    builder
+        .append_data(
+            &mut header,
+            format!("{}/Chart.yaml", name),
+            chart_yaml.as_bytes(),
+        )
+        .unwrap();
+    let bytes = builder.into_inner().unwrap().finish().unwrap();
+    fs::write(dir.join(format!("{}-{}.tgz", name, version)), bytes).unwrap();
+}
+
+/// Runs the tool in `dir`
+fn run(dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_helm_repo_updater"))
+        .current_dir(dir)
+        .args(args)
+        .output()
+        .expect("the binary runs")
+}
+
+/// Writes an empty index, the constants and parameters for `version`
+fn setup(dir: &Path, version: &str) {
+    fs::write(dir.join("index.yaml"), INDEX).unwrap();
+    fs::write(dir.join("constants.yaml"), CONSTANTS).unwrap();
+    fs::write(dir.join("parameters.yaml"), parameters(version)).unwrap();
+}
+
+const UPDATE: [&str; 7] = [
+    "update",
+    "-f",
+    "index.yaml",
+    "-c",
+    "constants.yaml",
+    "-p",
+    "parameters.yaml",
+];
+
+#[test]
+fn test_rollback_over_corrupt_or_missing_index() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    setup(dir.path(), "0.1.0");
+    assert!(run(dir.path(), &UPDATE).status.success());
+    fs::write(dir.path().join("parameters.yaml"), parameters("0.2.0"))?;
+    assert!(run(dir.path(), &[&UPDATE[..], &["--backup"]].concat())
+        .status
+        .success());
+    let backup = fs::read_dir(dir.path())?
+        .map(|entry| entry.unwrap().path())
+        .find(|path| path.to_string_lossy().contains(".bak."))
+        .expect("a backup was written");
+    let backed_up = fs::read_to_string(&backup)?;
+
+    let index = dir.path().join("index.yaml");
+    fs::write(&index, "entries: [unclosed")?;
+    let output = run(dir.path(), &["rollback", "-f", "index.yaml"]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(fs::read_to_string(&index)?, backed_up);
+    assert!(String::from_utf8(output.stdout)?.contains("+ test-chart@0.1.0"));
+
+    fs::remove_file(&index)?;
+    assert!(run(dir.path(), &["rollback", "-f", "index.yaml"])
+        .status
+        .success());
+    assert_eq!(fs::read_to_string(&index)?, backed_up);
+
+    Ok(())
+}
+
+#[test]
+fn test_write_checksum() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    setup(dir.path(), "0.1.0");
+    assert!(
+        run(dir.path(), &[&UPDATE[..], &["--write-checksum"]].concat())
+            .status
+            .success()
+    );
+
+    let index = fs::read(dir.path().join("index.yaml"))?;
+    let checksum = fs::read_to_string(dir.path().join("index.yaml.sha256"))?;
+    assert_eq!(
+        checksum,
+        format!(
+            "{}  index.yaml\n",
+            helm_repo_updater::package::digest(&index)
+        )
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_verify_checksum() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    setup(dir.path(), "0.1.0");
+    assert!(
+        run(dir.path(), &[&UPDATE[..], &["--write-checksum"]].concat())
+            .status
+            .success()
+    );
+    let verify = ["verify", "-f", "index.yaml", "--checksum"];
+    assert!(run(dir.path(), &verify).status.success());
+
+    let index = dir.path().join("index.yaml");
+    fs::write(&index, fs::read_to_string(&index)? + "\n")?;
+    let output = run(dir.path(), &verify);
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)?.contains("checksum mismatch"));
+
+    fs::remove_file(dir.path().join("index.yaml.sha256"))?;
+    assert!(!run(dir.path(), &verify).status.success());
+
+    Ok(())
+}
+
+#[test]
+fn test_yanked_entry_skipped_with_continue_on_error() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    setup(dir.path(), "0.1.0");
+    fs::write(
+        dir.path().join("parameters.yaml"),
+        parameters_list(&["0.1.0", "0.2.0"]),
+    )?;
+    fs::write(dir.path().join("yanked.txt"), "test-chart@0.1.0\n")?;
+    let update = [&UPDATE[..], &["--yanked-file", "yanked.txt"]].concat();
+
+    let output = run(dir.path(), &update);
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)?.contains("listed in yanked.txt as yanked"));
+    assert_eq!(fs::read_to_string(dir.path().join("index.yaml"))?, INDEX);
+
+    let output = run(
+        dir.path(),
+        &[&update[..], &["--continue-on-error"]].concat(),
+    );
+    assert!(!output.status.success());
+    let index = fs::read_to_string(dir.path().join("index.yaml"))?;
+    assert!(index.contains("version: 0.2.0"));
+    assert!(!index.contains("version: 0.1.0"));
+
+    Ok(())
+}
+
+#[test]
+fn test_yanked_after_strip_prerelease() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    setup(dir.path(), "1.2.3-ci.45");
+    fs::write(dir.path().join("yanked.txt"), "test-chart@1.2.3\n")?;
+
+    // Stored as 1.2.3, the yanked version
+    let output = run(
+        dir.path(),
+        &[
+            &UPDATE[..],
+            &["--strip-prerelease", "--yanked-file", "yanked.txt"],
+        ]
+        .concat(),
+    );
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)?
+        .contains("Refusing to add test-chart@1.2.3: it is listed in yanked.txt as yanked"));
+    assert_eq!(fs::read_to_string(dir.path().join("index.yaml"))?, INDEX);
+
+    Ok(())
+}
+
+#[test]
+fn test_locked_downgrade_skipped_with_continue_on_error() -> Result<(), Box<dyn std::error::Error>>
+{
+    let dir = tempfile::tempdir()?;
+    setup(dir.path(), "0.1.0");
+    fs::write(dir.path().join("versions.lock"), "test-chart: 0.2.0\n")?;
+    fs::write(
+        dir.path().join("parameters.yaml"),
+        parameters_list(&["0.1.0", "0.3.0"]),
+    )?;
+    let update = [&UPDATE[..], &["--version-lock", "versions.lock"]].concat();
+
+    let output = run(dir.path(), &update);
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)?.contains("0.2.0 was already inserted"));
+    assert_eq!(fs::read_to_string(dir.path().join("index.yaml"))?, INDEX);
+
+    let output = run(
+        dir.path(),
+        &[&update[..], &["--continue-on-error"]].concat(),
+    );
+    assert!(!output.status.success());
+    let index = fs::read_to_string(dir.path().join("index.yaml"))?;
+    assert!(index.contains("version: 0.3.0"));
+    assert!(!index.contains("version: 0.1.0"));
+    assert_eq!(
+        fs::read_to_string(dir.path().join("versions.lock"))?,
+        "test-chart: 0.3.0\n"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_version_lock_after_normalize_version() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    setup(dir.path(), "v1.0.0");
+    fs::write(dir.path().join("versions.lock"), "test-chart: 2.0.0\n")?;
+    let update = [&UPDATE[..], &["--version-lock", "versions.lock"]].concat();
+
+    // Stored as 1.0.0, below the locked version
+    let output = run(
+        dir.path(),
+        &[&update[..], &["--normalize-version"]].concat(),
+    );
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)?.contains("2.0.0 was already inserted"));
+    assert_eq!(fs::read_to_string(dir.path().join("index.yaml"))?, INDEX);
+
+    // Without the rewrite it isn't semver and can't be ordered at all
+    let output = run(dir.path(), &update);
+    assert!(!output.status.success());
+    assert_eq!(fs::read_to_string(dir.path().join("index.yaml"))?, INDEX);
+    assert_eq!(
+        fs::read_to_string(dir.path().join("versions.lock"))?,
+        "test-chart: 2.0.0\n"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_continue_on_error() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    setup(dir.path(), "0.1.0");
+    fs::write(
+        dir.path().join("parameters.yaml"),
+        parameters_list(&["0.1.0", "0.1.0+build", "0.3.0"]),
+    )?;
+    fs::write(dir.path().join("yanked.txt"), "test-chart@0.3.0\n")?;
+
+    let output = run(
+        dir.path(),
+        &[
+            &["--output-format", "json"],
+            &UPDATE[..],
+            &["--yanked-file", "yanked.txt", "--continue-on-error"],
+        ]
+        .concat(),
+    );
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8(output.stderr)?.contains("2 of 3 entries failed to be added"));
+    let result: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    assert_eq!(result["action"], "add");
+    assert_eq!(result["changed"], true);
+    let entries = result["entries"].as_array().unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0]["version"], "0.1.0");
+    let failed = result["failed"].as_array().unwrap();
+    assert_eq!(failed.len(), 2);
+    assert_eq!(failed[0]["entry"], "test-chart@0.1.0+build");
+    assert!(failed[0]["error"]
+        .as_str()
+        .unwrap()
+        .contains("only in build metadata"));
+    assert_eq!(failed[1]["entry"], "test-chart@0.3.0");
+    assert!(failed[1]["error"].as_str().unwrap().contains("as yanked"));
+
+    let index = fs::read_to_string(dir.path().join("index.yaml"))?;
+    assert!(index.contains("version: 0.1.0\n"));
+    assert!(!index.contains("0.1.0+build"));
+    assert!(!index.contains("version: 0.3.0"));
+
+    Ok(())
+}
+
+#[test]
+fn test_continue_on_error_rolls_back_failed_entry() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    setup(dir.path(), "0.1.0");
+
+    // Fails only after creating the chart's (empty) entries sequence
+    let output = run(
+        dir.path(),
+        &[
+            &UPDATE[..],
+            &["--continue-on-error", "--max-entries", "0", "--stdout"],
+        ]
+        .concat(),
+    );
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(String::from_utf8(output.stdout)?, INDEX);
+
+    Ok(())
+}
+
+#[test]
+fn test_validate_scope_all_warns_once_per_added_entry() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    setup(dir.path(), "0.1.0");
+    fs::write(
+        dir.path().join("index.yaml"),
+        format!(
+            "apiVersion: v1\nentries:\n  test-chart:\n  - version: 0.0.1\n    digest: {}\n    \
+             urls: [https://example.com/old.tgz]\n",
+            "cd".repeat(32)
+        ),
+    )?;
+    fs::write(
+        dir.path().join("parameters.yaml"),
+        parameters("0.1.0").replace("test-chart-0.1.0.tgz", "new.tgz"),
+    )?;
+
+    let output = run(
+        dir.path(),
+        &[&UPDATE[..], &["--validate-scope", "all"]].concat(),
+    );
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(stderr.contains("No URL file name contains version 0.1.0"));
+    assert!(stderr.contains("HRU007 test-chart@0.0.1"));
+    assert!(!stderr.contains("HRU007 test-chart@0.1.0"));
+    assert!(stderr.contains("2 warning(s) emitted"));
+
+    Ok(())
+}
+
+#[test]
+fn test_summary_file() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    setup(dir.path(), "0.1.0");
+    let update = [
+        &UPDATE[..],
+        &["--idempotent", "--summary-file", "summary.jsonl"],
+    ]
+    .concat();
+    assert!(run(dir.path(), &update).status.success());
+    assert!(run(dir.path(), &update).status.success());
+
+    let summary = fs::read_to_string(dir.path().join("summary.jsonl"))?;
+    let records = summary
+        .lines()
+        .map(serde_json::from_str)
+        .collect::<Result<Vec<serde_json::Value>, _>>()?;
+    let [changed, unchanged] = records.as_slice() else {
+        panic!("expected one record per run: {}", summary);
+    };
+    assert_eq!(changed["action"], "update");
+    assert_eq!(changed["file"], "index.yaml");
+    assert_eq!(changed["outcome"], "changed");
+    assert_eq!(
+        (&changed["entries_before"], &changed["entries_after"]),
+        (&0.into(), &1.into())
+    );
+    assert_eq!(
+        changed["entries"],
+        serde_json::json!([{
+            "chart": "test-chart",
+            "version": "0.1.0",
+            "digest": "ab".repeat(32),
+        }])
+    );
+    assert!(changed["timestamp"].is_string());
+
+    assert_eq!(unchanged["outcome"], "unchanged");
+    assert_eq!(unchanged["entries"], serde_json::json!([]));
+    assert_eq!(
+        (&unchanged["entries_before"], &unchanged["entries_after"]),
+        (&1.into(), &1.into())
+    );
+
+    Ok(())
+}
+
+/// Parses every line of `output` as a JSON object
+fn json_lines(output: &[u8]) -> Vec<serde_json::Value> {
+    String::from_utf8_lossy(output)
+        .lines()
+        .map(|line| serde_json::from_str(line).expect("each line is JSON"))
+        .collect()
+}
+
+#[test]
+fn test_update_jsonl_out() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    setup(dir.path(), "0.1.0");
+    fs::write(
+        dir.path().join("parameters.yaml"),
+        parameters_list(&["0.1.0", "0.2.0"]),
+    )?;
+
+    let output = run(dir.path(), &[&UPDATE[..], &["--jsonl-out", "-"]].concat());
+    assert!(output.status.success());
+    let events = json_lines(&output.stdout);
+    assert_eq!(events.len(), 2);
+    for (event, version) in events.iter().zip(["0.1.0", "0.2.0"]) {
+        assert_eq!(event["action"], "add");
+        assert_eq!(event["chart"], "test-chart");
+        assert_eq!(event["version"], version);
+        assert_eq!(event["digest"], "ab".repeat(32));
+        assert_eq!(
+            event["urls"],
+            serde_json::json!([format!("https://example.com/test-chart-{}.tgz", version)])
+        );
+        assert!(event["created"].is_string());
+    }
+
+    // Appended to a file, a re-run adding nothing writes no lines
+    let update = [
+        &UPDATE[..],
+        &["--idempotent", "--jsonl-out", "events.jsonl"],
+    ]
+    .concat();
+    assert!(run(dir.path(), &update).status.success());
+    assert_eq!(fs::read_to_string(dir.path().join("events.jsonl"))?, "");
+
+    Ok(())
+}
+
+#[test]
+fn test_index_jsonl_out() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    write_package(dir.path(), "alpha", "1.0.0");
+    write_package(dir.path(), "beta", "0.1.0");
+
+    let output = run(
+        dir.path(),
+        &[
+            "index",
+            "-d",
+            ".",
+            "-u",
+            "https://example.com",
+            "--jsonl-out",
+            "events.jsonl",
+        ],
+    );
+    assert!(output.status.success(), "{:?}", output);
+    let mut events = json_lines(&fs::read(dir.path().join("events.jsonl"))?);
+    events.sort_by_key(|event| event["chart"].to_string());
+    assert_eq!(events.len(), 2);
+    assert_eq!(events[0]["chart"], "alpha");
+    assert_eq!(events[0]["version"], "1.0.0");
+    assert_eq!(
+        events[0]["urls"],
+        serde_json::json!(["https://example.com/alpha-1.0.0.tgz"])
+    );
+    assert_eq!(events[1]["chart"], "beta");
+    assert_eq!(events[1]["version"], "0.1.0");
+    assert!(events
+        .iter()
+        .all(|event| event["action"] == "add" && event["digest"].is_string()));
+
+    Ok(())
+}
+
+#[test]
+fn test_allowed_names_file() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    setup(dir.path(), "0.1.0");
+    let update = [&UPDATE[..], &["--allowed-names-file", "allowed.txt"]].concat();
+
+    // Commented out, the chart's own name doesn't count
+    fs::write(
+        dir.path().join("allowed.txt"),
+        "# test-chart\n\nother-chart\n",
+    )?;
+    let output = run(dir.path(), &update);
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)?
+        .contains("Refusing to add 'test-chart': it isn't an allowed chart name in allowed.txt"));
+    assert_eq!(fs::read_to_string(dir.path().join("index.yaml"))?, INDEX);
+
+    fs::write(dir.path().join("allowed.txt"), "# only comments\n\n")?;
+    let output = run(dir.path(), &update);
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)?.contains("doesn't allow any chart names"));
+
+    fs::write(
+        dir.path().join("allowed.txt"),
+        "# platform charts\nother-chart\n\n  test-*  \n",
+    )?;
+    assert!(run(dir.path(), &update).status.success());
+    assert!(fs::read_to_string(dir.path().join("index.yaml"))?.contains("test-chart:"));
+
+    Ok(())
+}
+
+#[test]
+fn test_fail_if_empty() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    setup(dir.path(), "0.1.0");
+    let update = [&UPDATE[..], &["--fail-if-empty"]].concat();
+
+    // Every entry failing leaves the index empty
+    let output = run(
+        dir.path(),
+        &[&update[..], &["--continue-on-error", "--max-entries", "0"]].concat(),
+    );
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8(output.stderr)?
+        .contains("Refusing to write index.yaml without any entries (--fail-if-empty)"));
+
+    assert!(run(dir.path(), &update).status.success());
+    assert!(fs::read_to_string(dir.path().join("index.yaml"))?.contains("version: 0.1.0"));
+
+    Ok(())
+}
+
+#[test]
+fn test_remove_last_entry_needs_allow_empty() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    setup(dir.path(), "0.1.0");
+    assert!(run(dir.path(), &UPDATE).status.success());
+    let index = fs::read_to_string(dir.path().join("index.yaml"))?;
+    let remove = [
+        "remove",
+        "-f",
+        "index.yaml",
+        "-n",
+        "test-chart",
+        "-v",
+        "0.1.0",
+    ];
+
+    let output = run(dir.path(), &remove);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8(output.stderr)?.contains("pass --allow-empty to proceed"));
+    assert_eq!(fs::read_to_string(dir.path().join("index.yaml"))?, index);
+
+    let output = run(dir.path(), &[&remove[..], &["--allow-empty"]].concat());
+    assert!(output.status.success());
+    let index = fs::read_to_string(dir.path().join("index.yaml"))?;
+    assert!(!index.contains("0.1.0"));
+
+    Ok(())
+}
+
+#[test]
+fn test_reconcile() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let charts = dir.path().join("charts");
+    fs::create_dir(&charts)?;
+    write_package(&charts, "alpha", "1.0.0");
+    write_package(&charts, "beta", "0.1.0");
+    let url = "https://example.com";
+    let index = ["index", "-d", "charts", "-u", url, "-o", "index.yaml"];
+    assert!(run(dir.path(), &index).status.success());
+    // beta's package is deleted and gamma's published without an entry
+    fs::remove_file(charts.join("beta-0.1.0.tgz"))?;
+    write_package(&charts, "gamma", "2.0.0");
+    let reconcile = [
+        "--output-format",
+        "json",
+        "reconcile",
+        "-f",
+        "index.yaml",
+        "-d",
+        "charts",
+    ];
+
+    let before = fs::read_to_string(dir.path().join("index.yaml"))?;
+    let output = run(dir.path(), &reconcile);
+    assert_eq!(output.status.code(), Some(1));
+    let result: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    assert_eq!(result["orphaned"], serde_json::json!(["beta@0.1.0"]));
+    assert_eq!(result["missing"], serde_json::json!(["gamma@2.0.0"]));
+    assert_eq!(result["changed"], false);
+    assert_eq!(fs::read_to_string(dir.path().join("index.yaml"))?, before);
+
+    // gamma is added, while beta's entry is only reported
+    let output = run(
+        dir.path(),
+        &[&reconcile[..], &["--add-missing", "-u", url]].concat(),
+    );
+    assert_eq!(output.status.code(), Some(1));
+    let result: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    assert_eq!(result["changed"], true);
+    let after = fs::read_to_string(dir.path().join("index.yaml"))?;
+    assert!(after.contains("https://example.com/gamma-2.0.0.tgz"));
+    assert!(after.contains("beta:"));
+
+    let remove = ["remove", "-f", "index.yaml", "-n", "beta", "-v", "0.1.0"];
+    assert!(run(dir.path(), &remove).status.success());
+    let output = run(dir.path(), &reconcile);
+    assert!(output.status.success(), "{:?}", output);
+    let result: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    assert_eq!(result["orphaned"], serde_json::json!([]));
+    assert_eq!(result["missing"], serde_json::json!([]));
+
+    Ok(())
+}
+
+#[test]
+fn test_stdout_and_output_leave_the_index_alone() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    setup(dir.path(), "0.1.0");
+    // A fixed `created` makes separate runs write the same bytes
+    fs::write(
+        dir.path().join("parameters.yaml"),
+        parameters("0.1.0") + "created: 2026-01-01T00:00:00Z\n",
+    )?;
+
+    let output = run(dir.path(), &[&UPDATE[..], &["--stdout"]].concat());
+    assert!(output.status.success());
+    assert_eq!(fs::read_to_string(dir.path().join("index.yaml"))?, INDEX);
+    let printed = String::from_utf8(output.stdout)?;
+
+    let output = run(
+        dir.path(),
+        &[&UPDATE[..], &["--output", "updated.yaml"]].concat(),
+    );
+    assert!(output.status.success());
+    assert_eq!(fs::read_to_string(dir.path().join("index.yaml"))?, INDEX);
+    let written = fs::read_to_string(dir.path().join("updated.yaml"))?;
+    assert!(written.contains("version: 0.1.0"));
+    // Nothing but the index on stdout, so it can be piped into a file
+    assert_eq!(printed, written);
+
+    Ok(())
+}
+
+#[test]
+fn test_update_json_output() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    setup(dir.path(), "0.1.0");
+
+    let output = run(
+        dir.path(),
+        &[&["--output-format", "json"], &UPDATE[..]].concat(),
+    );
+    assert!(output.status.success());
+    assert_eq!(
+        serde_json::from_slice::<serde_json::Value>(&output.stdout)?,
+        serde_json::json!({
+            "action": "add",
+            "chart": "test-chart",
+            "version": "0.1.0",
+            "changed": true,
+            "total_versions": 1,
+            "pruned": [],
+            "warnings": [],
+        })
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_repo_name() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    setup(dir.path(), "0.1.0");
+    let update = |name: &'static str| {
+        [
+            &["--output-format", "json"],
+            &UPDATE[..],
+            &["--idempotent", "--repo-name", name],
+        ]
+        .concat()
+    };
+    let repo_name = || -> Result<serde_yaml::Value, Box<dyn std::error::Error>> {
+        let index: serde_yaml::Value =
+            serde_yaml::from_str(&fs::read_to_string(dir.path().join("index.yaml"))?)?;
+        Ok(index["repoName"].clone())
+    };
+
+    assert!(run(dir.path(), &update("charts")).status.success());
+    assert_eq!(repo_name()?, "charts");
+
+    // Only the name differs, and that alone is a change
+    let output = run(dir.path(), &update("platform"));
+    assert!(output.status.success());
+    let result: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    assert_eq!(result["changed"], true);
+    assert_eq!(repo_name()?, "platform");
+
+    let output = run(dir.path(), &update("platform"));
+    let result: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    assert_eq!(result["changed"], false);
+
+    Ok(())
+}
+
+#[test]
+fn test_assume_sorted_with_unsorted_versions() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    setup(dir.path(), "0.2.0");
+    fs::write(
+        dir.path().join("index.yaml"),
+        "apiVersion: v1\nentries:\n  test-chart:\n  - version: 0.1.0\n  - version: 0.3.0\n",
+    )?;
+
+    let output = run(
+        dir.path(),
+        &[&UPDATE[..], &["--sort", "--assume-sorted"]].concat(),
+    );
+    assert!(output.status.success(), "{:?}", output);
+    assert!(String::from_utf8(output.stderr)?.contains("aren't sorted newest first"));
+    let index: serde_yaml::Value =
+        serde_yaml::from_str(&fs::read_to_string(dir.path().join("index.yaml"))?)?;
+    let versions: Vec<_> = index["entries"]["test-chart"]
+        .as_sequence()
+        .unwrap()
+        .iter()
+        .map(|entry| entry["version"].as_str().unwrap())
+        .collect();
+    assert_eq!(versions, ["0.3.0", "0.2.0", "0.1.0"]);
+
+    Ok(())
+}